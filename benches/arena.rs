@@ -0,0 +1,142 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use confetti_rs::arena::Arena;
+use confetti_rs::{parse, ConfDirective, ConfOptions};
+
+/// Counts the allocations made and the bytes currently allocated.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        LIVE_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// The approximate size of every generated input.
+const INPUT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Virtual hosts with repeated directive names, as in a large server config.
+fn servers() -> String {
+    let mut input = String::with_capacity(INPUT_SIZE + 1024);
+    let mut index = 0;
+    while input.len() < INPUT_SIZE {
+        input.push_str(&format!(
+            "server srv{index} {{\n    listen 0.0.0.0:{port};\n    root /var/www/site{index};\n    \
+             location /api {{\n        proxy_pass http://backend{index}:8080;\n        \
+             timeout 30s;\n    }}\n}}\n",
+            port = 8000 + index % 1000
+        ));
+        index += 1;
+    }
+    input
+}
+
+/// Returns the allocations made by `build` and the bytes its result holds.
+fn measure<T>(build: impl FnOnce() -> T) -> (T, usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = LIVE_BYTES.load(Ordering::Relaxed);
+    let value = build();
+    (
+        value,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        LIVE_BYTES.load(Ordering::Relaxed).saturating_sub(bytes),
+    )
+}
+
+/// Prints the allocations and retained memory of both representations.
+fn report_memory(input: &str) {
+    let (unit, allocations, bytes) = measure(|| parse(input, ConfOptions::default()).unwrap());
+    println!("memory/parse: {allocations} allocations, {bytes} bytes retained");
+    drop(unit);
+
+    let (arena, allocations, bytes) =
+        measure(|| Arena::parse(input, ConfOptions::default()).unwrap());
+    println!("memory/arena: {allocations} allocations, {bytes} bytes retained");
+    drop(arena);
+}
+
+/// Counts the arguments of every directive in a tree.
+fn count_tree(directives: &[ConfDirective]) -> usize {
+    let mut stack: Vec<&ConfDirective> = directives.iter().collect();
+    let mut count = 0;
+    while let Some(directive) = stack.pop() {
+        count += directive.arguments.len();
+        stack.extend(&directive.children);
+    }
+    count
+}
+
+fn bench_arena(c: &mut Criterion) {
+    let input = servers();
+    report_memory(&input);
+
+    let mut group = c.benchmark_group("arena");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function(BenchmarkId::new("build", "parse"), |b| {
+        b.iter(|| parse(black_box(&input), ConfOptions::default()).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("build", "arena"), |b| {
+        b.iter(|| Arena::parse(black_box(&input), ConfOptions::default()).unwrap())
+    });
+
+    let unit = parse(&input, ConfOptions::default()).unwrap();
+    let arena = Arena::from_unit(&unit);
+    group.bench_function(BenchmarkId::new("walk", "parse"), |b| {
+        b.iter(|| count_tree(black_box(&unit.directives)))
+    });
+    group.bench_function(BenchmarkId::new("walk", "arena"), |b| {
+        b.iter(|| {
+            black_box(&arena)
+                .iter()
+                .map(|directive| directive.arguments().len())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function(BenchmarkId::new("find", "parse"), |b| {
+        b.iter(|| {
+            black_box(&unit.directives)
+                .iter()
+                .filter_map(|server| server.child("listen"))
+                .count()
+        })
+    });
+    group.bench_function(BenchmarkId::new("find", "arena"), |b| {
+        b.iter(|| {
+            black_box(&arena)
+                .directives()
+                .filter_map(|server| server.child("listen"))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_arena
+}
+criterion_main!(benches);
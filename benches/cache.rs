@@ -0,0 +1,48 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use confetti_rs::{parse, ConfOptions, ConfUnit};
+
+/// The approximate size of every generated input.
+const INPUT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Commented virtual hosts with quoted and unquoted arguments.
+fn servers() -> String {
+    let mut input = String::with_capacity(INPUT_SIZE + 1024);
+    let mut index = 0;
+    while input.len() < INPUT_SIZE {
+        input.push_str(&format!(
+            "# Site {index}\nserver srv{index} {{\n    listen 0.0.0.0:{port};\n    \
+             root \"/var/www/site {index}\";\n    location /api {{\n        \
+             proxy_pass http://backend{index}:8080;\n        timeout 30s;\n    }}\n}}\n",
+            port = 8000 + index % 1000
+        ));
+        index += 1;
+    }
+    input
+}
+
+/// Loading from the cache and parsing, leaving out the time to drop the
+/// unit, which is the same for both.
+fn bench_cache(c: &mut Criterion) {
+    let input = servers();
+    let bytes = parse(&input, ConfOptions::default())
+        .unwrap()
+        .to_bytes(&input);
+
+    let mut group = c.benchmark_group("cache");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function(BenchmarkId::new("load", "parse"), |b| {
+        b.iter_with_large_drop(|| parse(black_box(&input), ConfOptions::default()).unwrap())
+    });
+    group.bench_function(BenchmarkId::new("load", "from_bytes"), |b| {
+        b.iter_with_large_drop(|| ConfUnit::from_bytes(black_box(&bytes), &input).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_cache
+}
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+
+use confetti_rs::incremental::{reparse, TextEdit};
+use confetti_rs::lexer::{Lexer, TokenType};
+use confetti_rs::{parse, ConfOptions};
+
+/// The approximate size of every generated input.
+const INPUT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Repeats `block`, numbering each copy, until the input reaches `INPUT_SIZE`.
+fn generate(block: impl Fn(usize) -> String) -> String {
+    let mut input = String::with_capacity(INPUT_SIZE + 1024);
+    let mut index = 0;
+    while input.len() < INPUT_SIZE {
+        input.push_str(&block(index));
+        index += 1;
+    }
+    input
+}
+
+/// Typical configuration: nested blocks of short unquoted arguments.
+fn plain_ascii() -> String {
+    generate(|i| {
+        format!(
+            "server srv{i} {{\n    listen 0.0.0.0:{port};\n    root /var/www/site{i};\n    \
+             location /api {{\n        proxy_pass http://backend{i}:8080;\n        \
+             timeout 30s;\n    }}\n}}\n",
+            port = 8000 + i % 1000
+        )
+    })
+}
+
+/// Long comments between short directives.
+fn comment_heavy() -> String {
+    generate(|i| {
+        format!(
+            "# Section {i}: this comment explains the purpose of the directive below in \
+             some detail, as generated configuration files often do.\n\
+             # It continues on a second line with more explanatory text.\n\
+             key{i} value;\n"
+        )
+    })
+}
+
+/// Long quoted and triple-quoted strings.
+fn quoted_strings() -> String {
+    generate(|i| {
+        format!(
+            "message{i} \"The quick brown fox jumps over the lazy dog, \\\"escaped\\\" twice.\";\n\
+             template{i} \"\"\"\n  <html>\n    <body>Item {i} of the generated document</body>\n  \
+             </html>\n\"\"\";\n"
+        )
+    })
+}
+
+/// Non-ASCII names, arguments and comments.
+fn non_ascii() -> String {
+    generate(|i| {
+        format!(
+            "# Раздел {i}: комментарий на русском языке\n\
+             имя{i} \"значение с пробелами\" 値{i} ünïcödé;\n\
+             ブロック {{ 設定 真; }}\n"
+        )
+    })
+}
+
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        ("plain_ascii", plain_ascii()),
+        ("comment_heavy", comment_heavy()),
+        ("quoted_strings", quoted_strings()),
+        ("non_ascii", non_ascii()),
+    ]
+}
+
+/// Returns the number of tokens in the input.
+fn tokenize(input: &str) -> usize {
+    let mut lexer = Lexer::new(input, ConfOptions::default());
+    let mut count = 0;
+    loop {
+        let token = lexer.next_token().expect("benchmark inputs are valid");
+        if token.token_type == TokenType::Eof {
+            return count;
+        }
+        count += 1;
+    }
+}
+
+fn bench_lexer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lexer");
+    for (name, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| tokenize(black_box(input)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, input) in inputs() {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &input, |b, input| {
+            b.iter(|| parse(black_box(input), ConfOptions::default()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+/// A one-character edit in a block halfway through the input, reparsed
+/// incrementally and from scratch.
+fn bench_reparse(c: &mut Criterion) {
+    let input = plain_ascii();
+    let options = ConfOptions::default();
+    let unit = parse(&input, options.clone()).unwrap();
+    let position = input[input.len() / 2..].find("timeout").unwrap() + input.len() / 2;
+    let edit = TextEdit::insert(position, "x");
+    let edited = edit.apply(&input);
+
+    let mut group = c.benchmark_group("reparse");
+    group.bench_function("incremental", |b| {
+        b.iter_batched(
+            || unit.clone(),
+            |unit| reparse(unit, black_box(&input), &edit, &options).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("full", |b| {
+        b.iter(|| parse(black_box(&edited), options.clone()).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_lexer, bench_parse, bench_reparse
+}
+criterion_main!(benches);
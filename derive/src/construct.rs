@@ -0,0 +1,332 @@
+//! The `confetti!` macro, which reads directives from Rust tokens.
+//!
+//! Rust strips the whitespace between tokens, so the layout is recovered from
+//! their source positions: tokens that touch form one argument, and a line
+//! break ends a directive as it does in a file.
+
+use proc_macro::{Delimiter, Group, Span, TokenStream, TokenTree};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+
+/// A line and column in the macro input.
+type Position = (usize, usize);
+
+/// A token of the flattened input: text, or a block.
+enum Piece {
+    Text {
+        text: String,
+        kind: Kind,
+        span: Span,
+        start: Position,
+        end: Position,
+    },
+    Block(Group),
+}
+
+/// The kind of argument a token of text belongs to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Bare,
+    Quoted,
+    TripleQuoted,
+    Raw,
+}
+
+/// An argument being read.
+struct Argument {
+    text: String,
+    kind: Kind,
+}
+
+impl Argument {
+    fn to_tokens(&self) -> TokenStream2 {
+        let text = &self.text;
+        let is_quoted = self.kind != Kind::Bare;
+        let is_triple_quoted = self.kind == Kind::TripleQuoted;
+        let is_raw = self.kind == Kind::Raw;
+        quote! {
+            confetti_rs::__private::argument(#text, #is_quoted, #is_triple_quoted, #is_raw)
+        }
+    }
+}
+
+struct Error {
+    span: Span,
+    message: String,
+}
+
+impl Error {
+    fn new(span: Span, message: &str) -> Self {
+        Self {
+            span,
+            message: message.to_string(),
+        }
+    }
+
+    fn to_compile_error(&self) -> TokenStream2 {
+        syn::Error::new(self.span.into(), &self.message).to_compile_error()
+    }
+}
+
+/// Expands the macro input to an expression building a `ConfUnit`.
+pub fn expand(input: TokenStream) -> TokenStream2 {
+    match read_directives(input, None) {
+        Ok(directives) => {
+            let directives = array(&directives);
+            quote! { confetti_rs::builder::unit(#directives) }
+        }
+        Err(error) => error.to_compile_error(),
+    }
+}
+
+/// Reads the directives of the input or of a block, returning an expression
+/// building each. `close` is the closing brace of a block.
+fn read_directives(input: TokenStream, close: Option<Span>) -> Result<Vec<TokenStream2>, Error> {
+    let mut pieces = Vec::new();
+    flatten(input, &mut pieces)?;
+    let pieces = merge_triple_quotes(pieces);
+
+    let mut directives = Vec::new();
+    let mut current: Vec<Argument> = Vec::new();
+    // The end and kind of the previous token of text on the same directive
+    let mut previous: Option<(Position, Kind)> = None;
+    let mut pieces = pieces.into_iter().peekable();
+
+    while let Some(piece) = pieces.next() {
+        // A line break ends the directive
+        if previous.is_some_and(|(previous_end, _)| piece_line(&piece) > previous_end.0) {
+            directives.extend(finish(&mut current));
+        }
+
+        let (text, kind, span, start, end) = match piece {
+            Piece::Block(group) => {
+                let Some(name) = current.first() else {
+                    return Err(Error::new(group.span_open(), "Expected directive name"));
+                };
+                let name = name.to_tokens();
+                let arguments = current[1..].iter().map(Argument::to_tokens);
+                let children = array(&read_directives(group.stream(), Some(group.span_close()))?);
+                directives.push(quote! {
+                    confetti_rs::builder::Directive::from_name(#name)
+                        #(.argument(#arguments))*
+                        .children(#children)
+                });
+                current.clear();
+                previous = None;
+                continue;
+            }
+            Piece::Text {
+                text,
+                kind,
+                span,
+                start,
+                end,
+            } => (text, kind, span, start, end),
+        };
+
+        match text.as_str() {
+            ";" if kind == Kind::Bare => {
+                if current.is_empty() {
+                    return Err(Error::new(span, "Expected directive name"));
+                }
+                directives.extend(finish(&mut current));
+                previous = None;
+                continue;
+            }
+            "#" if kind == Kind::Bare => {
+                // A comment runs to the end of the line
+                while pieces
+                    .next_if(|piece| piece_line(piece) == start.0)
+                    .is_some()
+                {}
+                continue;
+            }
+            _ => {}
+        }
+
+        // Tokens that touch form one argument, but strings stand alone
+        let touches = previous.is_some_and(|(previous_end, previous_kind)| {
+            previous_end == start && previous_kind == Kind::Bare && kind == Kind::Bare
+        });
+        match current.last_mut() {
+            Some(argument) if touches => argument.text.push_str(&text),
+            _ => current.push(Argument { text, kind }),
+        }
+        previous = Some((end, kind));
+    }
+
+    if let (Some((last, _)), Some(close)) = (previous, close) {
+        if !current.is_empty() && close.start().line() == last.0 {
+            return Err(Error::new(close, "Expected ';', '{', or newline"));
+        }
+    }
+    directives.extend(finish(&mut current));
+    Ok(directives)
+}
+
+/// Returns an expression building the directive read so far, if any.
+fn finish(current: &mut Vec<Argument>) -> Option<TokenStream2> {
+    let mut arguments = current.drain(..);
+    let name = arguments.next()?.to_tokens();
+    let arguments: Vec<TokenStream2> = arguments.map(|argument| argument.to_tokens()).collect();
+    Some(quote! {
+        confetti_rs::builder::Directive::from_name(#name)
+            #(.argument(#arguments))*
+    })
+}
+
+/// Returns an array of directive builders, typed so that it may be empty.
+fn array(directives: &[TokenStream2]) -> TokenStream2 {
+    let len = directives.len();
+    quote! {{
+        let directives: [confetti_rs::builder::Directive; #len] = [#(#directives),*];
+        directives
+    }}
+}
+
+/// Appends the tokens of a stream, keeping blocks whole but opening up
+/// brackets, which are ordinary characters in a configuration.
+fn flatten(input: TokenStream, pieces: &mut Vec<Piece>) -> Result<(), Error> {
+    for tree in input {
+        match tree {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                pieces.push(Piece::Block(group));
+            }
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Bracket => ("[", "]"),
+                    // Parentheses are reserved for expression arguments
+                    Delimiter::Parenthesis => {
+                        return Err(Error::new(group.span_open(), "Expected argument"));
+                    }
+                    _ => ("", ""),
+                };
+                if !open.is_empty() {
+                    pieces.push(text_piece(open.to_string(), Kind::Bare, group.span_open()));
+                }
+                flatten(group.stream(), pieces)?;
+                if !close.is_empty() {
+                    pieces.push(text_piece(
+                        close.to_string(),
+                        Kind::Bare,
+                        group.span_close(),
+                    ));
+                }
+            }
+            TokenTree::Literal(literal) => {
+                let span = literal.span();
+                let text = span.source_text().unwrap_or_else(|| literal.to_string());
+                let kind = if text.starts_with('"') {
+                    Kind::Quoted
+                } else if text.starts_with("r\"") || text.starts_with("r#") {
+                    Kind::Raw
+                } else {
+                    Kind::Bare
+                };
+                if is_prefixed_or_suffixed(&text, kind) {
+                    return Err(Error::new(
+                        span,
+                        "Byte, C and suffixed string literals are not confetti arguments",
+                    ));
+                }
+                pieces.push(text_piece(text, kind, span));
+            }
+            TokenTree::Ident(ident) => {
+                pieces.push(text_piece(ident.to_string(), Kind::Bare, ident.span()))
+            }
+            TokenTree::Punct(punct) => pieces.push(text_piece(
+                punct.as_char().to_string(),
+                Kind::Bare,
+                punct.span(),
+            )),
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether a literal is a string with a prefix other than `r`, such as
+/// `b"z"`, or with a suffix, such as `"z"x`, neither of which confetti reads.
+fn is_prefixed_or_suffixed(text: &str, kind: Kind) -> bool {
+    match kind {
+        Kind::Quoted | Kind::Raw => !text.ends_with(['"', '#']),
+        _ => {
+            let prefix = text.trim_start_matches(char::is_alphabetic);
+            !prefix.is_empty() && prefix.len() < text.len() && prefix.starts_with(['"', '\'', '#'])
+        }
+    }
+}
+
+fn text_piece(text: String, kind: Kind, span: Span) -> Piece {
+    Piece::Text {
+        text,
+        kind,
+        span,
+        start: line_column(span.start()),
+        end: line_column(span.end()),
+    }
+}
+
+/// Joins the three touching strings Rust reads from `"""text"""` into one
+/// triple-quoted argument.
+fn merge_triple_quotes(pieces: Vec<Piece>) -> Vec<Piece> {
+    let mut merged: Vec<Piece> = Vec::with_capacity(pieces.len());
+    for piece in pieces {
+        if let Piece::Text {
+            text,
+            kind: Kind::Quoted,
+            start,
+            end,
+            ..
+        } = &piece
+        {
+            if let [.., Piece::Text {
+                text: first,
+                kind: Kind::Quoted,
+                end: first_end,
+                ..
+            }, Piece::Text {
+                text: middle,
+                kind: Kind::Quoted,
+                start: middle_start,
+                end: middle_end,
+                ..
+            }] = merged.as_slice()
+            {
+                if first == "\"\""
+                    && text == "\"\""
+                    && first_end == middle_start
+                    && middle_end == start
+                {
+                    let joined = format!("\"\"{}\"\"", middle);
+                    let end = *end;
+                    merged.pop();
+                    if let Some(Piece::Text {
+                        text,
+                        kind,
+                        end: last_end,
+                        ..
+                    }) = merged.last_mut()
+                    {
+                        *text = joined;
+                        *kind = Kind::TripleQuoted;
+                        *last_end = end;
+                    }
+                    continue;
+                }
+            }
+        }
+        merged.push(piece);
+    }
+    merged
+}
+
+fn line_column(span: Span) -> Position {
+    (span.line(), span.column())
+}
+
+fn piece_line(piece: &Piece) -> usize {
+    match piece {
+        Piece::Text { start, .. } => start.0,
+        Piece::Block(group) => group.span_open().line(),
+    }
+}
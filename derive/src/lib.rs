@@ -4,6 +4,8 @@ use quote::quote;
 use syn::spanned::Spanned;
 use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
 
+mod construct;
+
 /// Derives the FromConf and ToConf traits for struct types
 ///
 /// This attribute allows a struct to be serialized to and deserialized from
@@ -46,32 +48,16 @@ pub fn derive_conf_map(input: TokenStream) -> TokenStream {
                         let is_optional = is_option_type(field_type);
 
                         if is_optional {
+                            // A child without arguments leaves the field unset
                             quote! {
-                                #field_name: {
-                                    if let Some(child) = directive.children.iter().find(|d| d.name.value == #conf_name) {
-                                        if !child.arguments.is_empty() {
-                                            Some(confetti_rs::mapper::ValueConverter::from_conf_value(&child.arguments[0].value)?)
-                                        } else {
-                                            None
-                                        }
-                                    } else {
-                                        None
-                                    }
+                                #field_name: match directive.child(#conf_name) {
+                                    Some(child) if !child.arguments.is_empty() => Some(child.arg(0)?),
+                                    _ => None,
                                 }
                             }
                         } else {
                             quote! {
-                                #field_name: {
-                                    if let Some(child) = directive.children.iter().find(|d| d.name.value == #conf_name) {
-                                        if !child.arguments.is_empty() {
-                                            confetti_rs::mapper::ValueConverter::from_conf_value(&child.arguments[0].value)?
-                                        } else {
-                                            return Err(confetti_rs::mapper::MapperError::MissingField(#conf_name.to_string()));
-                                        }
-                                    } else {
-                                        return Err(confetti_rs::mapper::MapperError::MissingField(#conf_name.to_string()));
-                                    }
-                                }
+                                #field_name: directive.value(#conf_name)?
                             }
                         }
                     });
@@ -87,60 +73,12 @@ pub fn derive_conf_map(input: TokenStream) -> TokenStream {
                         if is_optional {
                             quote! {
                                 if let Some(value) = &self.#field_name {
-                                    let arg_value = confetti_rs::mapper::ValueConverter::to_conf_value(value)?;
-                                    // Use requires_quotes method
-                                    // Add ValueConverter trait directly to determine if quotes are needed
-                                    let is_quoted = confetti_rs::mapper::ValueConverter::requires_quotes(value);
-                                    let arg = confetti_rs::ConfArgument {
-                                        value: arg_value,
-                                        span: 0..0,
-                                        is_quoted: is_quoted,
-                                        is_triple_quoted: false,
-                                        is_expression: false,
-                                    };
-
-                                    let child = confetti_rs::ConfDirective {
-                                        name: confetti_rs::ConfArgument {
-                                            value: #conf_name.to_string(),
-                                            span: 0..0,
-                                            is_quoted: false,
-                                            is_triple_quoted: false,
-                                            is_expression: false,
-                                        },
-                                        arguments: vec![arg],
-                                        children: vec![],
-                                    };
-
-                                    children.push(child);
+                                    children.push(confetti_rs::__private::field_directive(#conf_name, value)?);
                                 }
                             }
                         } else {
                             quote! {
-                                let arg_value = confetti_rs::mapper::ValueConverter::to_conf_value(&self.#field_name)?;
-                                // Use requires_quotes method
-                                // Add ValueConverter trait directly to determine if quotes are needed
-                                let is_quoted = confetti_rs::mapper::ValueConverter::requires_quotes(&self.#field_name);
-                                let arg = confetti_rs::ConfArgument {
-                                    value: arg_value,
-                                    span: 0..0,
-                                    is_quoted: is_quoted,
-                                    is_triple_quoted: false,
-                                    is_expression: false,
-                                };
-
-                                let child = confetti_rs::ConfDirective {
-                                    name: confetti_rs::ConfArgument {
-                                        value: #conf_name.to_string(),
-                                        span: 0..0,
-                                        is_quoted: false,
-                                        is_triple_quoted: false,
-                                        is_expression: false,
-                                    },
-                                    arguments: vec![arg],
-                                    children: vec![],
-                                };
-
-                                children.push(child);
+                                children.push(confetti_rs::__private::field_directive(#conf_name, &self.#field_name)?);
                             }
                         }
                     });
@@ -168,17 +106,9 @@ pub fn derive_conf_map(input: TokenStream) -> TokenStream {
 
                                 #(#to_conf_fields)*
 
-                                Ok(confetti_rs::ConfDirective {
-                                    name: confetti_rs::ConfArgument {
-                                        value: #name_str.to_string(),
-                                        span: 0..0,
-                                        is_quoted: false,
-                                        is_triple_quoted: false,
-                                        is_expression: false,
-                                    },
-                                    arguments: vec![],
-                                    children,
-                                })
+                                Ok(confetti_rs::builder::Directive::new(#name_str)
+                                    .children(children)
+                                    .build())
                             }
                         }
                     };
@@ -213,6 +143,55 @@ pub fn derive_conf_map(input: TokenStream) -> TokenStream {
     expanded.into()
 }
 
+/// Builds a `ConfUnit` from configuration written inline.
+///
+/// The directives are checked when the crate is compiled, and syntax errors
+/// are reported as compile errors pointing at the offending token. The input
+/// must also consist of valid Rust tokens, so `//` starts a Rust comment and
+/// text holding it, such as a URL, is quoted. Strings are read as written and
+/// use confetti's escapes rather than Rust's, and byte, C and other prefixed
+/// or suffixed literals are rejected.
+///
+/// Spans are located with `proc_macro::Span::line`, so the macro needs Rust
+/// 1.88 or later.
+///
+/// # Example
+///
+/// ```rust
+/// use confetti_derive::confetti;
+///
+/// let unit = confetti! {
+///     server {
+///         listen 127.0.0.1:8080;
+///         root "/var/www/my site"
+///     }
+/// };
+///
+/// let server = &unit.directives[0];
+/// assert_eq!(server.children[0].arguments[0].value, "127.0.0.1:8080");
+/// assert_eq!(server.children[1].arguments[0].text(), "/var/www/my site");
+/// ```
+///
+/// A misplaced brace is rejected:
+///
+/// ```compile_fail
+/// let unit = confetti_derive::confetti! {
+///     { listen 80; }
+/// };
+/// ```
+///
+/// So is a byte string:
+///
+/// ```compile_fail
+/// let unit = confetti_derive::confetti! {
+///     key b"z";
+/// };
+/// ```
+#[proc_macro]
+pub fn confetti(input: TokenStream) -> TokenStream {
+    construct::expand(input).into()
+}
+
 // Helper functions
 
 fn get_conf_name_from_attrs(attrs: &[Attribute], default_name: &str) -> String {
@@ -0,0 +1,44 @@
+use confetti_derive::ConfMap;
+use confetti_rs::{FromConf, MapperError, ToConf};
+
+#[derive(ConfMap, Debug, PartialEq)]
+struct ServerConfig {
+    host: String,
+    port: u16,
+    #[conf_map(name = "max-connections")]
+    max_connections: Option<u32>,
+}
+
+#[test]
+fn test_round_trip() {
+    let config = ServerConfig::from_str(
+        "ServerConfig {\n  host \"example \\\"one\\\"\";\n  port 8080;\n}\n",
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        ServerConfig {
+            host: "example \"one\"".to_string(),
+            port: 8080,
+            max_connections: None,
+        }
+    );
+
+    let text = config.to_string().unwrap();
+    assert_eq!(ServerConfig::from_str(&text).unwrap(), config);
+}
+
+#[test]
+fn test_errors_carry_spans() {
+    let input = "ServerConfig {\n  host localhost;\n  port 8080;\n  max-connections many;\n}\n";
+    let error = ServerConfig::from_str(input).unwrap_err();
+    assert_eq!(&input[error.span().unwrap().range()], "many");
+
+    let input = "ServerConfig {\n  host localhost;\n}\n";
+    let error = ServerConfig::from_str(input).unwrap_err();
+    assert!(matches!(
+        error,
+        MapperError::Located { ref error, .. } if matches!(**error, MapperError::MissingField(ref name) if name == "port")
+    ));
+    assert_eq!(&input[error.span().unwrap().range()], "ServerConfig");
+}
@@ -0,0 +1,72 @@
+use confetti_derive::confetti;
+use confetti_rs::{parse, ConfOptions, ConfUnit};
+
+fn parsed(source: &str, options: ConfOptions) -> ConfUnit {
+    parse(source, options).unwrap()
+}
+
+#[test]
+fn test_matches_parsed_source() {
+    let unit = confetti! {
+        user www-data;
+        worker_processes 4
+
+        http {
+            # Comments are dropped
+            server "main site" {
+                listen 127.0.0.1:8080 default_server;
+                location /api/[v1|v2] { proxy_pass "http://backend:9000"; }
+                root "/var/www/my site"
+            }
+        }
+    };
+
+    let expected = parsed(
+        "user www-data;\nworker_processes 4\nhttp {\n  server \"main site\" {\n    listen 127.0.0.1:8080 default_server;\n    location /api/[v1|v2] { proxy_pass \"http://backend:9000\"; }\n    root \"/var/www/my site\"\n  }\n}\n",
+        ConfOptions::default(),
+    );
+    assert_eq!(unit.directives, expected.directives);
+    assert!(unit.comments.is_empty());
+}
+
+#[test]
+fn test_argument_kinds() {
+    let unit = confetti! {
+        text "a \"quoted\" word" """two
+    lines""" r"C:\temp" 1.5e3 -x
+    };
+
+    let arguments = &unit.directives[0].arguments;
+    assert_eq!(arguments[0].text(), "a \"quoted\" word");
+    assert!(arguments[1].is_triple_quoted);
+    assert_eq!(arguments[1].text(), "two\n    lines");
+    assert!(arguments[2].is_raw);
+    assert_eq!(arguments[2].text(), "C:\\temp");
+    assert_eq!(arguments[3].value, "1.5e3");
+    assert_eq!(arguments[4].value, "-x");
+
+    let values: Vec<&str> = arguments.iter().map(|a| a.value.as_str()).collect();
+    let source = format!("text {}", values.join(" "));
+    let expected = parsed(&source, ConfOptions::all_extensions());
+    assert_eq!(unit.directives, expected.directives);
+}
+
+#[test]
+fn test_empty() {
+    let unit = confetti! {};
+    assert!(unit.directives.is_empty());
+}
+
+#[test]
+fn test_strings_use_confetti_escapes() {
+    let unit = confetti! {
+        path "C:\temp\n";
+    };
+
+    let expected = parsed("path \"C:\\temp\\n\";", ConfOptions::all_extensions());
+    assert_eq!(unit.directives, expected.directives);
+    assert_eq!(
+        unit.directives[0].arguments[0].text(),
+        expected.directives[0].arguments[0].text()
+    );
+}
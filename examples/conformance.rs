@@ -0,0 +1,17 @@
+use confetti_rs::conformance::run_suite;
+use confetti_rs::ConfOptions;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Usage: cargo run --example conformance -- <suite directory>
+    let dir = std::env::args()
+        .nth(1)
+        .ok_or("usage: conformance <suite directory>")?;
+
+    let report = run_suite(&dir, &ConfOptions::spec_strict())?;
+    println!("{}", report);
+
+    if !report.is_success() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
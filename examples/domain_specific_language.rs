@@ -1,4 +1,5 @@
-use confetti_rs::{parse, ConfOptions};
+use confetti_rs::visit::{walk, Context, Flow, Visitor};
+use confetti_rs::{parse, ConfDirective, ConfOptions};
 use std::collections::HashMap;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -170,11 +171,13 @@ events {
 
     println!("\nParsed Control Flow Language:");
 
-    // Function to display control flow structure
-    fn print_control_flow(directives: &[confetti_rs::ConfDirective], indent: usize) {
-        let indent_str = " ".repeat(indent * 2);
+    // Visitor displaying the control flow structure, indented by block depth
+    struct PrintControlFlow;
+
+    impl Visitor for PrintControlFlow {
+        fn visit_directive(&mut self, directive: &ConfDirective, context: &Context<'_>) -> Flow {
+            let indent_str = " ".repeat(context.depth() * 2);
 
-        for directive in directives {
             match directive.name.value.as_str() {
                 "set" => {
                     if directive.arguments.len() >= 3 {
@@ -196,8 +199,6 @@ events {
                             directive.arguments[1].value,
                             directive.arguments[2].value
                         );
-
-                        print_control_flow(&directive.children, indent + 1);
                     }
                 }
                 "if" => {
@@ -206,12 +207,10 @@ events {
                             "{}If condition: {}",
                             indent_str, directive.arguments[0].value
                         );
-                        print_control_flow(&directive.children, indent + 1);
                     }
                 }
                 "else" => {
                     println!("{}Else branch:", indent_str);
-                    print_control_flow(&directive.children, indent + 1);
                 }
                 "print" => {
                     if !directive.arguments.is_empty() {
@@ -250,11 +249,19 @@ events {
                     println!("{}Unknown command: {}", indent_str, directive.name.value);
                 }
             }
+
+            // Only loops and branches have bodies to display
+            match directive.name.value.as_str() {
+                "for" if directive.arguments.len() >= 3 => Flow::Continue,
+                "if" if !directive.arguments.is_empty() => Flow::Continue,
+                "else" => Flow::Continue,
+                _ => Flow::SkipChildren,
+            }
         }
     }
 
     // Display control flow structure
-    print_control_flow(&control_conf.directives, 0);
+    walk(&mut PrintControlFlow, &control_conf);
 
     println!("\nParsed State Machine:");
 
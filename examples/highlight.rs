@@ -0,0 +1,25 @@
+use confetti_rs::highlight::{to_ansi, to_html, CSS};
+use confetti_rs::ConfOptions;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Usage: cargo run --example highlight -- [--html] <file>
+    let mut html = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--html" => html = true,
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.ok_or("usage: highlight [--html] <file>")?;
+    let source = std::fs::read_to_string(path)?;
+
+    let options = ConfOptions::default();
+    if html {
+        println!("<style>\n{}</style>", CSS);
+        println!("{}", to_html(&source, options));
+    } else {
+        print!("{}", to_ansi(&source, options));
+    }
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use confetti_rs::{parse, ConfOptions};
+use confetti_rs::visit::{walk, Context, Flow, Visitor};
+use confetti_rs::{parse, ConfDirective, ConfOptions};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Example of configuration using language extensions
@@ -195,11 +196,12 @@ server {
         combined_conf.comments.len()
     );
 
-    // Display configuration structure
-    fn print_combined_config(directives: &[confetti_rs::ConfDirective], indent: usize) {
-        let indent_str = " ".repeat(indent * 2);
+    // Display configuration structure, indenting directives by their depth
+    struct PrintCombinedConfig;
 
-        for directive in directives {
+    impl Visitor for PrintCombinedConfig {
+        fn visit_directive(&mut self, directive: &ConfDirective, context: &Context<'_>) -> Flow {
+            let indent_str = " ".repeat(context.depth() * 2);
             print!("{}Directive: {}", indent_str, directive.name.value);
 
             // Display arguments
@@ -213,16 +215,11 @@ server {
                 }
             }
             println!();
-
-            // Recursively process child directives
-            if !directive.children.is_empty() {
-                print_combined_config(&directive.children, indent + 1);
-            }
+            Flow::Continue
         }
     }
 
-    // Display configuration structure
-    print_combined_config(&combined_conf.directives, 0);
+    walk(&mut PrintCombinedConfig, &combined_conf);
 
     Ok(())
 }
@@ -1,7 +1,7 @@
 #[cfg(feature = "derive")]
-use confetti_rs::{
-    from_str, to_string, ConfArgument, ConfDirective, ConfMap, FromConf, MapperError, ToConf,
-};
+use confetti_rs::builder::Directive;
+#[cfg(feature = "derive")]
+use confetti_rs::{from_str, to_string, ConfDirective, ConfMap, FromConf, MapperError, ToConf};
 #[cfg(feature = "derive")]
 use std::error::Error;
 
@@ -50,63 +50,31 @@ fn main() -> Result<(), Box<dyn Error>> {
             }
 
             // Extract name and version from direct child directives
-            let name = directive
-                .children
-                .iter()
-                .find(|d| d.name.value == "name")
-                .and_then(|d| d.arguments.get(0))
-                .map(|arg| arg.value.clone())
-                .ok_or_else(|| MapperError::MissingField("name".into()))?;
+            let name = directive.value("name")?;
 
-            let version = directive
-                .children
-                .iter()
-                .find(|d| d.name.value == "version")
-                .and_then(|d| d.arguments.get(0))
-                .map(|arg| arg.value.clone())
-                .ok_or_else(|| MapperError::MissingField("version".into()))?;
+            let version = directive.value("version")?;
 
             // Find and parse database configuration - creating a custom directive for it
             let database_child = directive
-                .children
-                .iter()
-                .find(|d| d.name.value == "database")
+                .child("database")
                 .ok_or_else(|| MapperError::MissingField("database".into()))?;
 
             // Create a proper DatabaseConfig directive
-            let database_directive = ConfDirective {
-                name: ConfArgument {
-                    value: "DatabaseConfig".to_string(),
-                    span: database_child.name.span.clone(),
-                    is_quoted: false,
-                    is_triple_quoted: false,
-                    is_expression: false,
-                },
-                arguments: Vec::new(),
-                children: database_child.children.clone(),
-            };
+            let database_directive = Directive::new("DatabaseConfig")
+                .children(database_child.children.clone())
+                .build();
 
             let database = DatabaseConfig::from_directive(&database_directive)?;
 
             // Find and parse server configuration
             let server_child = directive
-                .children
-                .iter()
-                .find(|d| d.name.value == "server")
+                .child("server")
                 .ok_or_else(|| MapperError::MissingField("server".into()))?;
 
             // Create a proper ServerConfig directive
-            let server_directive = ConfDirective {
-                name: ConfArgument {
-                    value: "ServerConfig".to_string(),
-                    span: server_child.name.span.clone(),
-                    is_quoted: false,
-                    is_triple_quoted: false,
-                    is_expression: false,
-                },
-                arguments: Vec::new(),
-                children: server_child.children.clone(),
-            };
+            let server_directive = Directive::new("ServerConfig")
+                .children(server_child.children.clone())
+                .build();
 
             let server = ServerConfig::from_directive(&server_directive)?;
 
@@ -122,71 +90,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Implement ToConf for ServiceConfig manually
     impl ToConf for ServiceConfig {
         fn to_directive(&self) -> Result<ConfDirective, MapperError> {
-            let mut children = Vec::new();
-
-            // Add name and version directives
-            let name_arg = ConfArgument {
-                value: self.name.clone(),
-                span: 0..0,
-                is_quoted: true,
-                is_triple_quoted: false,
-                is_expression: false,
-            };
-
-            let name_directive = ConfDirective {
-                name: ConfArgument {
-                    value: "name".to_string(),
-                    span: 0..0,
-                    is_quoted: false,
-                    is_triple_quoted: false,
-                    is_expression: false,
-                },
-                arguments: vec![name_arg],
-                children: vec![],
-            };
-
-            children.push(name_directive);
-
-            let version_arg = ConfArgument {
-                value: self.version.clone(),
-                span: 0..0,
-                is_quoted: true,
-                is_triple_quoted: false,
-                is_expression: false,
-            };
-
-            let version_directive = ConfDirective {
-                name: ConfArgument {
-                    value: "version".to_string(),
-                    span: 0..0,
-                    is_quoted: false,
-                    is_triple_quoted: false,
-                    is_expression: false,
-                },
-                arguments: vec![version_arg],
-                children: vec![],
-            };
-
-            children.push(version_directive);
-
-            // Add database and server directives
-            let database_directive = self.database.to_directive()?;
-            children.push(database_directive);
-
-            let server_directive = self.server.to_directive()?;
-            children.push(server_directive);
-
-            Ok(ConfDirective {
-                name: ConfArgument {
-                    value: "ServiceConfig".to_string(),
-                    span: 0..0,
-                    is_quoted: false,
-                    is_triple_quoted: false,
-                    is_expression: false,
-                },
-                arguments: vec![],
-                children,
-            })
+            Ok(Directive::new("ServiceConfig")
+                .child(Directive::new("name").quoted(&self.name))
+                .child(Directive::new("version").quoted(&self.version))
+                .child(self.database.to_directive()?)
+                .child(self.server.to_directive()?)
+                .build())
         }
     }
 
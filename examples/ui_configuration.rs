@@ -1,4 +1,5 @@
-use confetti_rs::{parse, ConfOptions};
+use confetti_rs::visit::{walk_directives, Context, Flow, Visitor};
+use confetti_rs::{parse, ConfDirective, ConfOptions};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Example of UI configuration
@@ -32,41 +33,44 @@ function() {
 
     println!("Parsed UI configuration:");
 
-    // Function for recursive display of UI components
-    fn print_ui_component(directive: &confetti_rs::ConfDirective, indent: usize) {
-        let indent_str = " ".repeat(indent * 2);
-        println!("{}Component: {}", indent_str, directive.name.value);
+    // Visitor displaying UI components and their properties by depth
+    struct PrintUiComponent;
 
-        // Display component properties
-        for child in &directive.children {
-            if child.children.is_empty() {
-                let value = if !child.arguments.is_empty() {
-                    let arg = &child.arguments[0].value;
-                    if child.arguments[0].is_triple_quoted {
-                        // For multi-line values
-                        format!("<<< Multi-line code block >>>")
-                    } else if child.arguments[0].is_quoted {
-                        // Remove quotes for display
-                        arg[1..arg.len() - 1].to_string()
-                    } else {
-                        arg.clone()
-                    }
-                } else {
-                    "No value".to_string()
-                };
+    impl Visitor for PrintUiComponent {
+        fn visit_directive(&mut self, directive: &ConfDirective, context: &Context<'_>) -> Flow {
+            let indent_str = " ".repeat(context.depth() * 2);
+            if context.depth() == 0 || !directive.children.is_empty() {
+                println!("{}Component: {}", indent_str, directive.name.value);
+                return Flow::Continue;
+            }
 
-                println!("{}  Property: {} = {}", indent_str, child.name.value, value);
+            // Display component properties
+            let value = if !directive.arguments.is_empty() {
+                let arg = &directive.arguments[0].value;
+                if directive.arguments[0].is_triple_quoted {
+                    // For multi-line values
+                    format!("<<< Multi-line code block >>>")
+                } else if directive.arguments[0].is_quoted {
+                    // Remove quotes for display
+                    arg[1..arg.len() - 1].to_string()
+                } else {
+                    arg.clone()
+                }
             } else {
-                // Recursively process nested components
-                print_ui_component(child, indent + 1);
-            }
+                "No value".to_string()
+            };
+
+            println!(
+                "{}Property: {} = {}",
+                indent_str, directive.name.value, value
+            );
+            Flow::Continue
         }
     }
 
     // Process the root component (Application)
-    if let Some(app) = conf_unit.directives.first() {
-        print_ui_component(app, 0);
-    }
+    let app = conf_unit.directives.get(..1).unwrap_or_default();
+    walk_directives(&mut PrintUiComponent, app);
 
     // Example of generating pseudocode for UI
     println!("\nGenerated UI pseudocode:");
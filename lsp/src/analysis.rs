@@ -0,0 +1,582 @@
+//! The language features, computed from a document's text and parse result.
+//!
+//! Features that work on tokens (folding, semantic tokens, formatting, and
+//! the context for hover and completion) only need the lexer, so they keep
+//! working while the document has a syntax error further on.
+
+use std::ops::Range;
+
+use confetti_rs::edit::directive_extent;
+use confetti_rs::lexer::{tokens, CommentStyle, Lexer, Token, TokenClass, TokenType};
+use confetti_rs::{ConfDirective, ConfError, ConfOptions, ConfUnit};
+use serde_json::{json, Value};
+
+use crate::schema::Schema;
+use crate::text::LineIndex;
+
+/// The token types of the semantic tokens legend.
+pub const TOKEN_TYPES: &[&str] = &[
+    "property",
+    "parameter",
+    "string",
+    "macro",
+    "comment",
+    "operator",
+];
+
+// Indexes into `TOKEN_TYPES`
+const NAME: u32 = 0;
+const ARGUMENT: u32 = 1;
+const STRING: u32 = 2;
+const EXPRESSION: u32 = 3;
+const COMMENT: u32 = 4;
+const OPERATOR: u32 = 5;
+
+// Protocol enumerations
+const SEVERITY_ERROR: u32 = 1;
+const SYMBOL_PROPERTY: u32 = 7;
+const SYMBOL_OBJECT: u32 = 19;
+const COMPLETION_KEYWORD: u32 = 14;
+
+/// Lexes `text` up to the end or the first error.
+fn lex(text: &str, options: &ConfOptions) -> (Vec<Token>, Option<ConfError>) {
+    let mut lexer = Lexer::new(text, options.clone());
+    let mut tokens = Vec::new();
+    loop {
+        match lexer.next_token() {
+            Ok(token) if token.token_type == TokenType::Eof => return (tokens, None),
+            Ok(token) => tokens.push(token),
+            Err(error) => return (tokens, Some(error)),
+        }
+    }
+}
+
+/// Returns the diagnostics for a document's parse error, if any.
+pub fn diagnostics(text: &str, error: Option<&ConfError>) -> Value {
+    let index = LineIndex::new(text);
+    let diagnostics: Vec<Value> = error
+        .into_iter()
+        .map(|error| {
+            let mut start = error.position().unwrap_or(0).min(text.len());
+            while !text.is_char_boundary(start) {
+                start -= 1;
+            }
+            // Highlight the character at the position, unless it ends the line
+            let end = text[start..]
+                .chars()
+                .next()
+                .filter(|&c| c != '\n' && c != '\r')
+                .map_or(start, |c| start + c.len_utf8());
+            json!({
+                "range": index.range_json(start..end),
+                "severity": SEVERITY_ERROR,
+                "source": "confetti",
+                "message": error_message(error),
+            })
+        })
+        .collect();
+    Value::Array(diagnostics)
+}
+
+/// Returns the message of an error without its position, which the
+/// diagnostic range already shows.
+fn error_message(error: &ConfError) -> String {
+    match error {
+        ConfError::LexerError { message, .. }
+        | ConfError::ParserError { message, .. }
+        | ConfError::EncodingError { message, .. } => message.clone(),
+        ConfError::LimitExceeded { limit, max, .. } => {
+            format!("The {} is limited to {}", limit, max)
+        }
+        ConfError::IoError(error) => error.to_string(),
+    }
+}
+
+/// Returns the directive tree as nested document symbols.
+pub fn document_symbols(text: &str, unit: &ConfUnit, options: &ConfOptions) -> Value {
+    let index = LineIndex::new(text);
+    Value::Array(
+        unit.directives
+            .iter()
+            .map(|directive| symbol(text, &index, directive, options))
+            .collect(),
+    )
+}
+
+/// Converts a directive and its children, whose depth the parser has bounded.
+fn symbol(
+    text: &str,
+    index: &LineIndex,
+    directive: &ConfDirective,
+    options: &ConfOptions,
+) -> Value {
+    let (span, has_block) = match directive_extent(text, directive, options) {
+        Ok(extent) => (extent.span, extent.block.is_some()),
+        Err(_) => (directive.name.span.range(), !directive.children.is_empty()),
+    };
+    // Clients reject symbols with empty names, such as `""`
+    let mut name = directive.name.text();
+    if name.is_empty() {
+        name = directive.name.value.clone();
+    }
+    let detail: Vec<&str> = directive
+        .arguments
+        .iter()
+        .map(|argument| argument.value.as_str())
+        .collect();
+
+    json!({
+        "name": name,
+        "detail": detail.join(" "),
+        "kind": if has_block { SYMBOL_OBJECT } else { SYMBOL_PROPERTY },
+        "range": index.range_json(span),
+        "selectionRange": index.range_json(directive.name.span.range()),
+        "children": directive
+            .children
+            .iter()
+            .map(|child| symbol(text, index, child, options))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Returns folding ranges for blocks and multi-line block comments.
+///
+/// A block folds from the line of its `{` to the line before its `}`, so
+/// the closing brace stays visible.
+pub fn folding_ranges(text: &str, options: &ConfOptions) -> Value {
+    let index = LineIndex::new(text);
+    let mut ranges = Vec::new();
+    let mut open = Vec::new();
+    for token in lex(text, options).0 {
+        match token.token_type {
+            TokenType::LeftCurlyBrace => open.push(index.position(token.span.start).0),
+            TokenType::RightCurlyBrace => {
+                let close = index.position(token.span.start).0;
+                if let Some(start) = open.pop().filter(|&start| close > start + 1) {
+                    ranges.push((start, close - 1, None));
+                }
+            }
+            TokenType::Comment if matches!(token.comment_style, Some(CommentStyle::Block(_))) => {
+                let start = index.position(token.span.start).0;
+                let end = index.position(token.span.end).0;
+                if end > start {
+                    ranges.push((start, end, Some("comment")));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    ranges.sort_by_key(|&(start, end, _)| (start, end));
+    Value::Array(
+        ranges
+            .into_iter()
+            .map(|(start, end, kind)| match kind {
+                Some(kind) => json!({ "startLine": start, "endLine": end, "kind": kind }),
+                None => json!({ "startLine": start, "endLine": end }),
+            })
+            .collect(),
+    )
+}
+
+/// Returns the semantic tokens of a document, encoded relative to each other
+/// as the protocol requires. Tokens spanning several lines are split at line
+/// breaks.
+pub fn semantic_tokens(text: &str, options: &ConfOptions) -> Value {
+    let index = LineIndex::new(text);
+    let mut data: Vec<u32> = Vec::new();
+    let (mut previous_line, mut previous_column) = (0, 0);
+
+    for token in tokens(text, options.clone()).map_while(Result::ok) {
+        let class = match token.class {
+            TokenClass::DirectiveName => NAME,
+            TokenClass::Argument => ARGUMENT,
+            TokenClass::QuotedArgument
+            | TokenClass::TripleQuotedArgument
+            | TokenClass::RawArgument
+            | TokenClass::HeredocArgument => STRING,
+            TokenClass::ExpressionArgument => EXPRESSION,
+            TokenClass::LineComment | TokenClass::BlockComment => COMMENT,
+            TokenClass::Semicolon | TokenClass::OpenBrace | TokenClass::CloseBrace => OPERATOR,
+            TokenClass::Continuation | TokenClass::Newline | TokenClass::Whitespace => continue,
+        };
+        let token = token.token;
+
+        let mut start = token.span.start;
+        while start < token.span.end {
+            let line_end = text[start..token.span.end]
+                .find(['\r', '\n'])
+                .map_or(token.span.end, |i| start + i);
+            let piece = &text[start..line_end];
+            if !piece.is_empty() {
+                let (line, column) = index.position(start);
+                let length: usize = piece.chars().map(char::len_utf16).sum();
+                let delta_column = if line == previous_line {
+                    column - previous_column
+                } else {
+                    column
+                };
+                data.extend([line - previous_line, delta_column, length as u32, class, 0]);
+                previous_line = line;
+                previous_column = column;
+            }
+            start = line_end
+                + if text[line_end..].starts_with("\r\n") {
+                    2
+                } else {
+                    1
+                };
+        }
+    }
+    json!({ "data": data })
+}
+
+/// Reindents `text` by block depth with `indent` per level and removes
+/// trailing whitespace and blank lines at the end.
+///
+/// Lines inside multi-line strings, heredocs and block comments are kept as
+/// they are. Returns `None` if the lexer rejects the text, or if the result
+/// would not parse to the same tree as the text.
+pub fn format(text: &str, options: &ConfOptions, indent: &str) -> Option<String> {
+    let (tokens, error) = lex(text, options);
+    if error.is_some() {
+        return None;
+    }
+
+    let index = LineIndex::new(text);
+    let lines = index.line_count();
+    let mut depths = vec![0; lines];
+    let mut closes = vec![false; lines];
+    let mut continued = vec![false; lines];
+    // Lines whose start or whose line break is inside a token
+    let mut inside_start = vec![false; lines];
+    let mut inside_end = vec![false; lines];
+
+    let mut depth: usize = 0;
+    let mut next_line = 0;
+    for token in &tokens {
+        let first = index.position(token.span.start).0 as usize;
+        let last = index.position(token.span.end).0 as usize;
+        while next_line <= first {
+            depths[next_line] = depth;
+            closes[next_line] =
+                next_line == first && token.token_type == TokenType::RightCurlyBrace;
+            next_line += 1;
+        }
+        for line in first + 1..=last {
+            if index.line_start(line) < token.span.end {
+                inside_start[line] = true;
+                inside_end[line - 1] = true;
+            }
+        }
+        match token.token_type {
+            TokenType::LeftCurlyBrace => depth += 1,
+            TokenType::RightCurlyBrace => depth = depth.saturating_sub(1),
+            TokenType::Continuation if first + 1 < lines => continued[first + 1] = true,
+            _ => {}
+        }
+    }
+    for line_depth in &mut depths[next_line.min(lines)..] {
+        *line_depth = depth;
+    }
+
+    let mut output = String::with_capacity(text.len());
+    for line in 0..lines {
+        let start = index.line_start(line);
+        let end = if line + 1 < lines {
+            index.line_start(line + 1) - 1
+        } else {
+            text.len()
+        };
+        let content = &text[start..end];
+
+        if inside_start[line] {
+            output.push_str(content);
+        } else if inside_end[line] {
+            push_indented(
+                &mut output,
+                content.trim_start(),
+                indent,
+                depths[line],
+                closes[line],
+                continued[line],
+            );
+        } else {
+            let carriage_return = line + 1 < lines && content.ends_with('\r');
+            push_indented(
+                &mut output,
+                trim_unescaped(content),
+                indent,
+                depths[line],
+                closes[line],
+                continued[line],
+            );
+            if carriage_return {
+                output.push('\r');
+            }
+        }
+        if line + 1 < lines {
+            output.push('\n');
+        }
+    }
+
+    // End with exactly one line break
+    let keep = output.trim_end_matches(['\r', '\n']).len();
+    let line_break = if output[keep..].starts_with("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    output.truncate(keep);
+    if keep > 0 {
+        output.push_str(line_break);
+    }
+
+    // Formatting only changes whitespace, which must not change the parse
+    if let Ok(before) = confetti_rs::parse(text, options.clone()) {
+        let after = confetti_rs::parse(&output, options.clone()).ok()?;
+        if after.directives != before.directives || after.comments != before.comments {
+            return None;
+        }
+    }
+    Some(output)
+}
+
+/// Returns `content` without surrounding whitespace, except a whitespace
+/// character escaped by a backslash, which is part of an argument.
+fn trim_unescaped(content: &str) -> &str {
+    let content = content.trim_start();
+    let trimmed = content.trim_end();
+    let backslashes = trimmed
+        .bytes()
+        .rev()
+        .take_while(|&byte| byte == b'\\')
+        .count();
+    match content[trimmed.len()..].chars().next() {
+        // A backslash before a line break is a continuation instead
+        Some(c) if backslashes % 2 == 1 && !matches!(c, '\r' | '\x0B' | '\x0C') => {
+            &content[..trimmed.len() + c.len_utf8()]
+        }
+        _ => trimmed,
+    }
+}
+
+/// Appends a line's content, indented unless it is blank.
+fn push_indented(
+    output: &mut String,
+    content: &str,
+    indent: &str,
+    depth: usize,
+    closes: bool,
+    continued: bool,
+) {
+    if content.is_empty() {
+        return;
+    }
+    let level = depth.saturating_sub(closes as usize) + continued as usize;
+    for _ in 0..level {
+        output.push_str(indent);
+    }
+    output.push_str(content);
+}
+
+/// Where a position falls in the directive structure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Context {
+    /// The names of the blocks enclosing the position, outermost first.
+    pub path: Vec<String>,
+    /// The directive name at the position and its span, if any.
+    pub name: Option<(String, Range<usize>)>,
+    /// Whether a directive name can be written at the position.
+    pub expects_name: bool,
+}
+
+/// Finds the context of a byte offset from the tokens before it.
+pub fn context_at(text: &str, offset: usize, options: &ConfOptions) -> Context {
+    let mut lexer = Lexer::new(text, options.clone());
+    let mut path = Vec::new();
+    let mut current: Option<String> = None;
+    let mut expects_name = true;
+
+    while let Ok(token) = lexer.next_token() {
+        let span = token.span.clone();
+        if token.token_type == TokenType::Eof || span.start > offset {
+            break;
+        }
+        let inside = span.start < offset && offset < span.end;
+        match token.token_type {
+            TokenType::Argument if span.start <= offset && offset <= span.end => {
+                let name = expects_name.then(|| (text[span.clone()].to_string(), span));
+                return Context {
+                    path,
+                    expects_name: name.is_some(),
+                    name,
+                };
+            }
+            TokenType::Argument if expects_name => {
+                current = Some(text[span].to_string());
+                expects_name = false;
+            }
+            TokenType::Argument => {}
+            // Line comments end before the line break, so their end is inside
+            TokenType::Comment if span.start < offset && offset <= span.end => {
+                expects_name = false;
+                break;
+            }
+            _ if inside => {
+                expects_name = false;
+                break;
+            }
+            _ if span.start == offset => break,
+            TokenType::LeftCurlyBrace => {
+                path.push(current.take().unwrap_or_default());
+                expects_name = true;
+            }
+            TokenType::RightCurlyBrace => {
+                path.pop();
+                current = None;
+                expects_name = true;
+            }
+            TokenType::Semicolon | TokenType::Newline => {
+                current = None;
+                expects_name = true;
+            }
+            _ => {}
+        }
+    }
+
+    Context {
+        path,
+        name: None,
+        expects_name,
+    }
+}
+
+/// Returns the schema description of the directive name at an offset.
+pub fn hover(text: &str, offset: usize, options: &ConfOptions, schema: &Schema) -> Value {
+    let context = context_at(text, offset, options);
+    let Some((name, span)) = context.name else {
+        return Value::Null;
+    };
+    let mut path: Vec<&str> = context.path.iter().map(String::as_str).collect();
+    path.push(&name);
+    let Some(entry) = schema.entry(&path) else {
+        return Value::Null;
+    };
+
+    let mut value = format!("**{}**", entry.name);
+    if let Some(description) = &entry.description {
+        value.push_str("\n\n");
+        value.push_str(description);
+    }
+    json!({
+        "contents": { "kind": "markdown", "value": value },
+        "range": LineIndex::new(text).range_json(span),
+    })
+}
+
+/// Returns the directive names the schema allows at an offset.
+pub fn completion(text: &str, offset: usize, options: &ConfOptions, schema: &Schema) -> Value {
+    let context = context_at(text, offset, options);
+    if !context.expects_name {
+        return json!([]);
+    }
+    let path: Vec<&str> = context.path.iter().map(String::as_str).collect();
+    Value::Array(
+        schema
+            .allowed(&path)
+            .iter()
+            .map(|entry| match &entry.description {
+                Some(description) => json!({
+                    "label": entry.name,
+                    "kind": COMPLETION_KEYWORD,
+                    "detail": description,
+                }),
+                None => json!({ "label": entry.name, "kind": COMPLETION_KEYWORD }),
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_default(text: &str) -> Option<String> {
+        format(text, &ConfOptions::default(), "    ")
+    }
+
+    #[test]
+    fn test_format_reindents_blocks() {
+        let input = "server {\nlisten 80;   \n      location / {\n  root /srv;\n}\n  }\n\n\n";
+        let expected = "server {\n    listen 80;\n    location / {\n        root /srv;\n    }\n}\n";
+        assert_eq!(format_default(input).unwrap(), expected);
+        // Formatting is idempotent
+        assert_eq!(format_default(expected).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_multi_line_tokens() {
+        let input = "a {\nb \"\"\"one  \n   two\"\"\"\n   c d \\\n e;\n}";
+        let expected = "a {\n    b \"\"\"one  \n   two\"\"\"\n    c d \\\n        e;\n}\n";
+        assert_eq!(format_default(input).unwrap(), expected);
+
+        let options = ConfOptions::all_extensions();
+        let heredoc = "a {\n  text <<END\n    body\n    END\n}\n";
+        assert_eq!(
+            format(heredoc, &options, "\t").unwrap(),
+            "a {\n\ttext <<END\n    body\n    END\n}\n"
+        );
+
+        assert!(format_default("a \"unterminated").is_none());
+    }
+
+    #[test]
+    fn test_format_keeps_escaped_trailing_whitespace() {
+        // Trimming the escaped space would turn the backslash into a line
+        // continuation joining the two directives
+        let input = "x a\\ \ny;\n";
+        assert_eq!(format_default(input).unwrap(), input);
+        assert_eq!(format_default("x a\\\\  \ny;\n").unwrap(), "x a\\\\\ny;\n");
+    }
+
+    #[test]
+    fn test_format_keeps_crlf() {
+        assert_eq!(
+            format_default("a {\r\nb;  \r\n}\r\n").unwrap(),
+            "a {\r\n    b;\r\n}\r\n"
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_split_at_lone_carriage_returns() {
+        let data = semantic_tokens("a\rb \"\"\"x\ry\"\"\";", &ConfOptions::default());
+        assert_eq!(
+            data["data"],
+            json!([
+                0, 0, 1, NAME, 0, 1, 0, 1, NAME, 0, 0, 2, 4, STRING, 0, 1, 0, 4, STRING, 0, 0, 4,
+                1, OPERATOR, 0
+            ])
+        );
+    }
+
+    #[test]
+    fn test_context() {
+        let text = "server {\n  listen 80;\n  location / {\n    \n  }\n}\n";
+        let options = ConfOptions::default();
+
+        let context = context_at(text, text.find("isten").unwrap(), &options);
+        assert_eq!(context.path, ["server"]);
+        assert_eq!(context.name, Some(("listen".to_string(), 11..17)));
+
+        let context = context_at(text, text.find("    \n").unwrap() + 4, &options);
+        assert_eq!(context.path, ["server", "location"]);
+        assert!(context.expects_name);
+        assert_eq!(context.name, None);
+
+        // On an argument
+        assert!(!context_at(text, text.find("80").unwrap() + 1, &options).expects_name);
+        // After the last block
+        assert!(context_at(text, text.len(), &options).path.is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+/*!
+# confetti-lsp
+
+A [Language Server Protocol](https://microsoft.github.io/language-server-protocol/)
+server for confetti configuration files, built on the `confetti-rs` parser.
+The `confetti-lsp` binary speaks the protocol over stdin and stdout.
+
+## Features
+
+- Diagnostics from parse errors, updated as the document changes
+- Incremental document sync, reparsing only the block an edit touches
+- Document symbols for the directive tree
+- Folding ranges for blocks and multi-line comments
+- Semantic tokens for directive names, arguments, strings, comments and punctuation
+- Formatting that reindents blocks
+- Hover and completion for directive names when a schema is configured
+
+## Configuration
+
+The client can pass initialization options:
+
+```json
+{ "schema": "/path/to/schema.conf", "dialect": "all_extensions" }
+```
+
+`dialect` is one of `default`, `spec_strict`, `all_extensions` and
+`nginx_like`, selecting the matching `ConfOptions` preset. Pragma comments in a
+file adjust these options as they do for the parser. `schema` names a schema
+file, described in [`schema`].
+*/
+
+use std::io::{self, BufRead, Write};
+
+pub mod analysis;
+pub mod schema;
+pub mod server;
+pub mod text;
+pub mod transport;
+
+pub use crate::server::Server;
+
+/// Serves one client over `input` and `output` until it sends `exit` or
+/// closes the input.
+///
+/// Returns the exit code the protocol prescribes: 0 if the client sent
+/// `shutdown` before leaving, 1 otherwise.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<u8> {
+    let mut server = Server::new();
+    while let Some(body) = transport::read_message(&mut input)? {
+        for message in server.handle(&body) {
+            transport::write_message(&mut output, &message)?;
+        }
+        if let Some(code) = server.exit_code() {
+            return Ok(code);
+        }
+    }
+    Ok(if server.is_shut_down() { 0 } else { 1 })
+}
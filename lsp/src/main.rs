@@ -0,0 +1,15 @@
+//! The confetti language server, speaking the Language Server Protocol over
+//! stdin and stdout.
+
+use std::io;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match confetti_lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        Ok(code) => ExitCode::from(code),
+        Err(err) => {
+            eprintln!("confetti-lsp: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Directive schemas for hover and completion.
+//!
+//! A schema is itself a confetti file. Each directive in it declares a name
+//! allowed at its level, its optional first argument describes it, and its
+//! children are the directives allowed in its block:
+//!
+//! ```text
+//! server "A virtual server." {
+//!     listen "The address and port to accept connections on.";
+//!     location "Settings for requests under a path prefix." {
+//!         root "The directory files are served from.";
+//!     }
+//! }
+//! ```
+
+use confetti_rs::{parse, ConfDirective, ConfError, ConfOptions};
+
+/// A directive declared by a schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The name of the directive.
+    pub name: String,
+    /// The description of the directive, if the schema gives one.
+    pub description: Option<String>,
+    /// The directives allowed in the block of the directive.
+    pub children: Vec<Entry>,
+}
+
+/// The directives a schema allows, by level.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    /// The directives allowed at the top level.
+    pub directives: Vec<Entry>,
+}
+
+impl Schema {
+    /// Parses a schema from its source text.
+    pub fn parse(text: &str) -> Result<Self, ConfError> {
+        let unit = parse(text, ConfOptions::default())?;
+        Ok(Self {
+            directives: unit.directives.iter().map(entry).collect(),
+        })
+    }
+
+    /// Returns the entry for the directive at a path of names.
+    pub fn entry(&self, path: &[&str]) -> Option<&Entry> {
+        let (last, parents) = path.split_last()?;
+        self.allowed(parents)
+            .iter()
+            .find(|entry| entry.name == *last)
+    }
+
+    /// Returns the directives allowed in the block at a path of names, or at
+    /// the top level for an empty path. Unknown blocks allow nothing.
+    pub fn allowed(&self, path: &[&str]) -> &[Entry] {
+        let mut directives = self.directives.as_slice();
+        for name in path {
+            match directives.iter().find(|entry| entry.name == *name) {
+                Some(entry) => directives = &entry.children,
+                None => return &[],
+            }
+        }
+        directives
+    }
+}
+
+/// Converts a schema directive, whose depth the parser has already bounded.
+fn entry(directive: &ConfDirective) -> Entry {
+    Entry {
+        name: directive.name.text(),
+        description: directive.arguments.first().map(|argument| argument.text()),
+        children: directive.children.iter().map(entry).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let schema = Schema::parse(
+            "server \"A virtual server.\" {\n  listen \"Where to listen.\";\n  location {\n    root;\n  }\n}\nuser;\n",
+        )
+        .unwrap();
+
+        let names = |entries: &[Entry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.name.clone()).collect()
+        };
+        assert_eq!(names(schema.allowed(&[])), ["server", "user"]);
+        assert_eq!(names(schema.allowed(&["server"])), ["listen", "location"]);
+        assert!(schema.allowed(&["user", "x"]).is_empty());
+
+        let listen = schema.entry(&["server", "listen"]).unwrap();
+        assert_eq!(listen.description.as_deref(), Some("Where to listen."));
+        assert_eq!(
+            schema
+                .entry(&["server", "location", "root"])
+                .unwrap()
+                .description,
+            None
+        );
+        assert!(schema.entry(&["listen"]).is_none());
+        assert!(schema.entry(&[]).is_none());
+    }
+}
@@ -0,0 +1,358 @@
+//! Message dispatch and document state.
+
+use std::collections::HashMap;
+
+use confetti_rs::incremental::{reparse, TextEdit};
+use confetti_rs::{parse, ConfError, ConfOptions, ConfUnit};
+use serde_json::{json, Value};
+
+use crate::analysis;
+use crate::schema::Schema;
+use crate::text::LineIndex;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+// Protocol enumerations
+const SYNC_INCREMENTAL: u32 = 2;
+const MESSAGE_ERROR: u32 = 1;
+
+/// An error response: a JSON-RPC error code and message.
+type RequestError = (i64, String);
+
+/// An open document and the result of parsing it.
+struct Document {
+    text: String,
+    /// The parsed unit, kept for incremental reparsing after edits.
+    unit: Option<ConfUnit>,
+    error: Option<ConfError>,
+}
+
+impl Document {
+    fn new(text: String, options: &ConfOptions) -> Self {
+        let mut document = Self {
+            text,
+            unit: None,
+            error: None,
+        };
+        document.set_result(parse(&document.text, options.clone()));
+        document
+    }
+
+    /// Replaces a byte range of the text, reparsing only what the edit
+    /// affects when the previous text parsed.
+    fn edit(&mut self, edit: TextEdit, options: &ConfOptions) {
+        let result = match self.unit.take() {
+            Some(unit) => reparse(unit, &self.text, &edit, options),
+            None => parse(&edit.apply(&self.text), options.clone()),
+        };
+        self.text = edit.apply(&self.text);
+        self.set_result(result);
+    }
+
+    fn set_result(&mut self, result: Result<ConfUnit, ConfError>) {
+        match result {
+            Ok(unit) => {
+                self.unit = Some(unit);
+                self.error = None;
+            }
+            Err(error) => {
+                self.unit = None;
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+/// The state of the language server.
+pub struct Server {
+    options: ConfOptions,
+    schema: Option<Schema>,
+    documents: HashMap<String, Document>,
+    initialized: bool,
+    shutdown: bool,
+    exit_code: Option<u8>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    /// Creates a server waiting for `initialize`.
+    pub fn new() -> Self {
+        Self {
+            options: ConfOptions::default(),
+            schema: None,
+            documents: HashMap::new(),
+            initialized: false,
+            shutdown: false,
+            exit_code: None,
+        }
+    }
+
+    /// Returns the process exit code once the client has sent `exit`.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    /// Returns whether the client has sent `shutdown`.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutdown
+    }
+
+    /// Handles the body of one message, returning the messages to send back.
+    pub fn handle(&mut self, body: &[u8]) -> Vec<Value> {
+        let message: Value = match serde_json::from_slice(body) {
+            Ok(message) => message,
+            Err(err) => return vec![error_response(Value::Null, PARSE_ERROR, err.to_string())],
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        match (message["method"].as_str(), message.get("id")) {
+            (Some("initialize"), Some(id)) => {
+                let (result, notifications) = self.initialize(&params);
+                let mut outgoing = vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })];
+                outgoing.extend(notifications);
+                outgoing
+            }
+            (Some(method), Some(id)) => match self.request(method, &params) {
+                Ok(result) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+                Err((code, message)) => vec![error_response(id.clone(), code, message)],
+            },
+            (Some(method), None) => self.notification(method, &params),
+            // The server sends no requests, so there are no responses to handle
+            (None, _) => Vec::new(),
+        }
+    }
+
+    /// Reads the initialization options and returns the server capabilities,
+    /// along with messages to show when the options cannot be used.
+    fn initialize(&mut self, params: &Value) -> (Value, Vec<Value>) {
+        let options = &params["initializationOptions"];
+        let mut notifications = Vec::new();
+
+        match options["dialect"].as_str() {
+            None | Some("default") => {}
+            Some("spec_strict") => self.options = ConfOptions::spec_strict(),
+            Some("all_extensions") => self.options = ConfOptions::all_extensions(),
+            Some("nginx_like") => self.options = ConfOptions::nginx_like(),
+            Some(other) => notifications.push(show_message(&format!(
+                "Unknown dialect '{}', using the default options",
+                other
+            ))),
+        }
+
+        if let Some(path) = options["schema"].as_str() {
+            match std::fs::read_to_string(path) {
+                Ok(text) => match Schema::parse(&text) {
+                    Ok(schema) => self.schema = Some(schema),
+                    Err(err) => notifications
+                        .push(show_message(&format!("Invalid schema {}: {}", path, err))),
+                },
+                Err(err) => notifications.push(show_message(&format!(
+                    "Cannot read schema {}: {}",
+                    path, err
+                ))),
+            }
+        }
+        self.initialized = true;
+
+        let mut capabilities = json!({
+            "textDocumentSync": { "openClose": true, "change": SYNC_INCREMENTAL },
+            "documentSymbolProvider": true,
+            "foldingRangeProvider": true,
+            "semanticTokensProvider": {
+                "legend": { "tokenTypes": analysis::TOKEN_TYPES, "tokenModifiers": [] },
+                "full": true,
+            },
+            "documentFormattingProvider": true,
+        });
+        if self.schema.is_some() {
+            capabilities["hoverProvider"] = json!(true);
+            capabilities["completionProvider"] = json!({});
+        }
+
+        let result = json!({
+            "capabilities": capabilities,
+            "serverInfo": { "name": "confetti-lsp", "version": env!("CARGO_PKG_VERSION") },
+        });
+        (result, notifications)
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, RequestError> {
+        if !self.initialized {
+            return Err((
+                SERVER_NOT_INITIALIZED,
+                "The server is not initialized".to_string(),
+            ));
+        }
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "The server is shutting down".to_string()));
+        }
+
+        match method {
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/documentSymbol" => {
+                let document = self.document(params)?;
+                let options = self.options_for(&document.text);
+                Ok(match &document.unit {
+                    Some(unit) => analysis::document_symbols(&document.text, unit, &options),
+                    None => json!([]),
+                })
+            }
+            "textDocument/foldingRange" => {
+                let document = self.document(params)?;
+                let options = self.options_for(&document.text);
+                Ok(analysis::folding_ranges(&document.text, &options))
+            }
+            "textDocument/semanticTokens/full" => {
+                let document = self.document(params)?;
+                let options = self.options_for(&document.text);
+                Ok(analysis::semantic_tokens(&document.text, &options))
+            }
+            "textDocument/formatting" => {
+                let document = self.document(params)?;
+                if document.error.is_some() {
+                    return Ok(json!([]));
+                }
+                let formatting = &params["options"];
+                let tab_size = formatting["tabSize"].as_u64().unwrap_or(4) as usize;
+                let indent = match formatting["insertSpaces"].as_bool() {
+                    Some(false) => "\t".to_string(),
+                    _ => " ".repeat(tab_size),
+                };
+                let text = &document.text;
+                let options = self.options_for(text);
+                Ok(match analysis::format(text, &options, &indent) {
+                    Some(formatted) if formatted != *text => json!([{
+                        "range": LineIndex::new(text).range_json(0..text.len()),
+                        "newText": formatted,
+                    }]),
+                    _ => json!([]),
+                })
+            }
+            "textDocument/hover" | "textDocument/completion" => {
+                let document = self.document(params)?;
+                let Some(schema) = &self.schema else {
+                    return Ok(Value::Null);
+                };
+                let offset = LineIndex::new(&document.text)
+                    .offset_from_json(&params["position"])
+                    .ok_or_else(|| invalid_params("Missing position"))?;
+                let options = self.options_for(&document.text);
+                Ok(if method == "textDocument/hover" {
+                    analysis::hover(&document.text, offset, &options, schema)
+                } else {
+                    analysis::completion(&document.text, offset, &options, schema)
+                })
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method '{}'", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        if method == "exit" {
+            self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+            return Vec::new();
+        }
+        if !self.initialized {
+            return Vec::new();
+        }
+
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                let document = Document::new(text.to_string(), &self.options);
+                self.documents.insert(uri.to_string(), document);
+                self.publish_diagnostics(uri)
+            }
+            "textDocument/didChange" => {
+                let Some(document) = self.documents.get_mut(uri) else {
+                    return Vec::new();
+                };
+                let changes = params["contentChanges"]
+                    .as_array()
+                    .map_or(&[][..], Vec::as_slice);
+                for change in changes {
+                    let text = change["text"].as_str().unwrap_or_default();
+                    let range = LineIndex::new(&document.text).range_from_json(&change["range"]);
+                    match range {
+                        Some(range) => document.edit(TextEdit::new(range, text), &self.options),
+                        None => *document = Document::new(text.to_string(), &self.options),
+                    }
+                }
+                self.publish_diagnostics(uri)
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Vec<Value> {
+        let Some(document) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let diagnostics = analysis::diagnostics(&document.text, document.error.as_ref());
+        vec![notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )]
+    }
+
+    fn document(&self, params: &Value) -> Result<&Document, RequestError> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| invalid_params("Missing textDocument.uri"))?;
+        self.documents
+            .get(uri)
+            .ok_or_else(|| invalid_params(&format!("Unknown document {}", uri)))
+    }
+
+    /// Returns the options in effect for a text after its pragma, so that the
+    /// lexer-based features agree with the parser.
+    fn options_for(&self, text: &str) -> ConfOptions {
+        self.options
+            .for_input(text)
+            .unwrap_or_else(|_| self.options.clone())
+    }
+}
+
+fn invalid_params(message: &str) -> RequestError {
+    (INVALID_PARAMS, message.to_string())
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn show_message(message: &str) -> Value {
+    notification(
+        "window/showMessage",
+        json!({ "type": MESSAGE_ERROR, "message": message }),
+    )
+}
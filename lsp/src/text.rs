@@ -0,0 +1,141 @@
+//! Conversions between byte offsets and protocol positions.
+//!
+//! Positions are zero-based lines and UTF-16 code unit columns, the default
+//! position encoding of the protocol.
+
+use std::ops::Range;
+
+use serde_json::{json, Value};
+
+/// The start of every line of a text, for converting offsets and positions.
+pub struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    /// Indexes the lines of `text`. As in the protocol, `\r\n`, `\n` and a
+    /// lone `\r` each end a line.
+    pub fn new(text: &'a str) -> Self {
+        let bytes = text.as_bytes();
+        let mut line_starts = vec![0];
+        line_starts.extend(
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|&(index, &byte)| {
+                    byte == b'\n' || (byte == b'\r' && bytes.get(index + 1) != Some(&b'\n'))
+                })
+                .map(|(index, _)| index + 1),
+        );
+        Self { text, line_starts }
+    }
+
+    /// Returns the line and UTF-16 column of a byte offset.
+    pub fn position(&self, offset: usize) -> (u32, u32) {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let column: usize = self.text[start..offset].chars().map(char::len_utf16).sum();
+        (line as u32, column as u32)
+    }
+
+    /// Returns the byte offset of a line and UTF-16 column, clamped to the
+    /// end of the line and of the text.
+    pub fn offset(&self, line: u32, character: u32) -> usize {
+        let Some(&start) = self.line_starts.get(line as usize) else {
+            return self.text.len();
+        };
+        let mut remaining = character as usize;
+        for (index, c) in self.text[start..].char_indices() {
+            if remaining == 0 || c == '\n' || c == '\r' {
+                return start + index;
+            }
+            remaining = remaining.saturating_sub(c.len_utf16());
+        }
+        self.text.len()
+    }
+
+    /// Returns the number of lines.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Returns the byte offset where a line starts.
+    pub fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line]
+    }
+
+    /// Returns a protocol `Position` for a byte offset.
+    pub fn position_json(&self, offset: usize) -> Value {
+        let (line, character) = self.position(offset);
+        json!({ "line": line, "character": character })
+    }
+
+    /// Returns a protocol `Range` for a byte range.
+    pub fn range_json(&self, range: Range<usize>) -> Value {
+        json!({
+            "start": self.position_json(range.start),
+            "end": self.position_json(range.end),
+        })
+    }
+
+    /// Returns the byte range of a protocol `Range`, or `None` if it is malformed.
+    pub fn range_from_json(&self, range: &Value) -> Option<Range<usize>> {
+        let start = self.offset_from_json(&range["start"])?;
+        let end = self.offset_from_json(&range["end"])?;
+        Some(start..end.max(start))
+    }
+
+    /// Returns the byte offset of a protocol `Position`.
+    pub fn offset_from_json(&self, position: &Value) -> Option<usize> {
+        let line = position["line"].as_u64()?;
+        let character = position["character"].as_u64()?;
+        Some(self.offset(
+            u32::try_from(line).ok()?,
+            u32::try_from(character).unwrap_or(u32::MAX),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_positions_count_utf16_units() {
+        let text = "a é\n😀b\r\nc";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_count(), 3);
+
+        assert_eq!(index.position(0), (0, 0));
+        assert_eq!(index.position(2), (0, 2));
+        assert_eq!(index.position(5), (1, 0));
+        // The emoji is two UTF-16 code units
+        assert_eq!(index.position(9), (1, 2));
+        assert_eq!(index.position(text.len()), (2, 1));
+
+        assert_eq!(index.offset(1, 2), 9);
+        assert_eq!(index.offset(0, 3), 4);
+        // Past the end of a line or of the text
+        assert_eq!(index.offset(1, 40), 10);
+        assert_eq!(index.offset(9, 0), text.len());
+    }
+
+    #[test]
+    fn test_lone_carriage_returns_end_lines() {
+        let text = "a\rbc\r\nd\ne";
+        let index = LineIndex::new(text);
+        assert_eq!(index.line_count(), 4);
+
+        assert_eq!(index.position(1), (0, 1));
+        assert_eq!(index.position(2), (1, 0));
+        assert_eq!(index.position(4), (1, 2));
+        assert_eq!(index.position(6), (2, 0));
+        assert_eq!(index.position(8), (3, 0));
+
+        assert_eq!(index.offset(1, 1), 3);
+        assert_eq!(index.offset(1, 9), 4);
+        assert_eq!(index.offset(2, 0), 6);
+    }
+}
@@ -0,0 +1,75 @@
+//! The base protocol: JSON-RPC messages framed by a `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+/// Reads the body of the next message, or `None` at the end of the input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let header = line.trim_end_matches(['\r', '\n']);
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                let value = value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                length = Some(value);
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Writes a message with its header and flushes the writer.
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = serde_json::to_vec(message)?;
+    write!(writer, "Content-Length: {}\r\n\r\n", body.len())?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let mut buffer = Vec::new();
+        write_message(&mut buffer, &json!({"jsonrpc": "2.0", "method": "é"})).unwrap();
+        write_message(&mut buffer, &json!(1)).unwrap();
+
+        let mut reader = io::Cursor::new(buffer);
+        let first = read_message(&mut reader).unwrap().unwrap();
+        let first: Value = serde_json::from_slice(&first).unwrap();
+        assert_eq!(first["method"], "é");
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"1");
+        assert!(read_message(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_headers() {
+        let input = "content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}";
+        let mut reader = io::Cursor::new(input);
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"{}");
+
+        let mut reader = io::Cursor::new("Content-Type: x\r\n\r\n{}");
+        assert!(read_message(&mut reader).is_err());
+    }
+}
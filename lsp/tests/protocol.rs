@@ -0,0 +1,405 @@
+//! Scripted JSON-RPC sessions against the `confetti-lsp` binary.
+
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+
+use confetti_lsp::transport::{read_message, write_message};
+use serde_json::{json, Value};
+
+const URI: &str = "file:///srv/site.conf";
+
+/// A script of client messages, sent to the server in one go.
+struct Script {
+    messages: Vec<Value>,
+    next_id: i64,
+}
+
+/// The messages the server sent back and its exit code.
+struct Transcript {
+    messages: Vec<Value>,
+    code: Option<i32>,
+}
+
+impl Script {
+    fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Starts a script with `initialize` and `initialized`.
+    fn initialized(options: Value) -> Self {
+        let mut script = Self::new();
+        script.request(
+            "initialize",
+            json!({ "processId": null, "capabilities": {}, "initializationOptions": options }),
+        );
+        script.notify("initialized", json!({}));
+        script
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.messages
+            .push(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        id
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.messages
+            .push(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn open(&mut self, text: &str) {
+        self.notify(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "confetti", "version": 1, "text": text } }),
+        );
+    }
+
+    fn document_request(&mut self, method: &str, extra: Value) -> i64 {
+        let mut params = json!({ "textDocument": { "uri": URI } });
+        if let (Some(params), Some(extra)) = (params.as_object_mut(), extra.as_object()) {
+            params.extend(extra.clone());
+        }
+        self.request(method, params)
+    }
+
+    /// Ends the session properly and runs the script.
+    fn finish(mut self) -> Transcript {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        self.run()
+    }
+
+    fn run(self) -> Transcript {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_confetti-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("the server starts");
+
+        let mut input = Vec::new();
+        for message in &self.messages {
+            write_message(&mut input, message).unwrap();
+        }
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&input).unwrap();
+        drop(stdin);
+
+        let output = child.wait_with_output().unwrap();
+        let mut reader = Cursor::new(output.stdout);
+        let mut messages = Vec::new();
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            messages.push(serde_json::from_slice(&body).unwrap());
+        }
+        Transcript {
+            messages,
+            code: output.status.code(),
+        }
+    }
+}
+
+impl Transcript {
+    fn response(&self, id: i64) -> &Value {
+        self.messages
+            .iter()
+            .find(|message| message["id"] == id)
+            .unwrap_or_else(|| panic!("No response to request {}", id))
+    }
+
+    fn result(&self, id: i64) -> &Value {
+        let response = self.response(id);
+        assert!(response.get("error").is_none(), "{}", response);
+        &response["result"]
+    }
+
+    fn error_code(&self, id: i64) -> i64 {
+        self.response(id)["error"]["code"].as_i64().unwrap()
+    }
+
+    fn notifications(&self, method: &str) -> Vec<&Value> {
+        self.messages
+            .iter()
+            .filter(|message| message["method"] == method)
+            .map(|message| &message["params"])
+            .collect()
+    }
+}
+
+fn position(line: u32, character: u32) -> Value {
+    json!({ "line": line, "character": character })
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+    json!({ "start": position(start.0, start.1), "end": position(end.0, end.1) })
+}
+
+#[test]
+fn test_lifecycle() {
+    let mut script = Script::new();
+    let early = script.request("textDocument/documentSymbol", json!({}));
+    let initialize = script.request("initialize", json!({ "capabilities": {} }));
+    let unknown = script.request("workspace/unknown", json!({}));
+    let transcript = script.finish();
+
+    assert_eq!(transcript.code, Some(0));
+    assert_eq!(transcript.error_code(early), -32002);
+    assert_eq!(transcript.error_code(unknown), -32601);
+
+    let result = transcript.result(initialize);
+    assert_eq!(result["serverInfo"]["name"], "confetti-lsp");
+    let capabilities = &result["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"]["change"], 2);
+    assert_eq!(capabilities["documentFormattingProvider"], true);
+    // Hover and completion need a schema
+    assert!(capabilities.get("hoverProvider").is_none());
+
+    // Exiting without a shutdown request is an error
+    let mut script = Script::initialized(json!({}));
+    script.notify("exit", Value::Null);
+    assert_eq!(script.run().code, Some(1));
+}
+
+#[test]
+fn test_diagnostics_follow_edits() {
+    let mut script = Script::initialized(json!({}));
+    script.open("server {\n  listen 80;\n");
+    // Close the block
+    script.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "range": range((2, 0), (2, 0)), "text": "}\n" }],
+        }),
+    );
+    // Break an argument inside the block, then replace the whole text
+    script.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 3 },
+            "contentChanges": [{ "range": range((1, 9), (1, 11)), "text": "\"80" }],
+        }),
+    );
+    script.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 4 },
+            "contentChanges": [{ "text": "a; b;\n" }],
+        }),
+    );
+    script.notify(
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let transcript = script.finish();
+
+    let published = transcript.notifications("textDocument/publishDiagnostics");
+    assert_eq!(published.len(), 5);
+    assert!(published.iter().all(|params| params["uri"] == URI));
+
+    let diagnostic = &published[0]["diagnostics"][0];
+    assert_eq!(diagnostic["message"], "Expected '}'");
+    assert_eq!(diagnostic["severity"], 1);
+    assert_eq!(diagnostic["range"], range((2, 0), (2, 0)));
+
+    assert_eq!(published[1]["diagnostics"], json!([]));
+    let diagnostic = &published[2]["diagnostics"][0];
+    assert_eq!(diagnostic["range"]["start"]["line"], 1);
+    assert_eq!(published[3]["diagnostics"], json!([]));
+    assert_eq!(published[4]["diagnostics"], json!([]));
+}
+
+#[test]
+fn test_symbols_and_folding() {
+    let mut script = Script::initialized(json!({}));
+    script.open("http {\n  server {\n    listen 80;\n  }\n}\n}\n");
+    let symbols = script.document_request("textDocument/documentSymbol", json!({}));
+    let folding = script.document_request("textDocument/foldingRange", json!({}));
+    let transcript = script.finish();
+
+    // The stray brace makes the text fail to parse, so there are no symbols,
+    // but folding works on tokens
+    assert_eq!(transcript.result(symbols), &json!([]));
+    assert_eq!(
+        transcript.result(folding),
+        &json!([{ "startLine": 0, "endLine": 3 }, { "startLine": 1, "endLine": 2 }])
+    );
+
+    let mut script = Script::initialized(json!({ "dialect": "all_extensions" }));
+    script.open("user www;\nhttp {\n  server {\n    listen 80 default;\n  }\n}\n/* a\n b */\n");
+    let symbols = script.document_request("textDocument/documentSymbol", json!({}));
+    let folding = script.document_request("textDocument/foldingRange", json!({}));
+    let transcript = script.finish();
+
+    let symbols = transcript.result(symbols);
+    assert_eq!(symbols[0]["name"], "user");
+    assert_eq!(symbols[0]["kind"], 7);
+    let http = &symbols[1];
+    assert_eq!(http["kind"], 19);
+    assert_eq!(http["range"], range((1, 0), (5, 1)));
+    assert_eq!(http["selectionRange"], range((1, 0), (1, 4)));
+    let listen = &http["children"][0]["children"][0];
+    assert_eq!(listen["name"], "listen");
+    assert_eq!(listen["detail"], "80 default");
+    assert_eq!(listen["range"], range((3, 4), (3, 22)));
+
+    let folding = transcript.result(folding).as_array().unwrap();
+    assert_eq!(folding.len(), 3);
+    assert_eq!(
+        folding[2],
+        json!({ "startLine": 6, "endLine": 7, "kind": "comment" })
+    );
+}
+
+#[test]
+fn test_semantic_tokens() {
+    let mut script = Script::initialized(json!({}));
+    script.open("# note\nlog \"é x\" {\n  level debug;\n}\n");
+    let initialize = 1;
+    let tokens = script.document_request("textDocument/semanticTokens/full", json!({}));
+    let transcript = script.finish();
+
+    let legend = &transcript.result(initialize)["capabilities"]["semanticTokensProvider"]["legend"];
+    let types: Vec<&str> = legend["tokenTypes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|name| name.as_str().unwrap())
+        .collect();
+
+    // Decode the relative encoding into (line, column, length, type)
+    let data = transcript.result(tokens)["data"]
+        .as_array()
+        .unwrap()
+        .clone();
+    let mut decoded = Vec::new();
+    let (mut line, mut column) = (0, 0);
+    for chunk in data.chunks(5) {
+        let value = |i: usize| chunk[i].as_u64().unwrap();
+        if value(0) > 0 {
+            column = 0;
+        }
+        line += value(0);
+        column += value(1);
+        decoded.push((line, column, value(2), types[value(3) as usize]));
+    }
+
+    assert_eq!(
+        decoded,
+        vec![
+            (0, 0, 6, "comment"),
+            (1, 0, 3, "property"),
+            (1, 4, 5, "string"),
+            (1, 10, 1, "operator"),
+            (2, 2, 5, "property"),
+            (2, 8, 5, "parameter"),
+            (2, 13, 1, "operator"),
+            (3, 0, 1, "operator"),
+        ]
+    );
+}
+
+#[test]
+fn test_formatting() {
+    let mut script = Script::initialized(json!({}));
+    script.open("a {\nb 1;\n    c {\nd;\n}\n}");
+    let spaces = script.document_request(
+        "textDocument/formatting",
+        json!({ "options": { "tabSize": 2, "insertSpaces": true } }),
+    );
+    let tabs = script.document_request(
+        "textDocument/formatting",
+        json!({ "options": { "tabSize": 4, "insertSpaces": false } }),
+    );
+    let transcript = script.finish();
+
+    let edits = transcript.result(spaces).as_array().unwrap();
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0]["range"], range((0, 0), (5, 1)));
+    assert_eq!(edits[0]["newText"], "a {\n  b 1;\n  c {\n    d;\n  }\n}\n");
+    assert_eq!(
+        transcript.result(tabs)[0]["newText"],
+        "a {\n\tb 1;\n\tc {\n\t\td;\n\t}\n}\n"
+    );
+
+    // Formatted text and text with errors need no edits
+    let mut script = Script::initialized(json!({}));
+    script.open("a {\n    b;\n}\n");
+    let formatted = script.document_request("textDocument/formatting", json!({ "options": {} }));
+    let transcript = script.finish();
+    assert_eq!(transcript.result(formatted), &json!([]));
+}
+
+#[test]
+fn test_schema_hover_and_completion() {
+    let path =
+        std::env::temp_dir().join(format!("confetti-lsp-schema-{}.conf", std::process::id()));
+    std::fs::write(
+        &path,
+        "server \"A virtual server.\" {\n  listen \"Where to accept connections.\";\n  root;\n}\nuser;\n",
+    )
+    .unwrap();
+
+    let mut script = Script::initialized(json!({ "schema": path.to_str().unwrap() }));
+    script.open("server {\n  listen 80;\n  \n}\n");
+    let hover =
+        script.document_request("textDocument/hover", json!({ "position": position(1, 4) }));
+    let on_argument =
+        script.document_request("textDocument/hover", json!({ "position": position(1, 10) }));
+    let nested = script.document_request(
+        "textDocument/completion",
+        json!({ "position": position(2, 2) }),
+    );
+    let top = script.document_request(
+        "textDocument/completion",
+        json!({ "position": position(4, 0) }),
+    );
+    let transcript = script.finish();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(transcript.result(1)["capabilities"]["hoverProvider"], true);
+
+    let hover = transcript.result(hover);
+    assert_eq!(
+        hover["contents"]["value"],
+        "**listen**\n\nWhere to accept connections."
+    );
+    assert_eq!(hover["range"], range((1, 2), (1, 8)));
+    assert_eq!(transcript.result(on_argument), &Value::Null);
+
+    let labels = |id| -> Vec<String> {
+        transcript
+            .result(id)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(labels(nested), ["listen", "root"]);
+    assert_eq!(labels(top), ["server", "user"]);
+    assert_eq!(
+        transcript.result(nested)[0]["detail"],
+        "Where to accept connections."
+    );
+
+    // A missing schema is reported, and the server keeps working
+    let mut script = Script::initialized(json!({ "schema": "/nonexistent/schema.conf" }));
+    script.open("a;\n");
+    let transcript = script.finish();
+    let shown = transcript.notifications("window/showMessage");
+    assert_eq!(shown.len(), 1);
+    assert!(shown[0]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Cannot read schema"));
+    assert_eq!(
+        transcript
+            .notifications("textDocument/publishDiagnostics")
+            .len(),
+        1
+    );
+}
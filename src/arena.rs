@@ -0,0 +1,799 @@
+//! A compact representation of a configuration unit for large inputs.
+//!
+//! [`Arena`] keeps every directive in one vector and every argument in
+//! another, the text of all arguments and comments in a single string, and
+//! each distinct directive name once, as a [`Symbol`]. [`Arena::parse`]
+//! builds the arena directly while parsing, so a unit of any size takes a
+//! handful of allocations plus a few per distinct name, instead of several
+//! per directive as with [`parse`](crate::parse). Directives are read through
+//! [`Directive`] handles whose navigation mirrors [`ConfDirective`].
+//!
+//! Directives are stored level by level, so the children of a directive are
+//! adjacent and every child comes after its parent.
+//!
+//! # Examples
+//!
+//! ```
+//! use confetti_rs::arena::Arena;
+//! use confetti_rs::ConfOptions;
+//!
+//! let input = "server {\n  listen 80;\n  listen 443;\n}\n";
+//! let arena = Arena::parse(input, ConfOptions::default()).unwrap();
+//!
+//! let server = arena.directives().next().unwrap();
+//! assert_eq!(server.name().value, "server");
+//! let ports: Vec<&str> = server
+//!     .children_named("listen")
+//!     .map(|listen| listen.arguments().next().unwrap().value)
+//!     .collect();
+//! assert_eq!(ports, ["80", "443"]);
+//!
+//! let unit = arena.to_unit();
+//! assert_eq!(unit.directives[0].children.len(), 2);
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::ops::Range;
+
+use crate::borrowed::{self, map_tree};
+use crate::parser::{self, Parser};
+use crate::{
+    argument_text, ConfArgument, ConfComment, ConfDirective, ConfError, ConfOptions, ConfUnit, Span,
+};
+
+/// A directive name stored once in an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/// The position of a directive in an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DirectiveId(u32);
+
+impl DirectiveId {
+    /// Returns the position of the directive in its arena, counting from 0.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A configuration unit stored in a few flat vectors.
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    /// The distinct directive names as written in the source.
+    names: Vec<String>,
+    /// The symbol of each name, by its value.
+    symbols: HashMap<String, Symbol>,
+    /// The symbols of the names that stand for each text.
+    symbols_by_text: HashMap<String, Vec<Symbol>>,
+    /// The values of all arguments and the content of all comments.
+    text: String,
+    directives: Vec<Node>,
+    arguments: Vec<Slot>,
+    comments: Vec<Slot>,
+    /// The number of root directives, which come first.
+    roots: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    name: Symbol,
+    name_span: Span,
+    name_flags: Flags,
+    arguments: Range<u32>,
+    children: Range<u32>,
+}
+
+/// An argument or comment, whose text is a range of the arena's text.
+#[derive(Debug, Clone)]
+struct Slot {
+    text: Range<u32>,
+    span: Span,
+    flags: Flags,
+}
+
+/// The kind of an argument, or whether a comment spans lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Flags(u8);
+
+impl Flags {
+    const QUOTED: u8 = 1;
+    const TRIPLE_QUOTED: u8 = 1 << 1;
+    const RAW: u8 = 1 << 2;
+    const HEREDOC: u8 = 1 << 3;
+    const EXPRESSION: u8 = 1 << 4;
+    const MULTI_LINE: u8 = 1 << 5;
+
+    fn new(bits: [(bool, u8); 5]) -> Self {
+        Flags(
+            bits.iter()
+                .filter(|(set, _)| *set)
+                .fold(0, |flags, (_, bit)| flags | bit),
+        )
+    }
+
+    fn has(self, bit: u8) -> bool {
+        self.0 & bit != 0
+    }
+}
+
+/// A directive in an [`Arena`].
+#[derive(Clone, Copy)]
+pub struct Directive<'a> {
+    arena: &'a Arena,
+    id: DirectiveId,
+}
+
+/// An argument or directive name in an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argument<'a> {
+    /// The value of the argument, as written in the source.
+    pub value: &'a str,
+    /// The span of the argument in the source text.
+    pub span: Span,
+    /// Whether the argument is quoted.
+    pub is_quoted: bool,
+    /// Whether the argument is a triple-quoted string.
+    pub is_triple_quoted: bool,
+    /// Whether the argument is a raw string.
+    pub is_raw: bool,
+    /// Whether the argument is a heredoc.
+    pub is_heredoc: bool,
+    /// Whether the argument is an expression.
+    pub is_expression: bool,
+}
+
+/// A comment in an [`Arena`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comment<'a> {
+    /// The content of the comment.
+    pub content: &'a str,
+    /// The span of the comment in the source text.
+    pub span: Span,
+    /// Whether the comment is a multi-line comment.
+    pub is_multi_line: bool,
+}
+
+impl Arena {
+    /// Parses a configuration string into an arena.
+    ///
+    /// The arena is built as the input is parsed, without a tree of
+    /// directives in between.
+    pub fn parse(input: &str, options: ConfOptions) -> Result<Self, ConfError> {
+        Parser::new(input, options)?.parse_tree(&mut Builder::default())
+    }
+
+    /// Packs an owned unit into an arena.
+    pub fn from_unit(unit: &ConfUnit) -> Self {
+        Self::build(
+            &unit.directives,
+            unit.comments.iter().map(|comment| {
+                (
+                    comment.content.as_str(),
+                    comment.span,
+                    comment.is_multi_line,
+                )
+            }),
+        )
+    }
+
+    /// Packs a borrowed unit into an arena.
+    pub fn from_borrowed(unit: &borrowed::ConfUnit<'_>) -> Self {
+        Self::build(
+            &unit.directives,
+            unit.comments
+                .iter()
+                .map(|comment| (&*comment.content, comment.span, comment.is_multi_line)),
+        )
+    }
+
+    /// Returns the owned unit the arena stands for.
+    pub fn to_unit(&self) -> ConfUnit {
+        // Children come after their parents, so building from the last
+        // directive finds the children of each one already built
+        let mut built: Vec<Option<ConfDirective>> = Vec::new();
+        built.resize_with(self.directives.len(), || None);
+        for index in (0..self.directives.len()).rev() {
+            let directive = Directive {
+                arena: self,
+                id: DirectiveId(index as u32),
+            };
+            let children = self.directives[index]
+                .children
+                .clone()
+                .map(|child| {
+                    built[child as usize]
+                        .take()
+                        .expect("children come after their parent")
+                })
+                .collect();
+            built[index] = Some(ConfDirective {
+                name: directive.name().to_argument(),
+                arguments: directive.arguments().map(|a| a.to_argument()).collect(),
+                children,
+            });
+        }
+
+        ConfUnit {
+            directives: built
+                .into_iter()
+                .take(self.roots as usize)
+                .map(|directive| directive.expect("roots have no parent"))
+                .collect(),
+            comments: self
+                .comments()
+                .map(|comment| ConfComment {
+                    content: comment.content.to_string(),
+                    span: comment.span,
+                    is_multi_line: comment.is_multi_line,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the root directives.
+    pub fn directives(&self) -> impl ExactSizeIterator<Item = Directive<'_>> + '_ {
+        self.range(0..self.roots)
+    }
+
+    /// Returns every directive, the roots first and then level by level.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = Directive<'_>> + '_ {
+        self.range(0..self.directives.len() as u32)
+    }
+
+    /// Returns every directive with a name, at any depth.
+    ///
+    /// Names are compared by the text they stand for, as in
+    /// [`ConfDirective::child`].
+    pub fn directives_named<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = Directive<'a>> + 'a {
+        let symbols = self.symbols_for(name);
+        self.iter()
+            .filter(move |directive| symbols.contains(&directive.symbol()))
+    }
+
+    /// Returns a directive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the id is not from this arena.
+    pub fn directive(&self, id: DirectiveId) -> Directive<'_> {
+        assert!(id.index() < self.directives.len(), "no such directive");
+        Directive { arena: self, id }
+    }
+
+    /// Returns the comments.
+    pub fn comments(&self) -> impl ExactSizeIterator<Item = Comment<'_>> + '_ {
+        self.comments.iter().map(|slot| Comment {
+            content: self.slice(&slot.text),
+            span: slot.span,
+            is_multi_line: slot.flags.has(Flags::MULTI_LINE),
+        })
+    }
+
+    /// Returns the number of directives, at any depth.
+    pub fn len(&self) -> usize {
+        self.directives.len()
+    }
+
+    /// Returns whether the arena holds no directive.
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    /// Returns the symbol of a directive name as written in the source,
+    /// such as `listen` or `"log level"`, if a directive has it.
+    pub fn symbol(&self, value: &str) -> Option<Symbol> {
+        self.symbols.get(value).copied()
+    }
+
+    /// Returns the directive name of a symbol as written in the source.
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+
+    /// Returns the symbols whose names stand for `text`.
+    fn symbols_for(&self, text: &str) -> &[Symbol] {
+        self.symbols_by_text.get(text).map_or(&[], Vec::as_slice)
+    }
+
+    fn range(&self, range: Range<u32>) -> impl ExactSizeIterator<Item = Directive<'_>> + '_ {
+        range.map(move |index| Directive {
+            arena: self,
+            id: DirectiveId(index),
+        })
+    }
+
+    fn slice(&self, range: &Range<u32>) -> &str {
+        &self.text[range.start as usize..range.end as usize]
+    }
+
+    fn build<'t, D: Tree + 't>(
+        roots: &'t [D],
+        comments: impl ExactSizeIterator<Item = (&'t str, Span, bool)>,
+    ) -> Self {
+        let mut arena = Arena::default();
+
+        // Size the vectors up front so that they are allocated once
+        let (mut directives, mut arguments, mut text) = (0, 0, 0);
+        let mut stack: Vec<&D> = roots.iter().collect();
+        while let Some(directive) = stack.pop() {
+            directives += 1;
+            arguments += directive.arguments().len();
+            text += directive.arguments().map(|a| a.value.len()).sum::<usize>();
+            stack.extend(directive.children());
+        }
+        let comments: Vec<(&str, Span, bool)> = comments.collect();
+        text += comments
+            .iter()
+            .map(|(content, ..)| content.len())
+            .sum::<usize>();
+        arena.directives.reserve_exact(directives);
+        arena.arguments.reserve_exact(arguments);
+        arena.text.reserve_exact(text);
+        arena.comments.reserve_exact(comments.len());
+
+        let mut queue: VecDeque<(usize, &'t [D])> = VecDeque::new();
+        for directive in roots {
+            let index = arena.push(directive);
+            queue.push_back((index, directive.children()));
+        }
+        arena.roots = count(roots.len());
+        while let Some((parent, children)) = queue.pop_front() {
+            let start = count(arena.directives.len());
+            for child in children {
+                let index = arena.push(child);
+                queue.push_back((index, child.children()));
+            }
+            arena.directives[parent].children = start..count(arena.directives.len());
+        }
+
+        for (content, span, is_multi_line) in comments {
+            let text = arena.push_text(content);
+            let flags = Flags(if is_multi_line { Flags::MULTI_LINE } else { 0 });
+            arena.comments.push(Slot { text, span, flags });
+        }
+        arena
+    }
+
+    /// Appends a directive without its children and returns its index.
+    fn push<D: Tree>(&mut self, directive: &D) -> usize {
+        let name = directive.name();
+        let symbol = self.intern(name);
+
+        let start = count(self.arguments.len());
+        for argument in directive.arguments() {
+            let text = self.push_text(argument.value);
+            self.arguments.push(Slot {
+                text,
+                span: argument.span,
+                flags: argument.flags,
+            });
+        }
+
+        self.directives.push(Node {
+            name: symbol,
+            name_span: name.span,
+            name_flags: name.flags,
+            arguments: start..count(self.arguments.len()),
+            children: 0..0,
+        });
+        self.directives.len() - 1
+    }
+
+    fn intern(&mut self, name: View<'_>) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name.value) {
+            return symbol;
+        }
+        let symbol = Symbol(count(self.names.len()));
+        self.symbols_by_text
+            .entry(name.text())
+            .or_default()
+            .push(symbol);
+        self.names.push(name.value.to_string());
+        self.symbols.insert(name.value.to_string(), symbol);
+        symbol
+    }
+
+    fn push_text(&mut self, text: &str) -> Range<u32> {
+        let start = count(self.text.len());
+        self.text.push_str(text);
+        start..count(self.text.len())
+    }
+}
+
+impl From<&ConfUnit> for Arena {
+    fn from(unit: &ConfUnit) -> Self {
+        Arena::from_unit(unit)
+    }
+}
+
+impl From<&Arena> for ConfUnit {
+    fn from(arena: &Arena) -> Self {
+        arena.to_unit()
+    }
+}
+
+impl<'a> Directive<'a> {
+    /// Returns the id of the directive in its arena.
+    pub fn id(&self) -> DirectiveId {
+        self.id
+    }
+
+    /// Returns the name of the directive.
+    pub fn name(&self) -> Argument<'a> {
+        let node = self.node();
+        Argument::new(self.arena.name(node.name), node.name_span, node.name_flags)
+    }
+
+    /// Returns the symbol of the directive's name.
+    pub fn symbol(&self) -> Symbol {
+        self.node().name
+    }
+
+    /// Returns the arguments of the directive.
+    pub fn arguments(&self) -> impl ExactSizeIterator<Item = Argument<'a>> + 'a {
+        let arena = self.arena;
+        let range = self.node().arguments.clone();
+        arena.arguments[range.start as usize..range.end as usize]
+            .iter()
+            .map(move |slot| Argument::new(arena.slice(&slot.text), slot.span, slot.flags))
+    }
+
+    /// Returns the child directives.
+    pub fn children(&self) -> impl ExactSizeIterator<Item = Directive<'a>> + 'a {
+        self.arena.range(self.node().children.clone())
+    }
+
+    /// Returns the first child with a name, compared by the text it stands
+    /// for as in [`ConfDirective::child`].
+    pub fn child(&self, name: &str) -> Option<Directive<'a>> {
+        self.children_named(name).next()
+    }
+
+    /// Returns the children with a name, in order.
+    pub fn children_named(&self, name: &str) -> impl Iterator<Item = Directive<'a>> + 'a {
+        let symbols = self.arena.symbols_for(name);
+        self.children()
+            .filter(move |child| symbols.contains(&child.symbol()))
+    }
+
+    /// Returns the directive and its descendants as an owned directive.
+    pub fn to_directive(&self) -> ConfDirective {
+        map_tree(
+            *self,
+            |directive| (directive, directive.children().collect()),
+            |directive, children| ConfDirective {
+                name: directive.name().to_argument(),
+                arguments: directive.arguments().map(|a| a.to_argument()).collect(),
+                children,
+            },
+        )
+    }
+
+    fn node(&self) -> &'a Node {
+        &self.arena.directives[self.id.index()]
+    }
+}
+
+impl fmt::Debug for Directive<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Directive")
+            .field("id", &self.id)
+            .field("name", &self.name().value)
+            .finish()
+    }
+}
+
+impl<'a> Argument<'a> {
+    fn new(value: &'a str, span: Span, flags: Flags) -> Self {
+        Argument {
+            value,
+            span,
+            is_quoted: flags.has(Flags::QUOTED),
+            is_triple_quoted: flags.has(Flags::TRIPLE_QUOTED),
+            is_raw: flags.has(Flags::RAW),
+            is_heredoc: flags.has(Flags::HEREDOC),
+            is_expression: flags.has(Flags::EXPRESSION),
+        }
+    }
+
+    /// Returns the text the argument stands for, as [`ConfArgument::text`]
+    /// does.
+    pub fn text(&self) -> String {
+        argument_text(
+            self.value,
+            self.is_quoted,
+            self.is_triple_quoted,
+            self.is_raw,
+            self.is_heredoc,
+        )
+    }
+
+    /// Returns the argument as an owned argument.
+    pub fn to_argument(&self) -> ConfArgument {
+        ConfArgument {
+            value: self.value.to_string(),
+            span: self.span,
+            is_quoted: self.is_quoted,
+            is_triple_quoted: self.is_triple_quoted,
+            is_raw: self.is_raw,
+            is_heredoc: self.is_heredoc,
+            is_expression: self.is_expression,
+        }
+    }
+}
+
+/// Returns a length or offset as stored in the arena.
+fn count(value: usize) -> u32 {
+    u32::try_from(value).expect("an arena holds fewer than 2^32 items and bytes")
+}
+
+/// Builds an arena as the parser reads the input.
+///
+/// The parser finishes the children of a block before the block itself, so
+/// the builder links each directive to its next sibling and lays the
+/// directives out level by level once the whole unit is parsed.
+#[derive(Default)]
+struct Builder {
+    arena: Arena,
+    /// The directives in the order the parser finished them.
+    finished: Vec<Finished>,
+}
+
+/// A parsed directive waiting for its place in the arena.
+struct Finished {
+    node: Node,
+    first_child: Option<u32>,
+    next_sibling: Option<u32>,
+}
+
+impl<'a> parser::Tree<'a> for Builder {
+    type Argument = borrowed::ConfArgument<'a>;
+    /// The arguments of a directive are parsed one after another, so they
+    /// take a range of the arena's arguments.
+    type Arguments = Range<u32>;
+    /// The position of a directive in `finished`.
+    type Directive = u32;
+    /// The first and last of a list of siblings.
+    type Children = Option<(u32, u32)>;
+    /// Comments are stored as they are parsed.
+    type Comment = ();
+    type Unit = Arena;
+
+    fn argument(&mut self, argument: borrowed::ConfArgument<'a>) -> Self::Argument {
+        argument
+    }
+
+    fn push_argument(&mut self, arguments: &mut Self::Arguments, argument: Self::Argument) {
+        let argument = view(&argument);
+        let text = self.arena.push_text(argument.value);
+        let index = count(self.arena.arguments.len());
+        self.arena.arguments.push(Slot {
+            text,
+            span: argument.span,
+            flags: argument.flags,
+        });
+        if arguments.start == arguments.end {
+            arguments.start = index;
+        }
+        arguments.end = index + 1;
+    }
+
+    fn directive(
+        &mut self,
+        name: Self::Argument,
+        arguments: Self::Arguments,
+        children: Self::Children,
+    ) -> Self::Directive {
+        let name = view(&name);
+        let symbol = self.arena.intern(name);
+        self.finished.push(Finished {
+            node: Node {
+                name: symbol,
+                name_span: name.span,
+                name_flags: name.flags,
+                arguments,
+                children: 0..0,
+            },
+            first_child: children.map(|(first, _)| first),
+            next_sibling: None,
+        });
+        count(self.finished.len() - 1)
+    }
+
+    fn push_child(&mut self, children: &mut Self::Children, child: Self::Directive) {
+        *children = match *children {
+            Some((first, last)) => {
+                self.finished[last as usize].next_sibling = Some(child);
+                Some((first, child))
+            }
+            None => Some((child, child)),
+        };
+    }
+
+    fn comment(&mut self, comment: borrowed::ConfComment<'a>) -> Self::Comment {
+        let text = self.arena.push_text(&comment.content);
+        let flags = Flags(if comment.is_multi_line {
+            Flags::MULTI_LINE
+        } else {
+            0
+        });
+        self.arena.comments.push(Slot {
+            text,
+            span: comment.span,
+            flags,
+        });
+    }
+
+    fn unit(&mut self, directives: Self::Children, _comments: Vec<()>) -> Arena {
+        let mut arena = mem::take(&mut self.arena);
+        let finished = mem::take(&mut self.finished);
+        let siblings = |first: Option<u32>| {
+            iter::successors(first, |&index| finished[index as usize].next_sibling)
+        };
+
+        // `order` holds the directives in their arena order, and grows by
+        // the children of each directive as it is placed
+        let mut order: Vec<u32> = Vec::with_capacity(finished.len());
+        order.extend(siblings(directives.map(|(first, _)| first)));
+        arena.roots = count(order.len());
+        arena.directives.reserve_exact(finished.len());
+        let mut index = 0;
+        while let Some(&next) = order.get(index) {
+            let directive = &finished[next as usize];
+            let start = count(order.len());
+            order.extend(siblings(directive.first_child));
+            arena.directives.push(Node {
+                children: start..count(order.len()),
+                ..directive.node.clone()
+            });
+            index += 1;
+        }
+        arena
+    }
+}
+
+/// An argument of a tree being packed.
+#[derive(Clone, Copy)]
+struct View<'a> {
+    value: &'a str,
+    span: Span,
+    flags: Flags,
+}
+
+impl View<'_> {
+    fn text(&self) -> String {
+        Argument::new(self.value, self.span, self.flags).text()
+    }
+}
+
+/// The owned and borrowed directives, which are packed alike.
+trait Tree: Sized {
+    fn name(&self) -> View<'_>;
+    fn arguments(&self) -> impl ExactSizeIterator<Item = View<'_>>;
+    fn children(&self) -> &[Self];
+}
+
+macro_rules! impl_tree {
+    ($($directive:ty, $argument:ty);*) => {$(
+        impl Tree for $directive {
+            fn name(&self) -> View<'_> {
+                view(&self.name)
+            }
+
+            fn arguments(&self) -> impl ExactSizeIterator<Item = View<'_>> {
+                self.arguments.iter().map(view)
+            }
+
+            fn children(&self) -> &[Self] {
+                &self.children
+            }
+        }
+
+        impl<'a> From<&'a $argument> for View<'a> {
+            fn from(argument: &'a $argument) -> Self {
+                View {
+                    value: &argument.value,
+                    span: argument.span,
+                    flags: Flags::new([
+                        (argument.is_quoted, Flags::QUOTED),
+                        (argument.is_triple_quoted, Flags::TRIPLE_QUOTED),
+                        (argument.is_raw, Flags::RAW),
+                        (argument.is_heredoc, Flags::HEREDOC),
+                        (argument.is_expression, Flags::EXPRESSION),
+                    ]),
+                }
+            }
+        }
+    )*};
+}
+
+impl_tree!(ConfDirective, ConfArgument; borrowed::ConfDirective<'_>, borrowed::ConfArgument<'_>);
+
+fn view<'a, A>(argument: &'a A) -> View<'a>
+where
+    &'a A: Into<View<'a>>,
+{
+    argument.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const INPUT: &str = "# routes\nhttp {\n  server a {\n    listen 80;\n    \"log level\" info;\n  }\n  server b {\n    listen 443 ssl;\n    location /api { proxy_pass \"http://b\"; }\n  }\n}\nuser www;\n";
+
+    #[test]
+    fn test_round_trip() {
+        let unit = parse(INPUT, ConfOptions::default()).unwrap();
+        let arena = Arena::parse(INPUT, ConfOptions::default()).unwrap();
+        let converted = arena.to_unit();
+        assert_eq!(converted.directives, unit.directives);
+        assert_eq!(converted.comments, unit.comments);
+
+        let arena = Arena::from_unit(&unit);
+        assert_eq!(arena.len(), 9);
+        let converted = ConfUnit::from(&arena);
+        assert_eq!(converted.directives, unit.directives);
+        assert_eq!(
+            converted.directives[0].children[1].children[1].name.span,
+            unit.directives[0].children[1].children[1].name.span
+        );
+    }
+
+    #[test]
+    fn test_parse_lays_out_directives_as_packing_does() {
+        let parsed = Arena::parse(INPUT, ConfOptions::default()).unwrap();
+        let packed = Arena::from_unit(&parse(INPUT, ConfOptions::default()).unwrap());
+        assert_eq!(parsed.len(), packed.len());
+        for (a, b) in parsed.iter().zip(packed.iter()) {
+            assert_eq!(a.name(), b.name());
+            assert!(a.arguments().eq(b.arguments()));
+            assert!(a
+                .children()
+                .map(|c| c.id())
+                .eq(b.children().map(|c| c.id())));
+        }
+        assert!(parsed.comments().eq(packed.comments()));
+    }
+
+    #[test]
+    fn test_navigation() {
+        let arena = Arena::parse(INPUT, ConfOptions::default()).unwrap();
+        let roots: Vec<&str> = arena.directives().map(|d| d.name().value).collect();
+        assert_eq!(roots, ["http", "user"]);
+
+        let http = arena.directives().next().unwrap();
+        let servers: Vec<Directive> = http.children_named("server").collect();
+        assert_eq!(servers.len(), 2);
+        let b = servers[1];
+        assert_eq!(b.arguments().next().unwrap().value, "b");
+        let listen = b.child("listen").unwrap();
+        let arguments: Vec<&str> = listen.arguments().map(|a| a.value).collect();
+        assert_eq!(arguments, ["443", "ssl"]);
+
+        // Quoted names are found by their text
+        let level = servers[0].child("log level").unwrap();
+        assert_eq!(level.name().value, "\"log level\"");
+        assert_eq!(level.name().text(), "log level");
+
+        // Names are interned once
+        assert_eq!(arena.directives_named("listen").count(), 2);
+        let symbol = arena.symbol("server").unwrap();
+        assert_eq!(servers[0].symbol(), symbol);
+        assert_eq!(arena.name(symbol), "server");
+        assert!(arena.symbol("missing").is_none());
+
+        let subtree = b.to_directive();
+        let expected = &parse(INPUT, ConfOptions::default()).unwrap().directives[0].children[1];
+        assert_eq!(&subtree, expected);
+        assert_eq!(arena.directive(b.id()).name().value, "server");
+        assert_eq!(arena.comments().next().unwrap().content, "# routes");
+    }
+}
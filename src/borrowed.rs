@@ -0,0 +1,312 @@
+use std::borrow::Cow;
+
+use crate::Span;
+
+/// Represents a configuration argument that borrows its text from the input.
+#[derive(Debug, Clone, Default)]
+pub struct ConfArgument<'a> {
+    /// The value of the argument.
+    pub value: Cow<'a, str>,
+    /// The span of the argument in the source text.
+    pub span: Span,
+    /// Whether the argument is quoted.
+    pub is_quoted: bool,
+    /// Whether the argument is a triple-quoted string.
+    pub is_triple_quoted: bool,
+    /// Whether the argument is a raw string.
+    pub is_raw: bool,
+    /// Whether the argument is a heredoc.
+    pub is_heredoc: bool,
+    /// Whether the argument is an expression.
+    pub is_expression: bool,
+}
+
+/// Represents a configuration directive that borrows its text from the input.
+///
+/// Like [`crate::ConfDirective`], it is dropped and cloned without recursion
+/// and implements `Drop`.
+#[derive(Debug)]
+pub struct ConfDirective<'a> {
+    /// The name of the directive.
+    pub name: ConfArgument<'a>,
+    /// The arguments of the directive.
+    pub arguments: Vec<ConfArgument<'a>>,
+    /// The child directives of this directive.
+    pub children: Vec<ConfDirective<'a>>,
+}
+
+/// Represents a configuration unit that borrows its text from the input.
+///
+/// Produced by [`parse_borrowed`](crate::parse_borrowed), which makes no
+/// per-name or per-argument allocations. Use [`ConfUnit::into_owned`] to
+/// convert it to the owned [`crate::ConfUnit`].
+#[derive(Debug, Clone)]
+pub struct ConfUnit<'a> {
+    /// The root directives of the configuration.
+    pub directives: Vec<ConfDirective<'a>>,
+    /// The comments in the configuration.
+    pub comments: Vec<ConfComment<'a>>,
+}
+
+/// Represents a comment that borrows its text from the input.
+#[derive(Debug, Clone)]
+pub struct ConfComment<'a> {
+    /// The content of the comment.
+    pub content: Cow<'a, str>,
+    /// The span of the comment in the source text.
+    pub span: Span,
+    /// Whether the comment is a multi-line comment.
+    pub is_multi_line: bool,
+}
+
+impl ConfArgument<'_> {
+    /// Converts the argument to its owned form.
+    pub fn into_owned(self) -> crate::ConfArgument {
+        crate::ConfArgument {
+            value: self.value.into_owned(),
+            span: self.span,
+            is_quoted: self.is_quoted,
+            is_triple_quoted: self.is_triple_quoted,
+            is_raw: self.is_raw,
+            is_heredoc: self.is_heredoc,
+            is_expression: self.is_expression,
+        }
+    }
+}
+
+impl ConfDirective<'_> {
+    /// Converts the directive and its children to their owned form.
+    pub fn into_owned(self) -> crate::ConfDirective {
+        map_tree(
+            self,
+            |mut directive| {
+                let name = std::mem::take(&mut directive.name);
+                let arguments = std::mem::take(&mut directive.arguments);
+                let children = std::mem::take(&mut directive.children);
+                ((name, arguments), children)
+            },
+            |(name, arguments), children| crate::ConfDirective {
+                name: name.into_owned(),
+                arguments: arguments
+                    .into_iter()
+                    .map(ConfArgument::into_owned)
+                    .collect(),
+                children,
+            },
+        )
+    }
+}
+
+impl Clone for ConfDirective<'_> {
+    fn clone(&self) -> Self {
+        map_tree(
+            self,
+            |directive| (directive, directive.children.iter().collect()),
+            |directive, children| ConfDirective {
+                name: directive.name.clone(),
+                arguments: directive.arguments.clone(),
+                children,
+            },
+        )
+    }
+}
+
+impl Drop for ConfDirective<'_> {
+    fn drop(&mut self) {
+        // Tear deep trees down iteratively so dropping cannot overflow the stack
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut child) = stack.pop() {
+            stack.append(&mut child.children);
+        }
+    }
+}
+
+impl ConfComment<'_> {
+    /// Converts the comment to its owned form.
+    pub fn into_owned(self) -> crate::ConfComment {
+        crate::ConfComment {
+            content: self.content.into_owned(),
+            span: self.span,
+            is_multi_line: self.is_multi_line,
+        }
+    }
+}
+
+impl ConfUnit<'_> {
+    /// Converts the unit to its owned form.
+    pub fn into_owned(self) -> crate::ConfUnit {
+        crate::ConfUnit {
+            directives: self
+                .directives
+                .into_iter()
+                .map(ConfDirective::into_owned)
+                .collect(),
+            comments: self
+                .comments
+                .into_iter()
+                .map(ConfComment::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl<'a> From<ConfUnit<'a>> for crate::ConfUnit {
+    fn from(unit: ConfUnit<'a>) -> Self {
+        unit.into_owned()
+    }
+}
+
+impl<'a> From<ConfDirective<'a>> for crate::ConfDirective {
+    fn from(directive: ConfDirective<'a>) -> Self {
+        directive.into_owned()
+    }
+}
+
+impl<'a> From<ConfArgument<'a>> for crate::ConfArgument {
+    fn from(argument: ConfArgument<'a>) -> Self {
+        argument.into_owned()
+    }
+}
+
+impl crate::ConfArgument {
+    /// Returns a borrowed view of the argument.
+    pub fn as_borrowed(&self) -> ConfArgument<'_> {
+        ConfArgument {
+            value: Cow::Borrowed(&self.value),
+            span: self.span,
+            is_quoted: self.is_quoted,
+            is_triple_quoted: self.is_triple_quoted,
+            is_raw: self.is_raw,
+            is_heredoc: self.is_heredoc,
+            is_expression: self.is_expression,
+        }
+    }
+}
+
+impl crate::ConfDirective {
+    /// Returns a borrowed view of the directive and its children.
+    pub fn as_borrowed(&self) -> ConfDirective<'_> {
+        map_tree(
+            self,
+            |directive| (directive, directive.children.iter().collect()),
+            |directive, children| ConfDirective {
+                name: directive.name.as_borrowed(),
+                arguments: directive
+                    .arguments
+                    .iter()
+                    .map(crate::ConfArgument::as_borrowed)
+                    .collect(),
+                children,
+            },
+        )
+    }
+}
+
+impl crate::ConfUnit {
+    /// Returns a borrowed view of the unit.
+    pub fn as_borrowed(&self) -> ConfUnit<'_> {
+        ConfUnit {
+            directives: self
+                .directives
+                .iter()
+                .map(crate::ConfDirective::as_borrowed)
+                .collect(),
+            comments: self
+                .comments
+                .iter()
+                .map(|comment| ConfComment {
+                    content: Cow::Borrowed(&comment.content),
+                    span: comment.span,
+                    is_multi_line: comment.is_multi_line,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Converts a tree bottom-up with an explicit stack rather than recursion.
+///
+/// `split` separates a node into the data needed to build it and its
+/// children; `build` creates the converted node once its children are done.
+pub(crate) fn map_tree<S, H, T>(
+    root: S,
+    mut split: impl FnMut(S) -> (H, Vec<S>),
+    mut build: impl FnMut(H, Vec<T>) -> T,
+) -> T {
+    // Each frame holds a node's data, its unconverted children in reverse
+    // order, and its converted children
+    let (head, mut pending) = split(root);
+    pending.reverse();
+    let mut stack = vec![(head, pending, Vec::new())];
+
+    loop {
+        let frame = stack.last_mut().expect("the stack holds the root");
+        if let Some(child) = frame.1.pop() {
+            let (head, mut pending) = split(child);
+            pending.reverse();
+            stack.push((head, pending, Vec::new()));
+            continue;
+        }
+
+        let (head, _, children) = stack.pop().expect("the stack holds the root");
+        let node = build(head, children);
+        match stack.last_mut() {
+            Some(parent) => parent.2.push(node),
+            None => return node,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, parse_borrowed, ConfOptions};
+
+    #[test]
+    fn test_parse_borrowed_does_not_copy_text() {
+        let input = "# comment\nserver \"example.com\" {\n  listen 80;\n}";
+        let unit = parse_borrowed(input, ConfOptions::default()).unwrap();
+
+        let server = &unit.directives[0];
+        assert!(matches!(server.name.value, Cow::Borrowed("server")));
+        assert!(matches!(
+            server.arguments[0].value,
+            Cow::Borrowed("\"example.com\"")
+        ));
+        assert!(matches!(
+            server.children[0].arguments[0].value,
+            Cow::Borrowed("80")
+        ));
+        assert!(matches!(
+            unit.comments[0].content,
+            Cow::Borrowed("# comment")
+        ));
+
+        // The borrowed text points into the input
+        let name: &str = &server.name.value;
+        assert_eq!(name.as_ptr(), input[server.name.span.range()].as_ptr());
+    }
+
+    #[test]
+    fn test_into_owned_matches_owned_parse() {
+        let input = "a 1 \"two\";\nb {\n  c { d; }\n}\n# trailing";
+        let borrowed = parse_borrowed(input, ConfOptions::default()).unwrap();
+        let owned = parse(input, ConfOptions::default()).unwrap();
+
+        let converted = borrowed.into_owned();
+        assert_eq!(converted.directives, owned.directives);
+        assert_eq!(
+            converted.directives[1].name.span,
+            owned.directives[1].name.span
+        );
+        assert_eq!(converted.comments.len(), owned.comments.len());
+        assert_eq!(converted.comments[0].content, owned.comments[0].content);
+    }
+
+    #[test]
+    fn test_as_borrowed_round_trip() {
+        let owned = parse("a 1 { b 2; }", ConfOptions::default()).unwrap();
+        let round_trip = owned.as_borrowed().into_owned();
+        assert_eq!(round_trip.directives, owned.directives);
+    }
+}
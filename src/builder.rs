@@ -0,0 +1,256 @@
+//! Building directive trees in code.
+//!
+//! [`Directive`] is a fluent builder for [`ConfDirective`]. Arguments are
+//! given as the text they stand for and are quoted only when the text could
+//! not be written as a bare argument, so the built tree serializes to a file
+//! that parses back to the same values.
+//!
+//! # Examples
+//!
+//! ```
+//! use confetti_rs::builder::Directive;
+//!
+//! let server = Directive::new("server")
+//!     .child(Directive::new("listen").arg("80"))
+//!     .child(Directive::new("server_name").arg("example.com").arg("www.example.com"))
+//!     .child(Directive::new("root").arg("/var/www/my site"))
+//!     .build();
+//!
+//! assert_eq!(server.children[1].arguments.len(), 2);
+//! assert_eq!(server.children[2].arguments[0].value, "\"/var/www/my site\"");
+//! assert_eq!(server.children[2].arguments[0].text(), "/var/www/my site");
+//! ```
+
+use crate::{ConfArgument, ConfDirective, ConfUnit, Span};
+
+/// A builder for a [`ConfDirective`].
+#[derive(Debug, Clone)]
+pub struct Directive {
+    name: ConfArgument,
+    arguments: Vec<ConfArgument>,
+    children: Vec<ConfDirective>,
+}
+
+impl Directive {
+    /// Starts a directive with a name, quoted if it needs to be.
+    pub fn new(name: impl AsRef<str>) -> Self {
+        Self::from_name(argument(name))
+    }
+
+    /// Starts a directive with a name built as an argument.
+    pub fn from_name(name: ConfArgument) -> Self {
+        Self {
+            name,
+            arguments: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Adds an argument standing for `text`, quoted if it needs to be.
+    pub fn arg(self, text: impl AsRef<str>) -> Self {
+        self.argument(argument(text))
+    }
+
+    /// Adds an argument for each item, quoted where needed.
+    pub fn args<I>(mut self, texts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.arguments.extend(texts.into_iter().map(argument));
+        self
+    }
+
+    /// Adds a quoted argument standing for `text`.
+    pub fn quoted(self, text: impl AsRef<str>) -> Self {
+        self.argument(quoted(text))
+    }
+
+    /// Adds an argument built elsewhere.
+    pub fn argument(mut self, argument: ConfArgument) -> Self {
+        self.arguments.push(argument);
+        self
+    }
+
+    /// Adds a child directive, giving the directive a block.
+    pub fn child(mut self, child: impl Into<ConfDirective>) -> Self {
+        self.children.push(child.into());
+        self
+    }
+
+    /// Adds child directives.
+    pub fn children<I>(mut self, children: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<ConfDirective>,
+    {
+        self.children.extend(children.into_iter().map(Into::into));
+        self
+    }
+
+    /// Returns the built directive.
+    pub fn build(self) -> ConfDirective {
+        ConfDirective {
+            name: self.name,
+            arguments: self.arguments,
+            children: self.children,
+        }
+    }
+}
+
+impl From<Directive> for ConfDirective {
+    fn from(directive: Directive) -> Self {
+        directive.build()
+    }
+}
+
+/// Returns a unit holding `directives` and no comments.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::builder::{unit, Directive};
+///
+/// let unit = unit([Directive::new("user").arg("www"), Directive::new("worker_processes").arg("4")]);
+/// assert_eq!(unit.directives.len(), 2);
+/// ```
+pub fn unit<I>(directives: I) -> ConfUnit
+where
+    I: IntoIterator,
+    I::Item: Into<ConfDirective>,
+{
+    ConfUnit {
+        directives: directives.into_iter().map(Into::into).collect(),
+        comments: Vec::new(),
+    }
+}
+
+/// Returns an argument standing for `text`, quoted only if it cannot be
+/// written bare.
+///
+/// Text that is empty, or holds whitespace, reserved characters or the start
+/// of a `#`, `//` or `/*` comment, is quoted. Text spanning several lines is triple-quoted.
+pub fn argument(text: impl AsRef<str>) -> ConfArgument {
+    let text = text.as_ref();
+    if needs_quotes(text) {
+        quoted(text)
+    } else {
+        new_argument(text.to_string(), false, false)
+    }
+}
+
+/// Returns a quoted argument standing for `text`, escaping quotes and
+/// backslashes. Text spanning several lines is triple-quoted.
+pub fn quoted(text: impl AsRef<str>) -> ConfArgument {
+    let text = text.as_ref();
+    let triple = text.contains(is_line_terminator);
+    let delimiter = if triple { "\"\"\"" } else { "\"" };
+
+    let mut value = String::with_capacity(text.len() + 8);
+    value.push_str(delimiter);
+    for c in text.chars() {
+        if c == '"' || c == '\\' {
+            value.push('\\');
+        }
+        value.push(c);
+    }
+    value.push_str(delimiter);
+    new_argument(value, true, triple)
+}
+
+fn new_argument(value: String, is_quoted: bool, is_triple_quoted: bool) -> ConfArgument {
+    ConfArgument {
+        value,
+        span: Span::default(),
+        is_quoted,
+        is_triple_quoted,
+        is_raw: false,
+        is_heredoc: false,
+        is_expression: false,
+    }
+}
+
+/// Returns whether text must be quoted to be read back as one argument,
+/// with any of the parser's extensions and the default or C-style comments.
+fn needs_quotes(text: &str) -> bool {
+    text.is_empty()
+        || text.starts_with("<<")
+        || text.contains("//")
+        || text.contains("/*")
+        || text.chars().any(|c| {
+            c.is_whitespace()
+                || c.is_control()
+                || matches!(c, '"' | '{' | '}' | ';' | '#' | '(' | '\\')
+        })
+}
+
+pub(crate) fn is_line_terminator(c: char) -> bool {
+    matches!(
+        c,
+        '\n' | '\r' | '\u{000B}' | '\u{000C}' | '\u{0085}' | '\u{2028}' | '\u{2029}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, ConfOptions};
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(argument("80").value, "80");
+        assert!(!argument("/etc/nginx").is_quoted);
+        assert_eq!(argument("").value, "\"\"");
+        assert_eq!(argument("a b").value, "\"a b\"");
+        assert_eq!(argument("say \"hi\"").value, r#""say \"hi\"""#);
+        assert_eq!(argument("C:\\temp").value, r#""C:\\temp""#);
+        assert_eq!(argument("a;b").value, "\"a;b\"");
+        assert_eq!(argument("f(x)").value, "\"f(x)\"");
+
+        let multi_line = argument("one\ntwo");
+        assert!(multi_line.is_triple_quoted);
+        assert_eq!(multi_line.value, "\"\"\"one\ntwo\"\"\"");
+    }
+
+    #[test]
+    fn test_built_tree_round_trips() {
+        let texts = [
+            "plain",
+            "two words",
+            "quote \" and \\",
+            "line\nbreak",
+            "{}",
+            "#x",
+        ];
+        let built = unit([
+            Directive::new("values").args(texts),
+            Directive::new("block name")
+                .quoted("q")
+                .children([Directive::new("inner").arg("1")]),
+        ]);
+
+        let mut source = String::new();
+        for directive in &built.directives {
+            source.push_str(&directive.name.value);
+            for argument in &directive.arguments {
+                source.push(' ');
+                source.push_str(&argument.value);
+            }
+            if directive.children.is_empty() {
+                source.push_str(";\n");
+            } else {
+                source.push_str(" {\n  inner 1;\n}\n");
+            }
+        }
+
+        let parsed = parse(&source, ConfOptions::all_extensions()).unwrap();
+        assert_eq!(parsed.directives, built.directives);
+        let values: Vec<String> = parsed.directives[0]
+            .arguments
+            .iter()
+            .map(ConfArgument::text)
+            .collect();
+        assert_eq!(values, texts);
+        assert_eq!(parsed.directives[1].name.text(), "block name");
+    }
+}
@@ -0,0 +1,551 @@
+//! A binary encoding of parsed units, for loading large files without
+//! parsing them again.
+//!
+//! [`ConfUnit::to_bytes`] encodes a unit, spans and comments included, along
+//! with a checksum of the source text it was parsed from.
+//! [`ConfUnit::from_bytes`] decodes it only if the source still has that
+//! checksum, so a cache cannot outlive the text it stands for. [`load`] does
+//! both for a file, keeping its cache in a directory of the caller's choice.
+//!
+//! The encoding starts with [`MAGIC`], [`VERSION`] and a checksum of the
+//! crate version. Caches of any other version, or written by another release
+//! of the crate, are rejected rather than read, and are rebuilt by [`load`].
+//!
+//! # Examples
+//!
+//! ```
+//! use confetti_rs::{parse, ConfOptions, ConfUnit};
+//!
+//! let source = "# main\nserver {\n  listen 80;\n}\n";
+//! let unit = parse(source, ConfOptions::default()).unwrap();
+//! let bytes = unit.to_bytes(source);
+//!
+//! let cached = ConfUnit::from_bytes(&bytes, source).unwrap();
+//! assert_eq!(cached.directives, unit.directives);
+//! assert_eq!(cached.comments, unit.comments);
+//!
+//! // A changed source invalidates the cache
+//! assert!(ConfUnit::from_bytes(&bytes, "server {}\n").is_err());
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::source_map::FileId;
+use crate::{
+    parse, ConfArgument, ConfComment, ConfDirective, ConfError, ConfOptions, ConfUnit, Span,
+};
+
+/// The bytes every encoded unit starts with.
+pub const MAGIC: [u8; 8] = *b"confetti";
+
+/// The version of the encoding, raised whenever its layout changes.
+pub const VERSION: u32 = 1;
+
+/// The release of the crate that writes the encoding.
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The length of the magic, version, crate version, checksum and source
+/// length.
+const HEADER_LEN: usize = 8 + 4 + 8 + 8 + 8;
+
+/// Error type for decoding a cached unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheError {
+    /// The bytes are not an encoded unit
+    NotACache,
+    /// The unit was encoded with another version of the encoding
+    UnsupportedVersion(u32),
+    /// The unit was encoded by another release of the crate
+    OtherCrateVersion,
+    /// The source text is not the one the unit was parsed from
+    SourceChanged,
+    /// The encoding is truncated or malformed
+    Malformed,
+}
+
+impl Error for CacheError {}
+
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheError::NotACache => write!(f, "Not a cached configuration"),
+            CacheError::UnsupportedVersion(version) => write!(
+                f,
+                "Cache version {} is not supported, expected {}",
+                version, VERSION
+            ),
+            CacheError::OtherCrateVersion => write!(
+                f,
+                "The cache was written by another release than {}",
+                CRATE_VERSION
+            ),
+            CacheError::SourceChanged => write!(f, "The source changed since it was cached"),
+            CacheError::Malformed => write!(f, "The cache is truncated or malformed"),
+        }
+    }
+}
+
+/// Returns a checksum of a text, as stored in the encoding.
+///
+/// It detects changes to the text, not tampering: it is not cryptographic.
+pub fn checksum(text: &str) -> u64 {
+    const K: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+    let mut chunks = text.as_bytes().chunks_exact(8);
+    let mut hash = text.len() as u64;
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes"));
+        hash = (hash.rotate_left(5) ^ word).wrapping_mul(K);
+    }
+    for &byte in chunks.remainder() {
+        hash = (hash.rotate_left(5) ^ u64::from(byte)).wrapping_mul(K);
+    }
+    // Spread the last words over every bit
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^ (hash >> 33)
+}
+
+impl ConfUnit {
+    /// Encodes the unit, tied to the source text it was parsed from.
+    pub fn to_bytes(&self, source: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + source.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(CRATE_VERSION).to_le_bytes());
+        bytes.extend_from_slice(&checksum(source).to_le_bytes());
+        bytes.extend_from_slice(&(source.len() as u64).to_le_bytes());
+
+        let mut writer = Writer { bytes };
+        writer.count(self.comments.len());
+        for comment in &self.comments {
+            writer.string(&comment.content);
+            writer.span(comment.span);
+            writer.bytes.push(u8::from(comment.is_multi_line));
+        }
+
+        // Directives are written in preorder, each followed by the number
+        // of its children
+        writer.count(self.directives.len());
+        let mut stack: Vec<&ConfDirective> = self.directives.iter().rev().collect();
+        while let Some(directive) = stack.pop() {
+            writer.argument(&directive.name);
+            writer.count(directive.arguments.len());
+            for argument in &directive.arguments {
+                writer.argument(argument);
+            }
+            writer.count(directive.children.len());
+            stack.extend(directive.children.iter().rev());
+        }
+        writer.bytes
+    }
+
+    /// Decodes a unit encoded by [`ConfUnit::to_bytes`] from `source`.
+    ///
+    /// Fails with [`CacheError::SourceChanged`] if `source` is not the text
+    /// the unit was encoded with.
+    pub fn from_bytes(bytes: &[u8], source: &str) -> Result<ConfUnit, CacheError> {
+        if bytes.len() < HEADER_LEN || bytes[..8] != MAGIC {
+            return Err(CacheError::NotACache);
+        }
+        let mut reader = Reader { bytes, position: 8 };
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(CacheError::UnsupportedVersion(version));
+        }
+        if reader.u64()? != checksum(CRATE_VERSION) {
+            return Err(CacheError::OtherCrateVersion);
+        }
+        let expected = reader.u64()?;
+        let length = reader.u64()?;
+        // The length is compared first, as it is free to check
+        if length != source.len() as u64 || expected != checksum(source) {
+            return Err(CacheError::SourceChanged);
+        }
+
+        let count = reader.count()?;
+        let mut comments = Vec::with_capacity(reader.capacity(count));
+        for _ in 0..count {
+            comments.push(ConfComment {
+                content: reader.string()?,
+                span: reader.span()?,
+                is_multi_line: reader.flag()?,
+            });
+        }
+
+        // Each frame holds a directive whose children are still being read
+        struct Frame {
+            name: ConfArgument,
+            arguments: Vec<ConfArgument>,
+            remaining: usize,
+            children: Vec<ConfDirective>,
+        }
+        let mut remaining = reader.count()?;
+        let mut directives = Vec::with_capacity(reader.capacity(remaining));
+        let mut stack: Vec<Frame> = Vec::new();
+        loop {
+            let pending = match stack.last() {
+                Some(frame) => frame.remaining,
+                None => remaining,
+            };
+            if pending == 0 {
+                let Some(frame) = stack.pop() else { break };
+                let directive = ConfDirective {
+                    name: frame.name,
+                    arguments: frame.arguments,
+                    children: frame.children,
+                };
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(directive),
+                    None => directives.push(directive),
+                }
+                continue;
+            }
+            match stack.last_mut() {
+                Some(frame) => frame.remaining -= 1,
+                None => remaining -= 1,
+            }
+
+            let name = reader.argument()?;
+            let count = reader.count()?;
+            let mut arguments = Vec::with_capacity(reader.capacity(count));
+            for _ in 0..count {
+                arguments.push(reader.argument()?);
+            }
+            let count = reader.count()?;
+            stack.push(Frame {
+                name,
+                arguments,
+                remaining: count,
+                children: Vec::with_capacity(reader.capacity(count)),
+            });
+        }
+
+        if reader.position != bytes.len() {
+            return Err(CacheError::Malformed);
+        }
+        Ok(ConfUnit {
+            directives,
+            comments,
+        })
+    }
+}
+
+/// Parses a file, or decodes it from its cache in `cache_dir` if the file
+/// has not changed since the cache was written.
+///
+/// The file is read and decoded like [`crate::parse_bytes`]. The cache is
+/// named after the file and keyed by its path and `options`, and it is
+/// rewritten whenever the file changes, so a stale cache is never returned.
+/// Caching is best effort: a cache that cannot be read or written is
+/// ignored, and only errors reading or parsing the file itself are returned.
+pub fn load(
+    path: impl AsRef<Path>,
+    cache_dir: impl AsRef<Path>,
+    options: ConfOptions,
+) -> Result<ConfUnit, ConfError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path)?;
+    let source = crate::encoding::decode(&bytes)?;
+
+    let cache = cache_path(path, cache_dir.as_ref(), &options);
+    if let Ok(cached) = fs::read(&cache) {
+        if let Ok(unit) = ConfUnit::from_bytes(&cached, &source) {
+            return Ok(unit);
+        }
+    }
+
+    let unit = parse(&source, options)?;
+    // Write the cache under another name first, so that a reader never sees
+    // it half written
+    let partial = cache.with_extension("partial");
+    let written = fs::create_dir_all(cache_dir.as_ref())
+        .and_then(|_| fs::write(&partial, unit.to_bytes(&source)))
+        .and_then(|_| fs::rename(&partial, &cache));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    Ok(unit)
+}
+
+/// Returns where [`load`] keeps the cache of a file parsed with `options`
+/// by this release of the crate.
+pub fn cache_path(path: &Path, cache_dir: &Path, options: &ConfOptions) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let key = checksum(&format!(
+        "{}\n{}\n{:?}",
+        CRATE_VERSION,
+        path.display(),
+        options
+    ));
+    cache_dir.join(format!("{}.{:016x}.cache", name, key))
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    /// Writes a number in LEB128, seven bits to a byte.
+    fn count(&mut self, mut value: usize) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.count(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// Writes a span as its file, start and length.
+    fn span(&mut self, span: Span) {
+        self.count(span.file().index());
+        self.count(span.start());
+        self.count(span.len());
+    }
+
+    fn argument(&mut self, argument: &ConfArgument) {
+        let flags = [
+            argument.is_quoted,
+            argument.is_triple_quoted,
+            argument.is_raw,
+            argument.is_heredoc,
+            argument.is_expression,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0u8, |flags, (bit, &set)| flags | (u8::from(set) << bit));
+        self.bytes.push(flags);
+        self.string(&argument.value);
+        self.span(argument.span);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CacheError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or(CacheError::Malformed)?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, CacheError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().expect("took 4 bytes")))
+    }
+
+    fn u64(&mut self) -> Result<u64, CacheError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("took 8 bytes")))
+    }
+
+    fn count(&mut self) -> Result<usize, CacheError> {
+        let mut value: u64 = 0;
+        for shift in (0..u64::BITS).step_by(7) {
+            let byte = self.take(1)?[0];
+            let bits = u64::from(byte & 0x7f);
+            if bits << shift >> shift != bits {
+                return Err(CacheError::Malformed);
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(value).map_err(|_| CacheError::Malformed);
+            }
+        }
+        Err(CacheError::Malformed)
+    }
+
+    /// Returns how many items to reserve room for, which a malformed count
+    /// cannot push past the number of bytes left.
+    fn capacity(&self, count: usize) -> usize {
+        count.min(self.bytes.len() - self.position)
+    }
+
+    fn flag(&mut self) -> Result<bool, CacheError> {
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(CacheError::Malformed),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, CacheError> {
+        let len = self.count()?;
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .map_err(|_| CacheError::Malformed)
+    }
+
+    fn span(&mut self) -> Result<Span, CacheError> {
+        let file = u32::try_from(self.count()?).map_err(|_| CacheError::Malformed)?;
+        let file = FileId::new(file as usize);
+        let start = self.count()?;
+        let end = start
+            .checked_add(self.count()?)
+            .ok_or(CacheError::Malformed)?;
+        Ok(Span::new(file, start..end))
+    }
+
+    fn argument(&mut self) -> Result<ConfArgument, CacheError> {
+        let flags = self.take(1)?[0];
+        if flags >> 5 != 0 {
+            return Err(CacheError::Malformed);
+        }
+        Ok(ConfArgument {
+            value: self.string()?,
+            span: self.span()?,
+            is_quoted: flags & 1 != 0,
+            is_triple_quoted: flags & (1 << 1) != 0,
+            is_raw: flags & (1 << 2) != 0,
+            is_heredoc: flags & (1 << 3) != 0,
+            is_expression: flags & (1 << 4) != 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "# head\nserver \"main site\" {\n  listen 80 default;\n  /* inner */\n  path r#\"C:\\x\"#;\n  script <<EOF\n    echo hi\n    EOF\n}\nmessage \"\"\"one\ntwo\"\"\";\n// tail\n";
+
+    #[test]
+    fn test_round_trip() {
+        let unit = parse(INPUT, ConfOptions::all_extensions()).unwrap();
+        let bytes = unit.to_bytes(INPUT);
+        let cached = ConfUnit::from_bytes(&bytes, INPUT).unwrap();
+        assert_eq!(cached.directives, unit.directives);
+        assert_eq!(cached.comments, unit.comments);
+
+        // Spans are kept, which equality ignores
+        let spans = |unit: &ConfUnit| {
+            let server = &unit.directives[0];
+            [
+                server.name.span,
+                server.arguments[0].span,
+                server.children[2].arguments[0].span,
+                unit.directives[1].arguments[0].span,
+            ]
+        };
+        assert_eq!(spans(&cached), spans(&unit));
+
+        // Spans in other files keep their file
+        let mut unit = unit;
+        unit.directives[1].name.span = unit.directives[1].name.span.in_file(FileId::new(3));
+        let cached = ConfUnit::from_bytes(&unit.to_bytes(INPUT), INPUT).unwrap();
+        assert_eq!(cached.directives[1].name.span.file(), FileId::new(3));
+
+        let empty = ConfUnit {
+            directives: Vec::new(),
+            comments: Vec::new(),
+        };
+        let empty = ConfUnit::from_bytes(&empty.to_bytes(""), "").unwrap();
+        assert!(empty.directives.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_other_sources_and_bad_bytes() {
+        let unit = parse(INPUT, ConfOptions::all_extensions()).unwrap();
+        let bytes = unit.to_bytes(INPUT);
+
+        let edited = INPUT.replace("80", "81");
+        assert_eq!(
+            ConfUnit::from_bytes(&bytes, &edited).unwrap_err(),
+            CacheError::SourceChanged
+        );
+        assert_eq!(
+            ConfUnit::from_bytes(b"server {}", INPUT).unwrap_err(),
+            CacheError::NotACache
+        );
+
+        let mut newer = bytes.clone();
+        newer[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            ConfUnit::from_bytes(&newer, INPUT).unwrap_err(),
+            CacheError::UnsupportedVersion(VERSION + 1)
+        );
+        let mut other_release = bytes.clone();
+        other_release[12..20].copy_from_slice(&checksum("0.0.0").to_le_bytes());
+        assert_eq!(
+            ConfUnit::from_bytes(&other_release, INPUT).unwrap_err(),
+            CacheError::OtherCrateVersion
+        );
+
+        // Truncated or damaged caches are errors, never panics
+        for len in 0..bytes.len() {
+            assert!(ConfUnit::from_bytes(&bytes[..len], INPUT).is_err());
+        }
+        for index in HEADER_LEN..bytes.len() {
+            let mut damaged = bytes.clone();
+            damaged[index] ^= 0xff;
+            let _ = ConfUnit::from_bytes(&damaged, INPUT);
+        }
+
+        // A file index no span can hold, in place of the first comment's
+        let file = HEADER_LEN + 2 + unit.comments[0].content.len();
+        assert_eq!(bytes[file], 0);
+        let mut damaged = bytes[..file].to_vec();
+        damaged.extend_from_slice(&[0x80, 0x80, 0x80, 0x80, 0x10]);
+        damaged.extend_from_slice(&bytes[file + 1..]);
+        assert_eq!(
+            ConfUnit::from_bytes(&damaged, INPUT).unwrap_err(),
+            CacheError::Malformed
+        );
+    }
+
+    #[test]
+    fn test_load_invalidates_changed_files() {
+        let dir = std::env::temp_dir().join(format!("confetti-cache-{}", std::process::id()));
+        let path = dir.join("app.conf");
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&dir).unwrap();
+        let options = ConfOptions::default();
+        let cache = cache_path(&path, &cache_dir, &options);
+
+        fs::write(&path, "listen 80;\n").unwrap();
+        let unit = load(&path, &cache_dir, options.clone()).unwrap();
+        assert_eq!(unit.directives[0].arguments[0].value, "80");
+        let written = fs::read(&cache).unwrap();
+        let cached = load(&path, &cache_dir, options.clone()).unwrap();
+        assert_eq!(cached.directives, unit.directives);
+
+        // The cache is used while the file is unchanged
+        let mut planted = parse("listen 1;\n", options.clone()).unwrap();
+        planted.directives[0].arguments[0].value = "cached".to_string();
+        fs::write(&cache, planted.to_bytes("listen 80;\n")).unwrap();
+        let unit = load(&path, &cache_dir, options.clone()).unwrap();
+        assert_eq!(unit.directives[0].arguments[0].value, "cached");
+
+        // and rebuilt once it changes
+        fs::write(&path, "listen 443;\n").unwrap();
+        let unit = load(&path, &cache_dir, options.clone()).unwrap();
+        assert_eq!(unit.directives[0].arguments[0].value, "443");
+        assert_ne!(fs::read(&cache).unwrap(), written);
+
+        // Other options use another cache
+        assert_ne!(
+            cache_path(&path, &cache_dir, &ConfOptions::all_extensions()),
+            cache
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,343 @@
+//! Support for the conformance suite of the reference implementation.
+//!
+//! The suite is a directory of `.conf` inputs. Each input has a sibling
+//! `.pass` file holding the expected output in the reference format, or a
+//! `.fail` file when the input must be rejected. An input may also have
+//! `.ext_<name>` marker files naming the extensions it needs.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{parse, ConfArgument, ConfDirective, ConfOptions, ConfUnit};
+
+/// Formats a configuration unit in the reference format.
+///
+/// Every directive is printed on its own line as its arguments wrapped in
+/// `<` and `>`, with quotes removed and escapes processed. The children of a
+/// block are enclosed in `[` and `]` and indented by four spaces. Comments
+/// are not printed.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::conformance::to_reference_format;
+/// use confetti_rs::{parse, ConfOptions};
+///
+/// let unit = parse("server \"my host\" {\n  listen 80;\n}", ConfOptions::default()).unwrap();
+/// assert_eq!(
+///     to_reference_format(&unit),
+///     "<server> <my host> [\n    <listen> <80>\n]\n"
+/// );
+/// ```
+pub fn to_reference_format(unit: &ConfUnit) -> String {
+    let mut output = String::new();
+    for directive in &unit.directives {
+        write_directive(&mut output, directive, 0);
+    }
+    output
+}
+
+fn write_directive(output: &mut String, directive: &ConfDirective, depth: usize) {
+    for _ in 0..depth {
+        output.push_str("    ");
+    }
+    write_argument(output, &directive.name);
+    for argument in &directive.arguments {
+        output.push(' ');
+        write_argument(output, argument);
+    }
+    if directive.children.is_empty() {
+        output.push('\n');
+        return;
+    }
+
+    output.push_str(" [\n");
+    for child in &directive.children {
+        write_directive(output, child, depth + 1);
+    }
+    for _ in 0..depth {
+        output.push_str("    ");
+    }
+    output.push_str("]\n");
+}
+
+fn write_argument(output: &mut String, argument: &ConfArgument) {
+    output.push('<');
+    output.push_str(&argument.text());
+    output.push('>');
+}
+
+/// The outcome of a single conformance case.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The input behaved as expected.
+    Passed,
+    /// The input did not behave as expected; the message explains how.
+    Failed(String),
+    /// The case was not run; the message explains why.
+    Skipped(String),
+}
+
+/// The result of running a single conformance case.
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    /// The name of the case, which is the file stem of its `.conf` input.
+    pub name: String,
+    /// The outcome of the case.
+    pub outcome: Outcome,
+}
+
+/// The results of running a conformance suite.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The results of every case, sorted by name.
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    /// Returns the number of cases that passed.
+    pub fn passed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Passed))
+    }
+
+    /// Returns the number of cases that failed.
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    }
+
+    /// Returns the number of cases that were skipped.
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Skipped(_)))
+    }
+
+    /// Returns whether no case failed.
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+
+    fn count(&self, predicate: impl Fn(&Outcome) -> bool) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| predicate(&case.outcome))
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for case in &self.cases {
+            match &case.outcome {
+                Outcome::Passed => {}
+                Outcome::Failed(message) => writeln!(f, "FAIL {}: {}", case.name, message)?,
+                Outcome::Skipped(reason) => writeln!(f, "SKIP {}: {}", case.name, reason)?,
+            }
+        }
+        write!(
+            f,
+            "{} passed, {} failed, {} skipped",
+            self.passed(),
+            self.failed(),
+            self.skipped()
+        )
+    }
+}
+
+/// Runs every case in a conformance suite directory.
+///
+/// `options` are the base options for every case. The `c_style_comments` and
+/// `expression_arguments` extension markers enable the matching options; a
+/// case that needs any other extension is skipped, as is a `.conf` file with
+/// neither a `.pass` nor a `.fail` file.
+///
+/// # Examples
+///
+/// ```no_run
+/// use confetti_rs::conformance::run_suite;
+/// use confetti_rs::ConfOptions;
+///
+/// let report = run_suite("confetti/tests/conformance", &ConfOptions::spec_strict()).unwrap();
+/// println!("{}", report);
+/// assert!(report.is_success());
+/// ```
+pub fn run_suite<P: AsRef<Path>>(dir: P, options: &ConfOptions) -> io::Result<Report> {
+    let mut inputs: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "conf")
+        {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+
+    let mut report = Report::default();
+    for input in inputs {
+        let name = input
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let outcome = run_case(&input, options)?;
+        report.cases.push(CaseResult { name, outcome });
+    }
+    Ok(report)
+}
+
+/// Runs a single conformance case given the path of its `.conf` input.
+pub fn run_case<P: AsRef<Path>>(input: P, options: &ConfOptions) -> io::Result<Outcome> {
+    let input = input.as_ref();
+    let mut options = options.clone();
+    if let Some(outcome) = apply_extensions(input, &mut options)? {
+        return Ok(outcome);
+    }
+
+    let pass = input.with_extension("pass");
+    let fail = input.with_extension("fail");
+    let expected = if pass.exists() {
+        Some(fs::read_to_string(pass)?)
+    } else if fail.exists() {
+        None
+    } else {
+        return Ok(Outcome::Skipped("no .pass or .fail file".to_string()));
+    };
+
+    // Inputs are decoded like `parse_bytes` does, and invalid text is an
+    // error case in the suite, not an I/O error
+    let bytes = fs::read(input)?;
+    let result = crate::encoding::decode(&bytes)
+        .and_then(|text| parse(&text, options))
+        .map_err(|error| error.to_string());
+
+    Ok(match (expected, result) {
+        (Some(expected), Ok(unit)) => {
+            let actual = to_reference_format(&unit);
+            if actual == expected {
+                Outcome::Passed
+            } else {
+                Outcome::Failed(format!("expected {:?}, got {:?}", expected, actual))
+            }
+        }
+        (Some(_), Err(error)) => Outcome::Failed(format!("unexpected error: {}", error)),
+        (None, Ok(unit)) => Outcome::Failed(format!(
+            "expected an error, got {:?}",
+            to_reference_format(&unit)
+        )),
+        (None, Err(_)) => Outcome::Passed,
+    })
+}
+
+/// Enables the extensions named by the case's marker files, returning an
+/// outcome if the case needs an extension this crate does not support.
+fn apply_extensions(input: &Path, options: &mut ConfOptions) -> io::Result<Option<Outcome>> {
+    let (Some(dir), Some(stem)) = (input.parent(), input.file_stem()) else {
+        return Ok(None);
+    };
+    let prefix = format!("{}.ext_", stem.to_string_lossy());
+
+    for entry in fs::read_dir(dir)? {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let Some(extension) = file_name.strip_prefix(&prefix) else {
+            continue;
+        };
+        match extension {
+            "c_style_comments" => options.allow_c_style_comments = true,
+            "expression_arguments" => options.allow_expression_arguments = true,
+            other => {
+                return Ok(Some(Outcome::Skipped(format!(
+                    "unsupported extension {}",
+                    other
+                ))))
+            }
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(input: &str) -> String {
+        to_reference_format(&parse(input, ConfOptions::default()).unwrap())
+    }
+
+    #[test]
+    fn test_reference_format() {
+        assert_eq!(format(""), "");
+        assert_eq!(format("a b c"), "<a> <b> <c>\n");
+        assert_eq!(
+            format("a {\n  b {\n    c;\n  }\n  d\n}\ne"),
+            "<a> [\n    <b> [\n        <c>\n    ]\n    <d>\n]\n<e>\n"
+        );
+        // An empty block prints like a simple directive
+        assert_eq!(format("a {}"), "<a>\n");
+    }
+
+    #[test]
+    fn test_reference_format_values() {
+        assert_eq!(format("a \"\" \"x y\""), "<a> <> <x y>\n");
+        assert_eq!(format("a \\\"b\\\\ c\\;"), "<a> <\"b\\> <c;>\n");
+        assert_eq!(format("a \"\"\"one\ntwo\"\"\""), "<a> <one\ntwo>\n");
+        assert_eq!(format("a \"x\\\ny\""), "<a> <xy>\n");
+    }
+
+    #[test]
+    fn test_run_suite() {
+        let dir = std::env::temp_dir().join(format!("confetti-conformance-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("simple.conf", "a b;\n"),
+            ("simple.pass", "<a> <b>\n"),
+            ("wrong.conf", "a b;\n"),
+            ("wrong.pass", "<a>\n"),
+            ("unclosed.conf", "a {\n"),
+            ("unclosed.fail", "error: expected '}'\n"),
+            ("comments.conf", "a; // note\n"),
+            ("comments.pass", "<a>\n"),
+            ("comments.ext_c_style_comments", ""),
+            ("punct.conf", "a=b\n"),
+            ("punct.pass", "<a> <=> <b>\n"),
+            ("punct.ext_punctuator_arguments", ""),
+            ("orphan.conf", "a\n"),
+        ];
+        for (name, content) in files {
+            fs::write(dir.join(name), content).unwrap();
+        }
+        fs::write(dir.join("bad_utf8.conf"), b"a \xff\n").unwrap();
+        fs::write(dir.join("bad_utf8.fail"), "").unwrap();
+        fs::write(dir.join("bom.conf"), b"\xef\xbb\xbfa b;\n").unwrap();
+        fs::write(dir.join("bom.pass"), "<a> <b>\n").unwrap();
+
+        let report = run_suite(&dir, &ConfOptions::spec_strict()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let outcome = |name: &str| {
+            report
+                .cases
+                .iter()
+                .find(|case| case.name == name)
+                .map(|case| case.outcome.clone())
+                .unwrap()
+        };
+        assert_eq!(outcome("simple"), Outcome::Passed);
+        assert!(matches!(outcome("wrong"), Outcome::Failed(_)));
+        assert_eq!(outcome("unclosed"), Outcome::Passed);
+        assert_eq!(outcome("comments"), Outcome::Passed);
+        assert!(matches!(outcome("punct"), Outcome::Skipped(_)));
+        assert!(matches!(outcome("orphan"), Outcome::Skipped(_)));
+        assert_eq!(outcome("bad_utf8"), Outcome::Passed);
+        assert_eq!(outcome("bom"), Outcome::Passed);
+
+        assert_eq!(report.cases.len(), 8);
+        assert_eq!(
+            (report.passed(), report.failed(), report.skipped()),
+            (5, 1, 2)
+        );
+        assert!(!report.is_success());
+        assert!(report.to_string().contains("FAIL wrong"));
+    }
+}
@@ -0,0 +1,685 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::edit::directive_extent;
+use crate::{ConfDirective, ConfOptions, ConfUnit, Span};
+
+/// The kind of a structural change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The directive only exists in the new unit.
+    Added,
+    /// The directive only exists in the old unit.
+    Removed,
+    /// The directive exists in both units with different arguments.
+    Changed,
+}
+
+impl ChangeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+/// A single structural change between two configuration units.
+#[derive(Debug, Clone)]
+pub struct Change<'a> {
+    /// The kind of the change.
+    pub kind: ChangeKind,
+    /// The [`Query`](crate::Query) path of the directive, in the new unit for
+    /// added and changed directives and in the old unit for removed ones.
+    pub path: String,
+    /// The directive in the old unit.
+    pub old: Option<&'a ConfDirective>,
+    /// The directive in the new unit.
+    pub new: Option<&'a ConfDirective>,
+}
+
+impl Change<'_> {
+    /// Returns the span of the old directive's name and arguments.
+    pub fn old_span(&self) -> Option<Span> {
+        self.old.map(header_span)
+    }
+
+    /// Returns the span of the new directive's name and arguments.
+    pub fn new_span(&self) -> Option<Span> {
+        self.new.map(header_span)
+    }
+}
+
+/// Options for computing a diff.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Whether to match block directives by their name and first argument
+    /// instead of by position, so that reordered blocks such as
+    /// `server api { ... }` and `server web { ... }` are not reported as changed.
+    pub match_keyed_blocks: bool,
+}
+
+/// The structural changes between two configuration units.
+#[derive(Debug, Clone)]
+pub struct Diff<'a> {
+    /// The changes in document order.
+    pub changes: Vec<Change<'a>>,
+}
+
+/// Computes the structural changes between two configuration units.
+///
+/// Sibling directives are matched by name in order. Added and removed
+/// directives are reported once, without separate entries for their children.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse, ConfOptions};
+/// use confetti_rs::diff::{diff, ChangeKind};
+///
+/// let old = parse("server {\n  listen 80;\n  debug on;\n}", ConfOptions::default()).unwrap();
+/// let new = parse("server {\n  listen 443;\n}", ConfOptions::default()).unwrap();
+///
+/// let changes = diff(&old, &new).changes;
+/// assert_eq!(changes.len(), 2);
+/// assert_eq!(changes[0].kind, ChangeKind::Changed);
+/// assert_eq!(changes[0].path, "server[0]/listen[0]");
+/// assert_eq!(changes[1].kind, ChangeKind::Removed);
+/// assert_eq!(changes[1].path, "server[0]/debug[0]");
+/// ```
+pub fn diff<'a>(old: &'a ConfUnit, new: &'a ConfUnit) -> Diff<'a> {
+    diff_with_options(old, new, &DiffOptions::default())
+}
+
+/// Computes the structural changes between two configuration units with options.
+pub fn diff_with_options<'a>(
+    old: &'a ConfUnit,
+    new: &'a ConfUnit,
+    options: &DiffOptions,
+) -> Diff<'a> {
+    let mut changes = Vec::new();
+    diff_children(
+        &old.directives,
+        &new.directives,
+        "",
+        "",
+        options,
+        &mut changes,
+    );
+    Diff { changes }
+}
+
+impl Diff<'_> {
+    /// Returns whether the two units are structurally identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Formats the changes as a unified diff against the two source texts.
+    ///
+    /// Each hunk is labelled with the path of the changed directive. Changed
+    /// directives show their first line, while added and removed directives
+    /// show their full text including any block. The sources are read with the
+    /// default options.
+    pub fn to_unified(&self, old_source: &str, new_source: &str) -> String {
+        self.to_unified_with_options(old_source, new_source, &ConfOptions::default())
+    }
+
+    /// Formats the changes as a unified diff against two source texts parsed
+    /// with the given options.
+    pub fn to_unified_with_options(
+        &self,
+        old_source: &str,
+        new_source: &str,
+        options: &ConfOptions,
+    ) -> String {
+        let mut output = String::from("--- old\n+++ new\n");
+        for change in &self.changes {
+            let old_lines = change
+                .old
+                .map(|d| change_lines(old_source, d, change.kind, options))
+                .unwrap_or_default();
+            let new_lines = change
+                .new
+                .map(|d| change_lines(new_source, d, change.kind, options))
+                .unwrap_or_default();
+
+            let _ = writeln!(
+                output,
+                "@@ -{} +{} @@ {}",
+                hunk_range(&old_lines),
+                hunk_range(&new_lines),
+                change.path
+            );
+            for (_, line) in &old_lines {
+                let _ = writeln!(output, "-{}", line);
+            }
+            for (_, line) in &new_lines {
+                let _ = writeln!(output, "+{}", line);
+            }
+        }
+        output
+    }
+
+    /// Formats the changes as a JSON document.
+    ///
+    /// ```text
+    /// {"changes":[{"kind":"changed","path":"server[0]/listen[0]",
+    ///   "old":{"start":11,"end":20,"name":"listen","arguments":["80"]},
+    ///   "new":{"start":11,"end":21,"name":"listen","arguments":["443"]}}]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut output = String::from("{\"changes\":[");
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                output.push(',');
+            }
+            output.push_str("{\"kind\":");
+            push_json_string(&mut output, change.kind.as_str());
+            output.push_str(",\"path\":");
+            push_json_string(&mut output, &change.path);
+            for (key, directive) in [("old", change.old), ("new", change.new)] {
+                if let Some(directive) = directive {
+                    let span = header_span(directive);
+                    let _ = write!(
+                        output,
+                        ",\"{}\":{{\"start\":{},\"end\":{},\"name\":",
+                        key,
+                        span.start(),
+                        span.end()
+                    );
+                    push_json_string(&mut output, &directive.name.value);
+                    output.push_str(",\"arguments\":[");
+                    for (j, arg) in directive.arguments.iter().enumerate() {
+                        if j > 0 {
+                            output.push(',');
+                        }
+                        push_json_string(&mut output, &arg.value);
+                    }
+                    output.push_str("]}");
+                }
+            }
+            output.push('}');
+        }
+        output.push_str("]}");
+        output
+    }
+}
+
+/// Compares two sibling lists and records their changes.
+fn diff_children<'a>(
+    old: &'a [ConfDirective],
+    new: &'a [ConfDirective],
+    old_parent: &str,
+    new_parent: &str,
+    options: &DiffOptions,
+    changes: &mut Vec<Change<'a>>,
+) {
+    let pairs = match_siblings(old, new, options);
+    let old_paths = sibling_paths(old, old_parent);
+    let new_paths = sibling_paths(new, new_parent);
+
+    let mut old_matched = vec![false; old.len()];
+    for &(i, _) in &pairs {
+        old_matched[i] = true;
+    }
+    let old_for_new: HashMap<usize, usize> = pairs.iter().map(|&(i, j)| (j, i)).collect();
+
+    // Walk the new list, reporting removals just before the next matched
+    // directive that follows them in the old list
+    let mut next_old = 0;
+    for (j, new_directive) in new.iter().enumerate() {
+        match old_for_new.get(&j) {
+            Some(&i) => {
+                while next_old < i {
+                    if !old_matched[next_old] {
+                        changes.push(removed(&old[next_old], &old_paths[next_old]));
+                    }
+                    next_old += 1;
+                }
+                next_old = next_old.max(i + 1);
+
+                let old_directive = &old[i];
+                if old_directive.arguments != new_directive.arguments {
+                    changes.push(Change {
+                        kind: ChangeKind::Changed,
+                        path: new_paths[j].clone(),
+                        old: Some(old_directive),
+                        new: Some(new_directive),
+                    });
+                }
+                diff_children(
+                    &old_directive.children,
+                    &new_directive.children,
+                    &old_paths[i],
+                    &new_paths[j],
+                    options,
+                    changes,
+                );
+            }
+            None => changes.push(Change {
+                kind: ChangeKind::Added,
+                path: new_paths[j].clone(),
+                old: None,
+                new: Some(new_directive),
+            }),
+        }
+    }
+
+    for i in 0..old.len() {
+        if !old_matched[i] && i >= next_old {
+            changes.push(removed(&old[i], &old_paths[i]));
+        }
+    }
+}
+
+fn removed<'a>(directive: &'a ConfDirective, path: &str) -> Change<'a> {
+    Change {
+        kind: ChangeKind::Removed,
+        path: path.to_string(),
+        old: Some(directive),
+        new: None,
+    }
+}
+
+/// Pairs old and new siblings, returning `(old index, new index)` pairs.
+fn match_siblings(
+    old: &[ConfDirective],
+    new: &[ConfDirective],
+    options: &DiffOptions,
+) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut old_used = vec![false; old.len()];
+    let mut new_used = vec![false; new.len()];
+
+    if options.match_keyed_blocks {
+        let mut keyed: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+        for (i, directive) in old.iter().enumerate().rev() {
+            if let Some(key) = block_key(directive) {
+                keyed.entry(key).or_default().push(i);
+            }
+        }
+        for (j, directive) in new.iter().enumerate() {
+            if let Some(i) = block_key(directive).and_then(|key| keyed.get_mut(&key)?.pop()) {
+                pairs.push((i, j));
+                old_used[i] = true;
+                new_used[j] = true;
+            }
+        }
+    }
+
+    // Match the remaining directives by name with a longest common subsequence
+    let old_rest: Vec<usize> = (0..old.len()).filter(|&i| !old_used[i]).collect();
+    let new_rest: Vec<usize> = (0..new.len()).filter(|&j| !new_used[j]).collect();
+    let old_names: Vec<_> = old_rest.iter().map(|&i| &old[i].name).collect();
+    let new_names: Vec<_> = new_rest.iter().map(|&j| &new[j].name).collect();
+    let mut common = Vec::new();
+    common_subsequence(&old_names, &new_names, (0, 0), &mut common);
+    pairs.extend(common.into_iter().map(|(a, b)| (old_rest[a], new_rest[b])));
+
+    pairs
+}
+
+/// Appends the index pairs of a longest common subsequence of two lists,
+/// offset by `base`, in order.
+///
+/// The common prefix and suffix are matched directly, and the rest is split
+/// with Hirschberg's algorithm, which takes space linear in the lengths.
+fn common_subsequence<T: PartialEq>(
+    old: &[T],
+    new: &[T],
+    base: (usize, usize),
+    pairs: &mut Vec<(usize, usize)>,
+) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    pairs.extend((0..prefix).map(|k| (base.0 + k, base.1 + k)));
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+    let base = (base.0 + prefix, base.1 + prefix);
+
+    match old.len() {
+        0 => {}
+        1 => {
+            if let Some(j) = new.iter().position(|b| *b == old[0]) {
+                pairs.push((base.0, base.1 + j));
+            }
+        }
+        _ if new.is_empty() => {}
+        _ => {
+            // Split the new list where the two halves of the old one share the most
+            let half = old.len() / 2;
+            let forward = common_lengths(old[..half].iter(), new.iter());
+            let backward = common_lengths(old[half..].iter().rev(), new.iter().rev());
+            let split = (0..=new.len())
+                .max_by_key(|&j| (forward[j] + backward[new.len() - j], std::cmp::Reverse(j)))
+                .unwrap_or(0);
+            common_subsequence(&old[..half], &new[..split], base, pairs);
+            common_subsequence(
+                &old[half..],
+                &new[split..],
+                (base.0 + half, base.1 + split),
+                pairs,
+            );
+        }
+    }
+
+    pairs.extend((0..suffix).map(|k| (base.0 + old.len() + k, base.1 + new.len() + k)));
+}
+
+/// Returns, for every prefix of `new`, the length of its longest common
+/// subsequence with `old`.
+fn common_lengths<'t, T: PartialEq + 't>(
+    old: impl Iterator<Item = &'t T>,
+    new: impl Iterator<Item = &'t T> + Clone,
+) -> Vec<usize> {
+    let mut row = vec![0; new.clone().count() + 1];
+    for a in old {
+        let mut diagonal = 0;
+        for (j, b) in new.clone().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if a == b {
+                diagonal + 1
+            } else {
+                above.max(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row
+}
+
+/// Returns the matching key of a block directive with at least one argument.
+fn block_key(directive: &ConfDirective) -> Option<(&str, &str)> {
+    if directive.children.is_empty() {
+        return None;
+    }
+    let first = directive.arguments.first()?;
+    Some((&directive.name.value, &first.value))
+}
+
+/// Builds the query path of every directive in a sibling list.
+fn sibling_paths(directives: &[ConfDirective], parent: &str) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    directives
+        .iter()
+        .map(|directive| {
+            // Queries match names by their text
+            let name = directive.name.text();
+            let count = counts.entry(name.clone()).or_default();
+            let index = *count;
+            *count += 1;
+
+            // Quote names that would read as wildcards or not as a name at all
+            let step = if name.is_empty()
+                || name.starts_with('*')
+                || name.contains(['/', '[', ']', '"'])
+            {
+                format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                name
+            };
+            if parent.is_empty() {
+                format!("{}[{}]", step, index)
+            } else {
+                format!("{}/{}[{}]", parent, step, index)
+            }
+        })
+        .collect()
+}
+
+/// Returns the span from a directive's name to the end of its last argument.
+fn header_span(directive: &ConfDirective) -> Span {
+    let end = directive
+        .arguments
+        .last()
+        .map_or(directive.name.span, |arg| arg.span);
+    directive.name.span.to(end)
+}
+
+/// Returns the numbered source lines to show for one side of a change.
+fn change_lines<'s>(
+    source: &'s str,
+    directive: &ConfDirective,
+    kind: ChangeKind,
+    options: &ConfOptions,
+) -> Vec<(usize, &'s str)> {
+    let span = match kind {
+        ChangeKind::Changed => header_span(directive).range(),
+        _ => directive_extent(source, directive, options)
+            .map(|extent| extent.span)
+            .unwrap_or_else(|_| header_span(directive).range()),
+    };
+    if span.end > source.len() {
+        return Vec::new();
+    }
+
+    let first = source[..span.start].matches('\n').count();
+    let last = first + source[span.start..span.end].matches('\n').count();
+    source
+        .lines()
+        .enumerate()
+        .skip(first)
+        .take(last - first + 1)
+        .map(|(i, line)| (i + 1, line))
+        .collect()
+}
+
+/// Formats the `start,count` part of a hunk header.
+fn hunk_range(lines: &[(usize, &str)]) -> String {
+    match lines.first() {
+        Some((start, _)) => format!("{},{}", start, lines.len()),
+        None => "0,0".to_string(),
+    }
+}
+
+/// Appends a string as a quoted JSON string.
+fn push_json_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn unit(source: &str) -> ConfUnit {
+        parse(source, ConfOptions::default()).unwrap()
+    }
+
+    fn summary(diff: &Diff) -> Vec<(ChangeKind, String)> {
+        diff.changes
+            .iter()
+            .map(|c| (c.kind, c.path.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_units() {
+        let old = unit("a 1;\nb { c; }");
+        let new = unit("a   1;\n\nb {\n  c;\n}\n");
+        assert!(diff(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_added_removed_changed() {
+        let old = unit("a 1;\nb;\nc { d 1; }");
+        let new = unit("a 2;\nc { d 1; e; }\nf;");
+        let diff = diff(&old, &new);
+        assert_eq!(
+            summary(&diff),
+            vec![
+                (ChangeKind::Changed, "a[0]".to_string()),
+                (ChangeKind::Removed, "b[0]".to_string()),
+                (ChangeKind::Added, "c[0]/e[0]".to_string()),
+                (ChangeKind::Added, "f[0]".to_string()),
+            ]
+        );
+        assert_eq!(diff.changes[0].old_span().map(Span::range), Some(0..3));
+        assert_eq!(diff.changes[0].new_span().map(Span::range), Some(0..3));
+    }
+
+    #[test]
+    fn test_positional_matching_of_reordered_blocks() {
+        let old = unit("server api { port 1; }\nserver web { port 2; }");
+        let new = unit("server web { port 2; }\nserver api { port 1; }");
+        let changes = summary(&diff(&old, &new));
+        assert_eq!(changes.len(), 4);
+        assert!(changes.contains(&(ChangeKind::Changed, "server[0]".to_string())));
+        assert!(changes.contains(&(ChangeKind::Changed, "server[0]/port[0]".to_string())));
+    }
+
+    #[test]
+    fn test_keyed_block_matching() {
+        let old = unit("server api { port 1; }\nserver web { port 2; }");
+        let new = unit("server web { port 3; }\nserver api { port 1; }");
+        let options = DiffOptions {
+            match_keyed_blocks: true,
+        };
+        let diff = diff_with_options(&old, &new, &options);
+        assert_eq!(
+            summary(&diff),
+            vec![(ChangeKind::Changed, "server[0]/port[0]".to_string())]
+        );
+        // The changed directive is located in both sources
+        assert_eq!(diff.changes[0].old.unwrap().arguments[0].value, "2");
+        assert_eq!(diff.changes[0].new.unwrap().arguments[0].value, "3");
+    }
+
+    #[test]
+    fn test_unified_output() {
+        let old_source = "server {\n  listen 80;\n  debug on;\n}\n";
+        let new_source = "server {\n  listen 443;\n}\nextra {\n  x;\n}\n";
+        let (old, new) = (unit(old_source), unit(new_source));
+        let unified = diff(&old, &new).to_unified(old_source, new_source);
+        assert_eq!(
+            unified,
+            "--- old\n+++ new\n\
+             @@ -2,1 +2,1 @@ server[0]/listen[0]\n-  listen 80;\n+  listen 443;\n\
+             @@ -3,1 +0,0 @@ server[0]/debug[0]\n-  debug on;\n\
+             @@ -0,0 +4,3 @@ extra[0]\n+extra {\n+  x;\n+}\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_output_with_options() {
+        let options = ConfOptions {
+            allow_c_style_comments: true,
+            ..Default::default()
+        };
+        let new_source = "a {\n  b; // }\n}\n";
+        let (old, new) = (unit(""), parse(new_source, options.clone()).unwrap());
+        let unified = diff(&old, &new).to_unified_with_options("", new_source, &options);
+        assert_eq!(
+            unified,
+            "--- old\n+++ new\n@@ -0,0 +1,3 @@ a[0]\n+a {\n+  b; // }\n+}\n"
+        );
+    }
+
+    #[test]
+    fn test_common_subsequence() {
+        // Compare with the lengths of a full table
+        let cases = [
+            ("", "abc"),
+            ("abc", "abc"),
+            ("abcbdab", "bdcaba"),
+            ("xaybzc", "abc"),
+            ("aaaa", "aa"),
+            ("abcdefg", "gfedcba"),
+            ("kitten sitting", "sitting kitten"),
+        ];
+        for (old, new) in cases {
+            let (old, new): (Vec<char>, Vec<char>) = (old.chars().collect(), new.chars().collect());
+            let mut pairs = Vec::new();
+            common_subsequence(&old, &new, (0, 0), &mut pairs);
+
+            assert!(pairs.iter().all(|&(a, b)| old[a] == new[b]));
+            assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+            let mut table = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for a in (0..old.len()).rev() {
+                for b in (0..new.len()).rev() {
+                    table[a][b] = if old[a] == new[b] {
+                        table[a + 1][b + 1] + 1
+                    } else {
+                        table[a + 1][b].max(table[a][b + 1])
+                    };
+                }
+            }
+            assert_eq!(pairs.len(), table[0][0]);
+        }
+    }
+
+    #[test]
+    fn test_long_sibling_lists() {
+        // A full table for these would take gigabytes
+        let old_source: String = (0..50_000).map(|i| format!("d{};\n", i)).collect();
+        let new_source = old_source.replace("d25000;", "e;");
+        let (old, new) = (unit(&old_source), unit(&new_source));
+        let diff = diff(&old, &new);
+        let kinds: Vec<ChangeKind> = diff.changes.iter().map(|change| change.kind).collect();
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.contains(&ChangeKind::Added) && kinds.contains(&ChangeKind::Removed));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let old = unit("a \"x\";");
+        let new = unit("a \"y\";");
+        assert_eq!(
+            diff(&old, &new).to_json(),
+            "{\"changes\":[{\"kind\":\"changed\",\"path\":\"a[0]\",\
+             \"old\":{\"start\":0,\"end\":5,\"name\":\"a\",\"arguments\":[\"\\\"x\\\"\"]},\
+             \"new\":{\"start\":0,\"end\":5,\"name\":\"a\",\"arguments\":[\"\\\"y\\\"\"]}}]}"
+        );
+    }
+
+    #[test]
+    fn test_paths_are_valid_queries() {
+        let old = unit("a { b 1; b 2; }");
+        let new = unit("a { b 1; b 3; }");
+        let diff = diff(&old, &new);
+        assert_eq!(diff.changes.len(), 1);
+        let selected = new.select(&diff.changes[0].path).unwrap();
+        assert_eq!(selected[0].arguments[0].value, "3");
+
+        // Names that would read as wildcards, and empty names, are quoted, and
+        // escaped names are written as their text
+        for (old, new) in [
+            (
+                "\"a\\\"b\" 2;\n\"a\\\"b\" 1;",
+                "\"a\\\"b\" 2;\n\"a\\\"b\" 3;",
+            ),
+            ("a\\ b 2;\n\"a b\" 1;", "a\\ b 2;\n\"a b\" 3;"),
+            ("other 2;\n* 1;", "other 2;\n* 3;"),
+            ("other 2;\n** 1;", "other 2;\n** 3;"),
+            ("other 2;\n\"\" 1;", "other 2;\n\"\" 3;"),
+        ] {
+            let (old, new) = (unit(old), unit(new));
+            let changes = super::diff(&old, &new).changes;
+            assert_eq!(changes.len(), 1);
+            let selected = new.select(&changes[0].path).unwrap();
+            assert_eq!(selected.len(), 1, "{}", changes[0].path);
+            assert_eq!(selected[0].arguments[0].value, "3");
+        }
+    }
+}
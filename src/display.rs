@@ -0,0 +1,320 @@
+//! `Display` for the AST, printing confetti that parses back to the same tree.
+//!
+//! Arguments are printed in the form they were read in, so a parsed tree
+//! prints its quotes, escapes, raw strings and heredocs unchanged. An argument
+//! whose value is not in that form, such as one built with the text `a b` and
+//! no quotes, is quoted, and triple-quoted if the text spans several lines.
+//! Blocks are indented by two spaces and every directive without a block ends
+//! with `;`.
+//!
+//! A line continuation at the end of an argument takes in the whitespace after
+//! it, so nothing separates such an argument from the next one. A heredoc is
+//! quoted unless it ends its directive, as nothing but `;` may follow its
+//! closing tag.
+
+use std::fmt;
+
+use crate::builder::{is_line_terminator, quoted};
+use crate::lexer::{Lexer, TokenType};
+use crate::{ConfArgument, ConfComment, ConfDirective, ConfOptions, ConfUnit};
+
+impl fmt::Display for ConfArgument {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&printed(self))
+    }
+}
+
+impl fmt::Display for ConfDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_directive(f, self, 0, &mut [].iter().peekable())
+    }
+}
+
+impl fmt::Display for ConfUnit {
+    /// Prints each comment on its own line before the first directive that
+    /// follows it in the source, and the comments after the last directive
+    /// at the end.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut comments = self.comments.iter().peekable();
+        for directive in &self.directives {
+            write_directive(f, directive, 0, &mut comments)?;
+            f.write_str("\n")?;
+        }
+        for comment in comments {
+            writeln!(f, "{}", comment.content)?;
+        }
+        Ok(())
+    }
+}
+
+type Comments<'a> = std::iter::Peekable<std::slice::Iter<'a, ConfComment>>;
+
+/// Writes a directive without a trailing newline, preceded by the comments
+/// that come before it in the source.
+fn write_directive(
+    f: &mut fmt::Formatter<'_>,
+    directive: &ConfDirective,
+    depth: usize,
+    comments: &mut Comments<'_>,
+) -> fmt::Result {
+    let indent = "  ".repeat(depth);
+    while let Some(comment) = comments.next_if(|c| c.span.start() < directive.name.span.start()) {
+        writeln!(f, "{}{}", indent, comment.content)?;
+    }
+
+    let arguments: Vec<&ConfArgument> = std::iter::once(&directive.name)
+        .chain(&directive.arguments)
+        .collect();
+    let mut forms: Vec<String> = arguments.iter().map(|argument| printed(argument)).collect();
+    // A heredoc's closing tag may only be followed by `;`, so a heredoc
+    // followed by another argument or a block is quoted instead
+    let last = forms.len() - 1;
+    for (index, form) in forms.iter_mut().enumerate() {
+        let argument = arguments[index];
+        let ends_line = index == last && directive.children.is_empty();
+        if argument.is_heredoc && *form == argument.value && !ends_line {
+            *form = quoted(argument.text()).value;
+        }
+    }
+    // Only a quote ends an argument after a continuation, so an argument
+    // followed by anything else is quoted instead
+    for index in 1..forms.len() {
+        if ends_in_continuation(&forms[index - 1]) && !forms[index].starts_with('"') {
+            forms[index - 1] = quoted(arguments[index - 1].text()).value;
+        }
+    }
+
+    f.write_str(&indent)?;
+    for (index, form) in forms.iter().enumerate() {
+        if index > 0 && !ends_in_continuation(&forms[index - 1]) {
+            f.write_str(" ")?;
+        }
+        f.write_str(form)?;
+    }
+    if directive.children.is_empty() {
+        return f.write_str(";");
+    }
+
+    let last = forms.last().expect("a directive has a name");
+    if !ends_in_continuation(last) {
+        f.write_str(" ")?;
+    }
+    f.write_str("{\n")?;
+    for child in &directive.children {
+        write_directive(f, child, depth + 1, comments)?;
+        f.write_str("\n")?;
+    }
+    write!(f, "{}}}", indent)
+}
+
+/// Returns the text an argument is printed as.
+fn printed(argument: &ConfArgument) -> String {
+    if argument.is_expression {
+        argument.value.clone()
+    } else if has_delimiters(argument) {
+        if lexes_as(argument) {
+            argument.value.clone()
+        } else {
+            // Such as `"a" "b"`, which reads back as two arguments
+            quoted(argument.text()).value
+        }
+    } else if argument.is_quoted || argument.is_triple_quoted || argument.is_raw {
+        // The value is the text without its delimiters
+        quoted(&argument.value).value
+    } else if argument.is_heredoc || !is_bare(&argument.value) {
+        quoted(argument.text()).value
+    } else {
+        argument.value.clone()
+    }
+}
+
+/// Returns whether a printed argument ends in a line continuation, which
+/// takes in the whitespace after it.
+fn ends_in_continuation(form: &str) -> bool {
+    let form = form.trim_end_matches(|c: char| c.is_whitespace() && !is_line_terminator(c));
+    let Some(terminator) = form.chars().next_back().filter(|&c| is_line_terminator(c)) else {
+        return false;
+    };
+    let mut body = &form[..form.len() - terminator.len_utf8()];
+    if terminator == '\n' {
+        body = body.strip_suffix('\r').unwrap_or(body);
+    }
+    body.bytes().rev().take_while(|&byte| byte == b'\\').count() % 2 == 1
+}
+
+/// Returns whether a quoted, raw or heredoc value lexes as a single argument
+/// with the argument's flags.
+fn lexes_as(argument: &ConfArgument) -> bool {
+    let mut lexer = Lexer::new(&argument.value, ConfOptions::all_extensions());
+    let Ok(token) = lexer.next_token() else {
+        return false;
+    };
+    token.token_type == TokenType::Argument
+        && token.span == (0..argument.value.len())
+        && !token.is_expression
+        && token.is_triple_quoted == argument.is_triple_quoted
+        && token.is_raw == argument.is_raw
+        && token.is_heredoc == argument.is_heredoc
+        && lexer
+            .next_token()
+            .is_ok_and(|token| token.token_type == TokenType::Eof)
+}
+
+/// Returns whether the value of an argument has the delimiters its flags say
+/// it was read with.
+fn has_delimiters(argument: &ConfArgument) -> bool {
+    let value = argument.value.as_str();
+    if argument.is_heredoc {
+        value.starts_with("<<")
+    } else if argument.is_raw {
+        value.starts_with("r\"") || value.starts_with("r#")
+    } else if argument.is_triple_quoted {
+        value.len() >= 6 && value.starts_with("\"\"\"") && value.ends_with("\"\"\"")
+    } else if argument.is_quoted {
+        value.len() >= 2 && value.starts_with('"') && value.ends_with('"')
+    } else {
+        false
+    }
+}
+
+/// Returns whether a value reads back as one unquoted argument.
+fn is_bare(value: &str) -> bool {
+    let mut rest = value;
+    let mut chars = rest.chars();
+    if value.is_empty() {
+        return false;
+    }
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                // A line continuation, which may be followed by indentation
+                Some(next) if is_line_terminator(next) => {
+                    rest = chars.as_str();
+                    if next == '\r' {
+                        rest = rest.strip_prefix('\n').unwrap_or(rest);
+                    }
+                    rest = rest
+                        .trim_start_matches(|c: char| c.is_whitespace() && !is_line_terminator(c));
+                    chars = rest.chars();
+                }
+                Some(_) => {}
+                None => return false,
+            },
+            '"' | '{' | '}' | ';' | '#' | '(' => return false,
+            c if c.is_whitespace() || c.is_control() => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{unit, Directive};
+    use crate::{parse, ConfArgument, ConfOptions};
+
+    /// Checks that printing a parsed unit and parsing it again gives the same
+    /// tree, and that printing that tree gives the same text.
+    fn assert_fixpoint(input: &str, options: ConfOptions) -> String {
+        let first = parse(input, options.clone()).unwrap();
+        let printed = first.to_string();
+        let second = parse(&printed, options)
+            .unwrap_or_else(|error| panic!("{} does not parse: {}", printed, error));
+        assert_eq!(first.directives, second.directives, "printed:\n{}", printed);
+        assert_eq!(first.comments, second.comments, "printed:\n{}", printed);
+        assert_eq!(second.to_string(), printed);
+        printed
+    }
+
+    #[test]
+    fn test_parse_display_fixpoint() {
+        let printed = assert_fixpoint(
+            "# Server settings\nserver \"main site\" {\n  listen 127.0.0.1:80 default;\n\n    root /var/www\\ html\n  location /api { proxy_pass \"http://b\"; } }\nmessage \"\"\"one\n two\"\"\" path\\\n  continued\n# trailing\n",
+            ConfOptions::default(),
+        );
+        assert_eq!(
+            printed,
+            "# Server settings\nserver \"main site\" {\n  listen 127.0.0.1:80 default;\n  root /var/www\\ html;\n  location /api {\n    proxy_pass \"http://b\";\n  }\n}\nmessage \"\"\"one\n two\"\"\" path\\\n  continued;\n# trailing\n"
+        );
+
+        assert_fixpoint(
+            "path r#\"C:\\temp \"x\"\"#;\nscript <<EOF\n    echo hi\n    EOF\n/* block */ inner {\n  a;\n}\n",
+            ConfOptions::all_extensions(),
+        );
+        assert_fixpoint("", ConfOptions::default());
+
+        // A continuation at the end of an argument takes in the space after it
+        assert_fixpoint("x\\\n\"\";\n", ConfOptions::default());
+        assert_fixpoint("x\\\n\"\" {\n  a;\n}\n", ConfOptions::default());
+        assert_fixpoint("a b\\\n c\\\n;\n", ConfOptions::default());
+
+        // A heredoc followed by more of its directive is quoted
+        let options = ConfOptions::all_extensions();
+        let parsed = parse("x <<E\n  body\n  E;\n", options.clone()).unwrap();
+        let heredoc = parsed.directives[0].arguments[0].clone();
+        let built = unit([
+            Directive::new("x").argument(heredoc.clone()).arg("tail"),
+            Directive::new("y")
+                .argument(heredoc)
+                .child(Directive::new("z")),
+        ]);
+        let printed = assert_fixpoint(&built.to_string(), options.clone());
+        let reparsed = parse(&printed, options).unwrap();
+        assert_eq!(reparsed.directives[0].arguments[0].text(), "body");
+        assert_eq!(reparsed.directives[0].arguments[1].value, "tail");
+        assert_eq!(reparsed.directives[1].arguments[0].text(), "body");
+        assert_eq!(reparsed.directives[1].children[0].name.value, "z");
+    }
+
+    #[test]
+    fn test_quotes_by_content() {
+        let bare = |value: &str| ConfArgument {
+            value: value.to_string(),
+            span: Default::default(),
+            is_quoted: false,
+            is_triple_quoted: false,
+            is_raw: false,
+            is_heredoc: false,
+            is_expression: false,
+        };
+
+        assert_eq!(bare("80").to_string(), "80");
+        assert_eq!(bare("a b").to_string(), "\"a b\"");
+        assert_eq!(bare("a;b").to_string(), "\"a;b\"");
+        assert_eq!(bare("{").to_string(), "\"{\"");
+        assert_eq!(bare("#x").to_string(), "\"#x\"");
+        assert_eq!(bare("say \"hi\"").to_string(), r#""say \"hi\"""#);
+        assert_eq!(bare("one\ntwo").to_string(), "\"\"\"one\ntwo\"\"\"");
+        assert_eq!(bare("").to_string(), "\"\"");
+
+        // A quoted argument whose value lacks its quotes
+        let mut argument = bare("my host");
+        argument.is_quoted = true;
+        assert_eq!(argument.to_string(), "\"my host\"");
+
+        // A quoted value that does not read back as one argument is quoted again
+        let mut argument = bare("\"a\" \"b\"");
+        argument.is_quoted = true;
+        let parsed = parse(&format!("d {};", argument), ConfOptions::default()).unwrap();
+        assert_eq!(parsed.directives[0].arguments.len(), 1);
+        assert_eq!(parsed.directives[0].arguments[0].text(), "a\" \"b");
+
+        // The expression form is kept
+        let mut argument = bare("f(x)");
+        argument.is_expression = true;
+        assert_eq!(argument.to_string(), "f(x)");
+
+        let texts = ["a b", "x;y", "{}", "#", "q\"", "l\n2"];
+        let directive = texts.iter().fold(Directive::new("d"), |directive, text| {
+            directive.argument(bare(text))
+        });
+        let built = unit([directive]);
+        let parsed = parse(&built.to_string(), ConfOptions::default()).unwrap();
+        let values: Vec<String> = parsed.directives[0]
+            .arguments
+            .iter()
+            .map(ConfArgument::text)
+            .collect();
+        assert_eq!(values, texts);
+    }
+}
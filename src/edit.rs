@@ -0,0 +1,587 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+use crate::lexer::{Lexer, TokenType};
+use crate::query::{Query, QueryError};
+use crate::{parse, ConfDirective, ConfError, ConfOptions, ConfUnit, Span};
+
+/// Error type for editing operations
+#[derive(Debug)]
+pub enum EditError {
+    /// The path is not a valid query
+    Query(QueryError),
+    /// No directive matched the path
+    NotFound(String),
+    /// More than one directive matched the path
+    Ambiguous(String, usize),
+    /// The edit would produce text that no longer parses
+    Parse(ConfError),
+    /// The edit cannot be applied
+    Invalid(String),
+}
+
+impl Error for EditError {}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::Query(err) => write!(f, "{}", err),
+            EditError::NotFound(path) => write!(f, "No directive matches '{}'", path),
+            EditError::Ambiguous(path, count) => {
+                write!(
+                    f,
+                    "Path '{}' matches {} directives, expected one",
+                    path, count
+                )
+            }
+            EditError::Parse(err) => write!(f, "Edit produced invalid configuration: {}", err),
+            EditError::Invalid(msg) => write!(f, "Invalid edit: {}", msg),
+        }
+    }
+}
+
+impl From<QueryError> for EditError {
+    fn from(error: QueryError) -> Self {
+        EditError::Query(error)
+    }
+}
+
+impl From<ConfError> for EditError {
+    fn from(error: ConfError) -> Self {
+        EditError::Parse(error)
+    }
+}
+
+/// The source ranges covered by a directive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extent {
+    /// The whole directive, from its name up to and including its `;` or
+    /// closing `}`. Directives terminated by a newline end at their last argument.
+    pub span: Range<usize>,
+    /// The block of the directive, from `{` up to and including `}`.
+    pub block: Option<Range<usize>>,
+}
+
+/// Computes the source ranges covered by a parsed directive.
+///
+/// `source` must be the text the directive was parsed from, with the same options.
+pub fn directive_extent(
+    source: &str,
+    directive: &ConfDirective,
+    options: &ConfOptions,
+) -> Result<Extent, ConfError> {
+    let start = directive.name.span.start();
+    let after = directive
+        .arguments
+        .last()
+        .map_or(directive.name.span.end(), |arg| arg.span.end());
+
+    let relocate = |error: ConfError| error.shifted(after);
+
+    let mut lexer = Lexer::new(&source[after..], options.clone());
+    loop {
+        let token = lexer.next_token().map_err(relocate)?;
+        match token.token_type {
+            TokenType::Continuation => continue,
+            TokenType::Semicolon => {
+                return Ok(Extent {
+                    span: start..after + token.span.end,
+                    block: None,
+                });
+            }
+            TokenType::LeftCurlyBrace => {
+                let open = after + token.span.start;
+                let mut depth = 1;
+                loop {
+                    let token = lexer.next_token().map_err(relocate)?;
+                    match token.token_type {
+                        TokenType::LeftCurlyBrace => depth += 1,
+                        TokenType::RightCurlyBrace => {
+                            depth -= 1;
+                            if depth == 0 {
+                                let close = after + token.span.end;
+                                return Ok(Extent {
+                                    span: start..close,
+                                    block: Some(open..close),
+                                });
+                            }
+                        }
+                        TokenType::Eof => {
+                            return Err(ConfError::ParserError {
+                                span: Span::point(open),
+                                message: "Expected '}'".to_string(),
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {
+                return Ok(Extent {
+                    span: start..after,
+                    block: None,
+                });
+            }
+        }
+    }
+}
+
+/// A replacement of a source range with new text.
+struct Splice {
+    range: Range<usize>,
+    text: String,
+}
+
+/// A configuration source together with its parsed form, supporting edits
+/// that rewrite only the affected parts of the text.
+///
+/// Directives are addressed with [`Query`] paths, and each path must match
+/// exactly one directive. Every edit is applied to the source text and the
+/// result is parsed again, so [`Document::unit`] always reflects the current
+/// text and all regions outside the edit stay byte-identical. An edit that
+/// would leave the text unparseable is rejected and the document is unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::edit::Document;
+/// use confetti_rs::ConfOptions;
+///
+/// let source = "# web server\nserver {\n    port 8080;   # main port\n    debug on;\n}\n";
+/// let mut doc = Document::parse(source, ConfOptions::default()).unwrap();
+///
+/// doc.set("server/port", &["9090"]).unwrap();
+/// doc.insert_child("server", "listen 443;").unwrap();
+/// doc.remove("server/debug").unwrap();
+///
+/// assert_eq!(
+///     doc.source(),
+///     "# web server\nserver {\n    port 9090;   # main port\n    listen 443;\n}\n"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Document {
+    source: String,
+    options: ConfOptions,
+    /// The options the current source is lexed with, after its pragma.
+    source_options: ConfOptions,
+    unit: ConfUnit,
+}
+
+impl Document {
+    /// Parses a source text into an editable document.
+    pub fn parse(source: impl Into<String>, options: ConfOptions) -> Result<Self, ConfError> {
+        let source = source.into();
+        let unit = parse(&source, options.clone())?;
+        let source_options = options.for_input(&source)?;
+        Ok(Self {
+            source,
+            options,
+            source_options,
+            unit,
+        })
+    }
+
+    /// Returns the current source text.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the parsed form of the current source text.
+    pub fn unit(&self) -> &ConfUnit {
+        &self.unit
+    }
+
+    /// Consumes the document, returning its source text.
+    pub fn into_source(self) -> String {
+        self.source
+    }
+
+    /// Replaces the arguments of a directive.
+    ///
+    /// Arguments are written as given, so quoted values must include their quotes.
+    pub fn set(&mut self, path: &str, arguments: &[&str]) -> Result<(), EditError> {
+        let directive = self.find(path)?;
+        let start = directive.name.span.end();
+        let end = directive
+            .arguments
+            .last()
+            .map_or(start, |arg| arg.span.end());
+
+        let mut text = String::new();
+        for arg in arguments {
+            text.push(' ');
+            text.push_str(arg);
+        }
+
+        self.apply(vec![Splice {
+            range: start..end,
+            text,
+        }])
+    }
+
+    /// Renames a directive.
+    pub fn rename(&mut self, path: &str, name: &str) -> Result<(), EditError> {
+        let range = self.find(path)?.name.span.range();
+        self.apply(vec![Splice {
+            range,
+            text: name.to_string(),
+        }])
+    }
+
+    /// Removes a directive. A line left empty by the removal is removed too.
+    pub fn remove(&mut self, path: &str) -> Result<(), EditError> {
+        let directive = self.find(path)?;
+        let extent = directive_extent(&self.source, directive, &self.source_options)?;
+        let range = self.removal_range(extent.span);
+        self.apply(vec![Splice {
+            range,
+            text: String::new(),
+        }])
+    }
+
+    /// Appends a child directive, given as source text, to a directive.
+    ///
+    /// An empty path (or `/`) appends a top-level directive. A directive
+    /// without a block gets one. The child is indented like its siblings.
+    pub fn insert_child(&mut self, parent: &str, directive: &str) -> Result<(), EditError> {
+        let splice = self.insertion(parent, directive, None)?;
+        self.apply(vec![splice])
+    }
+
+    /// Moves a directive to the end of another directive's children.
+    ///
+    /// An empty destination path (or `/`) moves the directive to the top level.
+    pub fn move_to(&mut self, path: &str, parent: &str) -> Result<(), EditError> {
+        let directive = self.find(path)?;
+        let extent = directive_extent(&self.source, directive, &self.source_options)?;
+        let text = self.source[extent.span.clone()].to_string();
+        let removal = self.removal_range(extent.span.clone());
+
+        let splice = self.insertion(parent, &text, Some(extent.span.start))?;
+        if extent.span.start <= splice.range.start && splice.range.start < extent.span.end {
+            return Err(EditError::Invalid(
+                "cannot move a directive into itself".to_string(),
+            ));
+        }
+
+        self.apply(vec![
+            Splice {
+                range: removal,
+                text: String::new(),
+            },
+            splice,
+        ])
+    }
+
+    /// Returns the single directive matched by a path.
+    fn find(&self, path: &str) -> Result<&ConfDirective, EditError> {
+        let matches = Query::parse(path)?.select(&self.unit.directives);
+        match matches.len() {
+            0 => Err(EditError::NotFound(path.to_string())),
+            1 => Ok(matches[0]),
+            n => Err(EditError::Ambiguous(path.to_string(), n)),
+        }
+    }
+
+    /// Computes the splice that appends `text` as a child of `parent`.
+    ///
+    /// `origin` is the start of the directive being moved, whose lines are
+    /// re-indented relative to their original indentation.
+    fn insertion(
+        &self,
+        parent: &str,
+        text: &str,
+        origin: Option<usize>,
+    ) -> Result<Splice, EditError> {
+        let old_indent = origin.map_or("", |pos| self.indent_at(pos));
+
+        if parent.is_empty() || parent == "/" {
+            let text = reindent(text, old_indent, "");
+            let mut insert = String::new();
+            if !self.source.is_empty() && !self.source.ends_with('\n') {
+                insert.push('\n');
+            }
+            insert.push_str(&text);
+            insert.push('\n');
+            return Ok(Splice {
+                range: self.source.len()..self.source.len(),
+                text: insert,
+            });
+        }
+
+        let directive = self.find(parent)?;
+        let extent = directive_extent(&self.source, directive, &self.source_options)?;
+        let parent_indent = self.indent_at(extent.span.start);
+        let indent = match directive.children.first() {
+            Some(child) if self.starts_line(child.name.span.start()) => {
+                self.indent_at(child.name.span.start()).to_string()
+            }
+            _ => format!("{}    ", parent_indent),
+        };
+        let text = reindent(text, old_indent, &indent);
+
+        let block = match extent.block {
+            Some(block) => block,
+            None => {
+                // Give the directive a block, replacing its ';' if it has one
+                let end = extent.span.end;
+                let start = if self.source[..end].ends_with(';') {
+                    end - 1
+                } else {
+                    end
+                };
+                return Ok(Splice {
+                    range: start..end,
+                    text: format!(" {{\n{}{}\n{}}}", indent, text, parent_indent),
+                });
+            }
+        };
+
+        let close = block.end - 1;
+        if self.starts_line(close) {
+            // Insert a new line above the closing brace
+            let line_start = self.line_start(close);
+            Ok(Splice {
+                range: line_start..line_start,
+                text: format!("{}{}\n", indent, text),
+            })
+        } else if block.end - block.start == 2 {
+            Ok(Splice {
+                range: close..close,
+                text: format!(" {} ", text),
+            })
+        } else {
+            let needs_space = !self.source[..close].ends_with(char::is_whitespace);
+            Ok(Splice {
+                range: close..close,
+                text: format!("{}{} ", if needs_space { " " } else { "" }, text),
+            })
+        }
+    }
+
+    /// Widens a removed range to the whole line when nothing else is on it,
+    /// or to the whitespace that follows it otherwise.
+    fn removal_range(&self, range: Range<usize>) -> Range<usize> {
+        let line_start = self.line_start(range.start);
+        let rest = &self.source[range.end..];
+        let trailing = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let after = range.end + trailing;
+
+        if self.starts_line(range.start) {
+            let rest = &self.source[after..];
+            if rest.is_empty() {
+                return line_start..after;
+            }
+            if rest.starts_with("\r\n") {
+                return line_start..after + 2;
+            }
+            if rest.starts_with('\n') {
+                return line_start..after + 1;
+            }
+        }
+        range.start..after
+    }
+
+    /// Returns the byte offset of the start of the line containing `pos`.
+    fn line_start(&self, pos: usize) -> usize {
+        self.source[..pos].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Returns whether only whitespace precedes `pos` on its line.
+    fn starts_line(&self, pos: usize) -> bool {
+        self.source[self.line_start(pos)..pos]
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+    }
+
+    /// Returns the leading whitespace of the line containing `pos`.
+    fn indent_at(&self, pos: usize) -> &str {
+        let start = self.line_start(pos);
+        let line = &self.source[start..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    /// Applies non-overlapping splices and parses the result.
+    fn apply(&mut self, mut splices: Vec<Splice>) -> Result<(), EditError> {
+        splices.sort_by_key(|splice| std::cmp::Reverse(splice.range.start));
+        let mut source = self.source.clone();
+        for splice in splices {
+            source.replace_range(splice.range, &splice.text);
+        }
+
+        self.unit = parse(&source, self.options.clone())?;
+        self.source_options = self.options.for_input(&source)?;
+        self.source = source;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Replaces the indentation of every line after the first.
+fn reindent(text: &str, old: &str, new: &str) -> String {
+    let mut lines = text.split('\n');
+    let mut result = lines.next().unwrap_or_default().to_string();
+    for line in lines {
+        result.push('\n');
+        result.push_str(new);
+        result.push_str(line.strip_prefix(old).unwrap_or(line));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(source: &str) -> Document {
+        Document::parse(source, ConfOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_directive_extent() {
+        let source = "a 1;\nb {\n  c { d; }\n}\ne 2\n";
+        let unit = parse(source, ConfOptions::default()).unwrap();
+        let options = ConfOptions::default();
+
+        let a = directive_extent(source, &unit.directives[0], &options).unwrap();
+        assert_eq!(&source[a.span], "a 1;");
+        assert!(a.block.is_none());
+
+        let b = directive_extent(source, &unit.directives[1], &options).unwrap();
+        assert_eq!(&source[b.span], "b {\n  c { d; }\n}");
+        assert_eq!(&source[b.block.unwrap()], "{\n  c { d; }\n}");
+
+        let e = directive_extent(source, &unit.directives[2], &options).unwrap();
+        assert_eq!(&source[e.span], "e 2");
+    }
+
+    #[test]
+    fn test_set_preserves_surroundings() {
+        let mut doc = doc("server {\n    port   8080 ; # keep\n}\n");
+        doc.set("server/port", &["9090"]).unwrap();
+        assert_eq!(doc.source(), "server {\n    port 9090 ; # keep\n}\n");
+        assert_eq!(
+            doc.unit().directives[0].children[0].arguments[0].value,
+            "9090"
+        );
+    }
+
+    #[test]
+    fn test_set_adds_and_clears_arguments() {
+        let mut doc = doc("flag;\nlisten 80 ssl;\n");
+        doc.set("flag", &["on", "\"quoted value\""]).unwrap();
+        doc.set("listen", &[]).unwrap();
+        assert_eq!(doc.source(), "flag on \"quoted value\";\nlisten;\n");
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut doc = doc("server {\n  debug on;\n}\n");
+        doc.rename("server/debug", "verbose").unwrap();
+        assert_eq!(doc.source(), "server {\n  verbose on;\n}\n");
+    }
+
+    #[test]
+    fn test_remove_whole_line() {
+        let mut doc = doc("server {\n  debug on;\n  port 80;\n}\n");
+        doc.remove("server/debug").unwrap();
+        assert_eq!(doc.source(), "server {\n  port 80;\n}\n");
+
+        doc.remove("server").unwrap();
+        assert_eq!(doc.source(), "");
+    }
+
+    #[test]
+    fn test_remove_inline() {
+        let mut doc = doc("a { b; c; d; }\n");
+        doc.remove("a/c").unwrap();
+        assert_eq!(doc.source(), "a { b; d; }\n");
+    }
+
+    #[test]
+    fn test_insert_child_uses_sibling_indent() {
+        let mut doc = doc("server {\n  port 80;\n}\n");
+        doc.insert_child("server", "listen 443;").unwrap();
+        assert_eq!(doc.source(), "server {\n  port 80;\n  listen 443;\n}\n");
+    }
+
+    #[test]
+    fn test_insert_child_into_one_line_blocks() {
+        let mut doc = doc("a { b; }\nc {}\n");
+        doc.insert_child("a", "x;").unwrap();
+        doc.insert_child("c", "y;").unwrap();
+        assert_eq!(doc.source(), "a { b; x; }\nc { y; }\n");
+    }
+
+    #[test]
+    fn test_insert_child_creates_block() {
+        let mut doc = doc("server {\n    listen 80;\n}\n");
+        doc.insert_child("server/listen", "ssl on;").unwrap();
+        assert_eq!(
+            doc.source(),
+            "server {\n    listen 80 {\n        ssl on;\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_insert_top_level() {
+        let mut doc = doc("a;");
+        doc.insert_child("", "b;").unwrap();
+        assert_eq!(doc.source(), "a;\nb;\n");
+    }
+
+    #[test]
+    fn test_move_to() {
+        let mut doc = doc("old {\n  keep;\n  item {\n    value 1;\n  }\n}\nnew {\n}\n");
+        doc.move_to("old/item", "new").unwrap();
+        assert_eq!(
+            doc.source(),
+            "old {\n  keep;\n}\nnew {\n    item {\n      value 1;\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_move_into_itself_is_rejected() {
+        let mut doc = doc("a {\n  b {\n  }\n}\n");
+        assert!(matches!(
+            doc.move_to("a", "a/b"),
+            Err(EditError::Invalid(_))
+        ));
+        assert_eq!(doc.source(), "a {\n  b {\n  }\n}\n");
+    }
+
+    #[test]
+    fn test_errors_leave_document_unchanged() {
+        let mut doc = doc("a 1;\na 2;\n");
+        assert!(matches!(
+            doc.set("a", &["3"]),
+            Err(EditError::Ambiguous(_, 2))
+        ));
+        assert!(matches!(doc.remove("b"), Err(EditError::NotFound(_))));
+        assert!(matches!(doc.rename("a[0]", "{"), Err(EditError::Parse(_))));
+        assert_eq!(doc.source(), "a 1;\na 2;\n");
+    }
+
+    #[test]
+    fn test_edits_use_the_pragma_options() {
+        let options = ConfOptions {
+            pragma_policy: crate::PragmaPolicy::Allow,
+            ..ConfOptions::default()
+        };
+        let source = "# confetti: c-comments\nserver {\n  port 80; /* } */\n}\nother;\n";
+        let mut doc = Document::parse(source, options).unwrap();
+        doc.remove("server").unwrap();
+        assert_eq!(doc.source(), "# confetti: c-comments\nother;\n");
+
+        doc.insert_child("other", "a { b; /* } */ }").unwrap();
+        doc.remove("other/a").unwrap();
+        assert_eq!(doc.source(), "# confetti: c-comments\nother {\n}\n");
+    }
+}
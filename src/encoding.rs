@@ -0,0 +1,228 @@
+//! Decoding of configuration files from bytes.
+//!
+//! Input is UTF-8 unless it starts with a UTF-16 byte order mark. A UTF-8
+//! byte order mark is removed rather than becoming part of the first
+//! directive name.
+
+use std::borrow::Cow;
+
+use crate::lexer::line_terminator_len;
+use crate::{ConfError, Span};
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16_LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16_BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// The encoding of a configuration file, as detected by [`detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8 without a byte order mark.
+    Utf8,
+    /// UTF-8 with a byte order mark.
+    Utf8Bom,
+    /// UTF-16, little-endian, with a byte order mark.
+    Utf16Le,
+    /// UTF-16, big-endian, with a byte order mark.
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Returns the length in bytes of the byte order mark of this encoding.
+    pub fn bom_len(self) -> usize {
+        match self {
+            Encoding::Utf8 => 0,
+            Encoding::Utf8Bom => UTF8_BOM.len(),
+            Encoding::Utf16Le | Encoding::Utf16Be => UTF16_LE_BOM.len(),
+        }
+    }
+}
+
+/// Detects the encoding of the input from its byte order mark.
+pub fn detect(input: &[u8]) -> Encoding {
+    if input.starts_with(UTF8_BOM) {
+        Encoding::Utf8Bom
+    } else if input.starts_with(UTF16_LE_BOM) {
+        Encoding::Utf16Le
+    } else if input.starts_with(UTF16_BE_BOM) {
+        Encoding::Utf16Be
+    } else {
+        Encoding::Utf8
+    }
+}
+
+/// Decodes the input to text, without its byte order mark.
+///
+/// UTF-8 input is borrowed; UTF-16 input is transcoded. Invalid input fails
+/// with [`ConfError::EncodingError`] giving the byte offset of the first
+/// invalid sequence and its line.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::encoding::decode;
+/// use confetti_rs::ConfError;
+///
+/// assert_eq!(decode(b"\xEF\xBB\xBFport 80;").unwrap(), "port 80;");
+/// assert_eq!(decode(b"\xFF\xFEa\x00;\x00").unwrap(), "a;");
+///
+/// match decode(b"a;\nb \xFF;") {
+///     Err(ConfError::EncodingError { span, line, .. }) => {
+///         assert_eq!((span.start(), line), (5, 2));
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn decode(input: &[u8]) -> Result<Cow<'_, str>, ConfError> {
+    let encoding = detect(input);
+    let offset = encoding.bom_len();
+    let body = &input[offset..];
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => match std::str::from_utf8(body) {
+            Ok(text) => Ok(Cow::Borrowed(text)),
+            Err(error) => {
+                let valid = error.valid_up_to();
+                Err(ConfError::EncodingError {
+                    span: Span::point(offset + valid),
+                    line: line_at(&body[..valid]),
+                    message: "Invalid UTF-8".to_string(),
+                })
+            }
+        },
+        Encoding::Utf16Le => decode_utf16(body, offset, u16::from_le_bytes).map(Cow::Owned),
+        Encoding::Utf16Be => decode_utf16(body, offset, u16::from_be_bytes).map(Cow::Owned),
+    }
+}
+
+/// Transcodes UTF-16 code units to a string. `offset` is the position of
+/// `body` in the input, for error positions.
+fn decode_utf16(
+    body: &[u8],
+    offset: usize,
+    from_bytes: fn([u8; 2]) -> u16,
+) -> Result<String, ConfError> {
+    let units = body
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+
+    let mut text = String::with_capacity(body.len() / 2);
+    let mut position = offset;
+    for result in char::decode_utf16(units) {
+        match result {
+            Ok(c) => {
+                text.push(c);
+                position += c.len_utf16() * 2;
+            }
+            Err(_) => {
+                return Err(ConfError::EncodingError {
+                    span: Span::point(position),
+                    line: line_at(text.as_bytes()),
+                    message: "Invalid UTF-16: unpaired surrogate".to_string(),
+                });
+            }
+        }
+    }
+
+    if !body.len().is_multiple_of(2) {
+        return Err(ConfError::EncodingError {
+            span: Span::point(offset + body.len() - 1),
+            line: line_at(text.as_bytes()),
+            message: "Invalid UTF-16: odd number of bytes".to_string(),
+        });
+    }
+    Ok(text)
+}
+
+/// Returns the 1-based line at the end of `text`, counting the same line
+/// terminators as the lexer: LF, CR, CRLF, VT, FF, NEL, LS and PS.
+pub(crate) fn line_at(text: &[u8]) -> usize {
+    let mut line = 1;
+    let mut position = 0;
+    // Every line terminator starts with one of these bytes
+    while let Some(offset) = text[position..]
+        .iter()
+        .position(|byte| matches!(byte, b'\n' | b'\r' | 0x0B | 0x0C | 0xC2 | 0xE2))
+    {
+        position += offset;
+        match line_terminator_len(text, position) {
+            Some(length) => {
+                line += 1;
+                position += length;
+            }
+            None => position += 1,
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        let mut bytes = if big_endian {
+            UTF16_BE_BOM.to_vec()
+        } else {
+            UTF16_LE_BOM.to_vec()
+        };
+        for unit in text.encode_utf16() {
+            let pair = if big_endian {
+                unit.to_be_bytes()
+            } else {
+                unit.to_le_bytes()
+            };
+            bytes.extend_from_slice(&pair);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"a"), Encoding::Utf8);
+        assert_eq!(detect(b""), Encoding::Utf8);
+        assert_eq!(detect(b"\xEF\xBB\xBFa"), Encoding::Utf8Bom);
+        assert_eq!(detect(b"\xFF\xFEa\x00"), Encoding::Utf16Le);
+        assert_eq!(detect(b"\xFE\xFF\x00a"), Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn test_decode_utf8() {
+        assert!(matches!(decode(b"a b;").unwrap(), Cow::Borrowed("a b;")));
+        assert!(matches!(
+            decode(b"\xEF\xBB\xBFa b;").unwrap(),
+            Cow::Borrowed("a b;")
+        ));
+    }
+
+    #[test]
+    fn test_decode_utf16() {
+        let text = "server caf\u{e9} {\n  emoji \u{1f600};\n}\n";
+        assert_eq!(decode(&utf16(text, false)).unwrap(), text);
+        assert_eq!(decode(&utf16(text, true)).unwrap(), text);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let cases: [(&[u8], usize, usize); 6] = [
+            (b"a;\r\nb;\rc \xC3(", 9, 3),
+            // VT, FF, NEL, LS and PS end lines as they do for the lexer
+            (
+                b"\x0B\x0C\xC2\x85\xE2\x80\xA8\xE2\x80\xA9\xC2\xA0 \xFF",
+                13,
+                6,
+            ),
+            (b"\xEF\xBB\xBF\n\xFF", 4, 2),
+            (b"abc \xE2\x82", 4, 1),
+            // An unpaired low surrogate on the second line
+            (b"\xFF\xFEa\x00\n\x00\x00\xDC", 6, 2),
+            (b"\xFE\xFF\x00a\x00", 4, 1),
+        ];
+        for (input, expected_position, expected_line) in cases {
+            match decode(input) {
+                Err(ConfError::EncodingError { span, line, .. }) => {
+                    assert_eq!((span.start(), line), (expected_position, expected_line));
+                }
+                other => panic!("Expected EncodingError for {:?}, got {:?}", input, other),
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Syntax highlighting for terminals and HTML.
+//!
+//! Both highlighters color the tokens of [`lexer::tokens`](crate::lexer::tokens)
+//! by their [`TokenClass`]. Text after a lexer error is copied without
+//! styling, so highlighting never fails. In terminal output its control
+//! characters are replaced, so that the text cannot send escape sequences of
+//! its own.
+
+use std::fmt::Write;
+
+use crate::lexer::{tokens, TokenClass};
+use crate::ConfOptions;
+
+/// A stylesheet for the output of [`to_html`].
+pub const CSS: &str = "\
+pre.confetti .cf-directive-name { color: #0550ae; font-weight: bold; }
+pre.confetti .cf-quoted-argument,
+pre.confetti .cf-triple-quoted-argument,
+pre.confetti .cf-raw-argument,
+pre.confetti .cf-heredoc-argument { color: #0a7a2f; }
+pre.confetti .cf-expression-argument { color: #8250df; }
+pre.confetti .cf-line-comment,
+pre.confetti .cf-block-comment { color: #6e7781; font-style: italic; }
+pre.confetti .cf-semicolon,
+pre.confetti .cf-open-brace,
+pre.confetti .cf-close-brace { font-weight: bold; }
+pre.confetti .cf-continuation { color: #953800; }
+";
+
+/// Returns the SGR parameters of the terminal style of a class, if it has one.
+fn ansi_style(class: TokenClass) -> Option<&'static str> {
+    match class {
+        TokenClass::DirectiveName => Some("1;34"),
+        TokenClass::QuotedArgument
+        | TokenClass::TripleQuotedArgument
+        | TokenClass::RawArgument
+        | TokenClass::HeredocArgument => Some("32"),
+        TokenClass::ExpressionArgument => Some("35"),
+        TokenClass::LineComment | TokenClass::BlockComment => Some("90"),
+        TokenClass::Semicolon | TokenClass::OpenBrace | TokenClass::CloseBrace => Some("1"),
+        TokenClass::Continuation => Some("33"),
+        TokenClass::Argument | TokenClass::Newline | TokenClass::Whitespace => None,
+    }
+}
+
+/// Highlights `input` with ANSI escape sequences for a terminal.
+///
+/// Styles are reset at the end of every line, so the output also displays
+/// correctly in pagers such as `less -R`.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::highlight::to_ansi;
+/// use confetti_rs::ConfOptions;
+///
+/// let output = to_ansi("listen 80;", ConfOptions::default());
+/// assert_eq!(output, "\x1b[1;34mlisten\x1b[0m 80\x1b[1m;\x1b[0m");
+/// ```
+pub fn to_ansi(input: &str, options: ConfOptions) -> String {
+    let mut output = String::with_capacity(input.len() * 2);
+    let mut position = 0;
+    for token in tokens(input, options) {
+        let Ok(token) = token else {
+            break;
+        };
+        let span = token.token.span;
+        position = span.end;
+        let text = &input[span];
+        let Some(style) = ansi_style(token.class) else {
+            output.push_str(text);
+            continue;
+        };
+
+        for (index, line) in text.split('\n').enumerate() {
+            if index > 0 {
+                output.push('\n');
+            }
+            // Keep a CR outside the styled text
+            let (line, cr) = match line.strip_suffix('\r') {
+                Some(line) => (line, "\r"),
+                None => (line, ""),
+            };
+            if !line.is_empty() {
+                let _ = write!(output, "\x1b[{}m{}\x1b[0m", style, line);
+            }
+            output.push_str(cr);
+        }
+    }
+    push_without_controls(&mut output, &input[position..]);
+    output
+}
+
+/// Highlights `input` as an HTML `<pre>` element.
+///
+/// Every token except whitespace and line breaks is wrapped in a `<span>`
+/// whose CSS class is `cf-` followed by the [name](TokenClass::name) of its
+/// class, and the element has the class `confetti`. [`CSS`] styles the
+/// output.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::highlight::to_html;
+/// use confetti_rs::ConfOptions;
+///
+/// let output = to_html("a <b>;", ConfOptions::default());
+/// assert_eq!(
+///     output,
+///     "<pre class=\"confetti\"><code><span class=\"cf-directive-name\">a</span> \
+///      <span class=\"cf-argument\">&lt;b&gt;</span><span class=\"cf-semicolon\">;</span>\
+///      </code></pre>"
+/// );
+/// ```
+pub fn to_html(input: &str, options: ConfOptions) -> String {
+    let mut output = String::with_capacity(input.len() * 3);
+    output.push_str("<pre class=\"confetti\"><code>");
+    let mut position = 0;
+    for token in tokens(input, options) {
+        let Ok(token) = token else {
+            break;
+        };
+        let span = token.token.span;
+        position = span.end;
+        let text = &input[span];
+        match token.class {
+            TokenClass::Whitespace | TokenClass::Newline => escape_html(&mut output, text),
+            class => {
+                let _ = write!(output, "<span class=\"cf-{}\">", class.name());
+                escape_html(&mut output, text);
+                output.push_str("</span>");
+            }
+        }
+    }
+    escape_html(&mut output, &input[position..]);
+    output.push_str("</code></pre>");
+    output
+}
+
+/// Appends text with C0 and C1 control characters other than tabs and line
+/// breaks replaced by U+FFFD.
+fn push_without_controls(output: &mut String, text: &str) {
+    for c in text.chars() {
+        if c.is_control() && !matches!(c, '\t' | '\n' | '\r') {
+            output.push(char::REPLACEMENT_CHARACTER);
+        } else {
+            output.push(c);
+        }
+    }
+}
+
+/// Appends text with the HTML special characters escaped.
+fn escape_html(output: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ansi_styles_each_line() {
+        let options = ConfOptions {
+            allow_c_style_comments: true,
+            ..ConfOptions::default()
+        };
+        let output = to_ansi("a \"x\" {\n/* one\r\ntwo */\n}", options);
+        assert_eq!(
+            output,
+            "\x1b[1;34ma\x1b[0m \x1b[32m\"x\"\x1b[0m \x1b[1m{\x1b[0m\n\
+             \x1b[90m/* one\x1b[0m\r\n\x1b[90mtwo */\x1b[0m\n\x1b[1m}\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_text_after_an_error_is_unstyled() {
+        let input = "a \"<unterminated";
+        assert_eq!(
+            to_ansi(input, ConfOptions::default()),
+            "\x1b[1;34ma\x1b[0m \"<unterminated"
+        );
+        assert_eq!(
+            to_html(input, ConfOptions::default()),
+            "<pre class=\"confetti\"><code><span class=\"cf-directive-name\">a</span> \
+             &quot;&lt;unterminated</code></pre>"
+        );
+
+        // Control characters in the unstyled text cannot reach the terminal
+        let input = "a \x1b]0;title\x07\u{9b}2J\tb\n";
+        assert_eq!(
+            to_ansi(input, ConfOptions::default()),
+            "\x1b[1;34ma\x1b[0m \u{fffd}]0;title\u{fffd}\u{fffd}2J\tb\n"
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Incremental reparsing after a text edit.
+//!
+//! An editor that reparses on every keystroke can keep the previous unit and
+//! pass each edit to [`reparse`]. When the edit lies between the braces of a
+//! block, only the body of the innermost such block is parsed again; the rest
+//! of the tree is reused, with the spans after the edit shifted. Edits at the
+//! top level, edits that touch a brace, and options with a limit on the whole
+//! input fall back to a full parse, so the result is always the unit that
+//! [`parse`] would return for the edited source.
+
+use std::ops::Range;
+
+use crate::edit::{directive_extent, Extent};
+use crate::parser::Parser;
+use crate::{ConfArgument, ConfDirective, ConfError, ConfOptions, ConfUnit, FileId, Span};
+
+/// A replacement of a range of the source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    /// The byte range of the old source that is replaced.
+    pub range: Range<usize>,
+    /// The text that replaces the range.
+    pub replacement: String,
+}
+
+impl TextEdit {
+    /// Creates an edit replacing `range` with `replacement`.
+    pub fn new(range: Range<usize>, replacement: impl Into<String>) -> Self {
+        Self {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+
+    /// Creates an edit inserting `text` at `position`.
+    pub fn insert(position: usize, text: impl Into<String>) -> Self {
+        Self::new(position..position, text)
+    }
+
+    /// Creates an edit deleting `range`.
+    pub fn delete(range: Range<usize>) -> Self {
+        Self::new(range, String::new())
+    }
+
+    /// Returns the source with the edit applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or not on character boundaries.
+    pub fn apply(&self, source: &str) -> String {
+        let mut result =
+            String::with_capacity(source.len() - self.range.len() + self.replacement.len());
+        result.push_str(&source[..self.range.start]);
+        result.push_str(&self.replacement);
+        result.push_str(&source[self.range.end..]);
+        result
+    }
+
+    /// The change in length of the source.
+    fn delta(&self) -> isize {
+        self.replacement.len() as isize - self.range.len() as isize
+    }
+}
+
+/// Reparses `source` after `edit`, reusing the parts of `previous` that the
+/// edit does not touch.
+///
+/// `previous` must be the unit parsed from `source` with the same options.
+/// The result equals a full parse of the edited source, spans included, and
+/// errors are those of the full parse. Spans keep the file of `previous`.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse, ConfOptions};
+/// use confetti_rs::incremental::{reparse, TextEdit};
+///
+/// let source = "server {\n  listen 80;\n}\nuser www;\n";
+/// let unit = parse(source, ConfOptions::default()).unwrap();
+///
+/// // Replace "80" with "8080"
+/// let edit = TextEdit::new(18..20, "8080");
+/// let unit = reparse(unit, source, &edit, &ConfOptions::default()).unwrap();
+///
+/// assert_eq!(unit.directives[0].children[0].arguments[0].value, "8080");
+/// assert_eq!(unit.directives[1].name.span.range(), 26..30);
+/// ```
+pub fn reparse(
+    mut previous: ConfUnit,
+    source: &str,
+    edit: &TextEdit,
+    options: &ConfOptions,
+) -> Result<ConfUnit, ConfError> {
+    let range = &edit.range;
+    if range.start > range.end
+        || range.end > source.len()
+        || !source.is_char_boundary(range.start)
+        || !source.is_char_boundary(range.end)
+    {
+        return Err(ConfError::ParserError {
+            span: Span::point(range.start),
+            message: format!(
+                "Invalid edit range {}..{} for input of length {}",
+                range.start,
+                range.end,
+                source.len()
+            ),
+        });
+    }
+
+    if reparse_block(&mut previous, source, edit, options).is_some() {
+        Ok(previous)
+    } else {
+        let file = file_of(&previous);
+        Parser::new(&edit.apply(source), options.clone())
+            .map_err(|error| error.in_file(file))?
+            .in_file(file)
+            .parse()
+    }
+}
+
+/// Returns the file the spans of a unit belong to.
+fn file_of(unit: &ConfUnit) -> FileId {
+    unit.directives
+        .first()
+        .map(|directive| directive.name.span.file())
+        .or_else(|| unit.comments.first().map(|comment| comment.span.file()))
+        .unwrap_or_default()
+}
+
+/// Reparses only the body of the innermost block containing the edit,
+/// returning `None` without changing the unit when a full parse is needed.
+fn reparse_block(
+    unit: &mut ConfUnit,
+    source: &str,
+    edit: &TextEdit,
+    options: &ConfOptions,
+) -> Option<()> {
+    // Counting limits apply to the whole input
+    if options.max_input_bytes != usize::MAX
+        || options.max_directives != usize::MAX
+        || options.max_tokens != usize::MAX
+    {
+        return None;
+    }
+    let options = crate::pragma::leading(source, options.clone()).ok()?;
+    let (path, block) = find_block(unit, source, edit, &options)?;
+    let (&last, ancestors) = path.split_last()?;
+
+    let mut body = String::with_capacity(block.len() - edit.range.len() + edit.replacement.len());
+    body.push_str(&source[block.start + 1..edit.range.start]);
+    body.push_str(&edit.replacement);
+    body.push_str(&source[edit.range.end..block.end]);
+
+    let file = file_of(unit);
+    let children = Parser::for_block(&body, options, path.len())
+        .and_then(|parser| parser.in_file(file).parse())
+        .ok()?
+        .directives;
+
+    let delta = edit.delta();
+    let after = edit.range.end;
+    for comment in &mut unit.comments {
+        if comment.span.start() >= after {
+            shift(&mut comment.span, delta);
+        }
+    }
+
+    let mut siblings = &mut unit.directives;
+    for &index in ancestors {
+        for directive in &mut siblings[index + 1..] {
+            shift_directive(directive, delta);
+        }
+        siblings = &mut siblings[index].children;
+    }
+    for directive in &mut siblings[last + 1..] {
+        shift_directive(directive, delta);
+    }
+
+    let directive = &mut siblings[last];
+    directive.children = children;
+    for child in &mut directive.children {
+        shift_directive(child, (block.start + 1) as isize);
+    }
+
+    Some(())
+}
+
+/// Finds the innermost block whose braces strictly enclose the edit, returning
+/// the indices leading to its directive and the range of the block in the old
+/// source, braces included.
+fn find_block(
+    unit: &ConfUnit,
+    source: &str,
+    edit: &TextEdit,
+    options: &ConfOptions,
+) -> Option<(Vec<usize>, Range<usize>)> {
+    // Only the last directive starting before the edit can contain it
+    let mut path = Vec::new();
+    let mut directives = &unit.directives;
+    loop {
+        let index = directives.partition_point(|d| d.name.span.start() < edit.range.start);
+        if index == 0 {
+            break;
+        }
+        path.push(index - 1);
+        directives = &directives[index - 1].children;
+    }
+
+    while !path.is_empty() {
+        let directive = resolve(&unit.directives, &path);
+        if let Ok(Extent {
+            block: Some(block), ..
+        }) = directive_extent(source, directive, options)
+        {
+            if block.start < edit.range.start && edit.range.end < block.end {
+                return Some((path, block));
+            }
+        }
+        path.pop();
+    }
+    None
+}
+
+/// Returns the directive at the end of a path of child indices.
+fn resolve<'a>(directives: &'a [ConfDirective], path: &[usize]) -> &'a ConfDirective {
+    let (&first, rest) = path.split_first().expect("the path is not empty");
+    rest.iter().fold(&directives[first], |directive, &index| {
+        &directive.children[index]
+    })
+}
+
+/// Shifts the spans of a directive and its descendants.
+fn shift_directive(directive: &mut ConfDirective, delta: isize) {
+    let mut stack = vec![directive];
+    while let Some(directive) = stack.pop() {
+        shift_argument(&mut directive.name, delta);
+        for argument in &mut directive.arguments {
+            shift_argument(argument, delta);
+        }
+        stack.extend(directive.children.iter_mut());
+    }
+}
+
+fn shift_argument(argument: &mut ConfArgument, delta: isize) {
+    shift(&mut argument.span, delta);
+}
+
+fn shift(span: &mut Span, delta: isize) {
+    *span = span.shifted(delta);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    /// Collects every span in a unit, in source order within each level.
+    fn spans(unit: &ConfUnit) -> Vec<Span> {
+        let mut result: Vec<Span> = unit.comments.iter().map(|c| c.span).collect();
+        let mut stack: Vec<&ConfDirective> = unit.directives.iter().rev().collect();
+        while let Some(directive) = stack.pop() {
+            result.push(directive.name.span);
+            result.extend(directive.arguments.iter().map(|a| a.span));
+            stack.extend(directive.children.iter().rev());
+        }
+        result
+    }
+
+    /// Checks that reparsing after the edit matches a full parse.
+    fn check(source: &str, edit: &TextEdit, options: &ConfOptions) {
+        let previous = match parse(source, options.clone()) {
+            Ok(unit) => unit,
+            Err(_) => return,
+        };
+        let edited = edit.apply(source);
+        let expected = parse(&edited, options.clone());
+        let actual = reparse(previous, source, edit, options);
+        match (expected, actual) {
+            (Ok(expected), Ok(actual)) => {
+                assert_eq!(actual.directives, expected.directives, "{:?}", edited);
+                let comments = |unit: &ConfUnit| -> Vec<String> {
+                    unit.comments.iter().map(|c| c.content.clone()).collect()
+                };
+                assert_eq!(comments(&actual), comments(&expected), "{:?}", edited);
+                assert_eq!(spans(&actual), spans(&expected), "{:?}", edited);
+            }
+            (Err(expected), Err(actual)) => {
+                assert_eq!(actual.to_string(), expected.to_string(), "{:?}", edited)
+            }
+            (expected, actual) => panic!(
+                "{:?}: expected {:?}, got {:?}",
+                edited,
+                expected.map(|_| ()),
+                actual.map(|_| ())
+            ),
+        }
+    }
+
+    const SOURCE: &str = "# top\nuser www;\nhttp {\n  server {\n    listen 80;\n    location / {\n      root \"/var/www\";\n    }\n  }\n  gzip on;\n}\n# end\nworker 4;\n";
+
+    #[test]
+    fn test_edit_inside_block_reparses_only_that_block() {
+        let options = ConfOptions::default();
+        let unit = parse(SOURCE, options.clone()).unwrap();
+        let position = SOURCE.find("80").unwrap();
+        let edit = TextEdit::new(position..position + 2, "8080");
+
+        let (path, block) = find_block(&unit, SOURCE, &edit, &options).unwrap();
+        assert_eq!(path, vec![1, 0]);
+        assert_eq!(&SOURCE[block.end - 1..block.end], "}");
+        assert_eq!(&SOURCE[block.start..block.start + 1], "{");
+        check(SOURCE, &edit, &options);
+
+        // Inserting a directive into the innermost block
+        let position = SOURCE.find("root").unwrap();
+        let edit = TextEdit::insert(position, "index index.html;\n      ");
+        assert_eq!(
+            find_block(&unit, SOURCE, &edit, &options).unwrap().0,
+            vec![1, 0, 1]
+        );
+        check(SOURCE, &edit, &options);
+    }
+
+    #[test]
+    fn test_edits_that_need_a_full_parse() {
+        let options = ConfOptions::default();
+        let unit = parse(SOURCE, options.clone()).unwrap();
+        let cases = [
+            // At the top level
+            TextEdit::insert(SOURCE.find("user").unwrap(), "group www;\n"),
+            // Touching the brace of a top-level block
+            TextEdit::delete(SOURCE.find('{').unwrap()..SOURCE.find('{').unwrap() + 1),
+            // In a directive head
+            TextEdit::new(
+                SOURCE.find("http").unwrap()..SOURCE.find("http").unwrap() + 4,
+                "web",
+            ),
+        ];
+        for edit in &cases {
+            assert!(
+                find_block(&unit, SOURCE, edit, &options).is_none(),
+                "{:?}",
+                edit
+            );
+            check(SOURCE, edit, &options);
+        }
+    }
+
+    #[test]
+    fn test_edits_that_break_the_block() {
+        let options = ConfOptions::default();
+        let position = SOURCE.find("gzip").unwrap();
+        for text in ["}", "{", "\"", "a {", "} b {", "x\\"] {
+            check(SOURCE, &TextEdit::insert(position, text), &options);
+        }
+
+        let unbalanced = TextEdit::insert(position, "}\n");
+        let unit = parse(SOURCE, options.clone()).unwrap();
+        assert!(reparse(unit, SOURCE, &unbalanced, &options).is_err());
+    }
+
+    #[test]
+    fn test_depth_and_limits_match_a_full_parse() {
+        let source = "a {\n  b {\n    c;\n  }\n}\n";
+        let position = source.find("c;").unwrap();
+        let deeper = TextEdit::insert(position, "d { e; }\n    ");
+
+        let options = ConfOptions {
+            max_depth: 3,
+            ..ConfOptions::default()
+        };
+        check(source, &deeper, &options);
+        let unit = parse(source, options.clone()).unwrap();
+        assert!(reparse(unit, source, &deeper, &options).is_err());
+
+        let options = ConfOptions {
+            max_directives: 4,
+            ..ConfOptions::default()
+        };
+        check(source, &deeper, &options);
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        let unit = parse("a;", ConfOptions::default()).unwrap();
+        let edit = TextEdit::insert(5, "b");
+        assert!(reparse(unit, "a;", &edit, &ConfOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_random_edits_match_a_full_parse() {
+        const PIECES: &[&str] = &[
+            "a",
+            "b c",
+            ";",
+            "\n",
+            " ",
+            "{",
+            "}",
+            "\"",
+            "\"x y\"",
+            "#",
+            "# note\n",
+            "\\",
+            "\\\n",
+            "k {\n v;\n}\n",
+            "'",
+            "é",
+        ];
+
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        for options in [ConfOptions::default(), ConfOptions::all_extensions()] {
+            let mut source = SOURCE.to_string();
+            let mut unit = parse(&source, options.clone()).unwrap();
+            for _ in 0..1000 {
+                let boundaries: Vec<usize> = source
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain(std::iter::once(source.len()))
+                    .collect();
+                let start = boundaries[next(boundaries.len())];
+                let end = if next(2) == 0 {
+                    start
+                } else {
+                    let candidates: Vec<usize> = boundaries
+                        .iter()
+                        .copied()
+                        .filter(|&b| b >= start && b <= start + 6)
+                        .collect();
+                    candidates[next(candidates.len())]
+                };
+                let edit = TextEdit::new(start..end, PIECES[next(PIECES.len())]);
+
+                check(&source, &edit, &options);
+                // Keep editing from inputs that still parse
+                let edited = edit.apply(&source);
+                if let Ok(next_unit) = reparse(unit.clone(), &source, &edit, &options) {
+                    source = edited;
+                    unit = next_unit;
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
-use super::ConfError;
+use super::{ConfError, ConfOptions, Limit, Span};
+use crate::source_map::MAX_OFFSET;
+use std::borrow::Cow;
 use std::ops::Range;
 use unicode_general_category::{get_general_category, GeneralCategory};
 
@@ -36,10 +38,28 @@ pub struct Token {
     pub is_quoted: bool,
     /// Whether the token is triple-quoted.
     pub is_triple_quoted: bool,
+    /// Whether the token is a raw string.
+    pub is_raw: bool,
+    /// Whether the token is a heredoc.
+    pub is_heredoc: bool,
+    /// For comments, the delimiter that started the comment.
+    pub comment_style: Option<CommentStyle>,
     /// Whether the token is an expression.
     pub is_expression: bool,
 }
 
+/// The delimiter that started a comment, as recorded on comment tokens.
+///
+/// Indexes refer to the delimiters of
+/// [`ConfOptions::effective_comment_syntax`](super::ConfOptions::effective_comment_syntax).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// A line comment opened by the line prefix at this index.
+    Line(usize),
+    /// A block comment delimited by the pair at this index.
+    Block(usize),
+}
+
 /// A lexer for the configuration language.
 pub struct Lexer<'a> {
     /// The input string.
@@ -47,17 +67,42 @@ pub struct Lexer<'a> {
     /// The current position in the input string.
     position: usize,
     /// The options for the lexer.
-    options: super::ConfOptions,
+    options: Cow<'a, ConfOptions>,
+    /// The number of tokens returned so far, excluding `Eof`.
+    tokens: usize,
+    /// Whether a byte can start a comment delimiter.
+    comment_starts: [bool; 256],
 }
 
 impl<'a> Lexer<'a> {
     /// Creates a new lexer.
     pub fn new(input: &'a str, options: super::ConfOptions) -> Self {
-        Self {
+        Self::with_options(input, Cow::Owned(options))
+    }
+
+    /// Creates a lexer that borrows its options, for callers that lex many
+    /// short inputs with the same options.
+    pub(crate) fn borrowing(input: &'a str, options: &'a ConfOptions) -> Self {
+        Self::with_options(input, Cow::Borrowed(options))
+    }
+
+    fn with_options(input: &'a str, options: Cow<'a, ConfOptions>) -> Self {
+        let mut lexer = Self {
             input,
             position: 0,
             options,
+            tokens: 0,
+            comment_starts: [false; 256],
+        };
+        let starts: Vec<u8> = lexer
+            .line_prefixes()
+            .chain(lexer.block_delimiters().map(|(open, _)| open))
+            .filter_map(|delimiter| delimiter.bytes().next())
+            .collect();
+        for byte in starts {
+            lexer.comment_starts[byte as usize] = true;
         }
+        lexer
     }
 
     /// Returns the input string.
@@ -65,124 +110,186 @@ impl<'a> Lexer<'a> {
         self.input
     }
 
+    /// Returns the current position in the input string.
+    ///
+    /// This can be past the end of the last token's span, since a line
+    /// continuation token covers only its backslash.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns where the next token starts, past any whitespace, and whether
+    /// it is a comment.
+    pub(crate) fn peek_token_start(&mut self) -> (usize, bool) {
+        self.skip_whitespace();
+        (self.position, self.is_comment())
+    }
+
     /// Returns the next token in the input string.
+    ///
+    /// Fails with [`ConfError::LimitExceeded`] when the input, the number of
+    /// tokens, or the length of an argument or comment exceeds its limit in
+    /// the options.
     pub fn next_token(&mut self) -> Result<Token, ConfError> {
+        // Spans hold 32-bit offsets, which bounds the input whatever the options
+        let max_input_bytes = self.options.max_input_bytes.min(MAX_OFFSET);
+        if self.position == 0 && self.input.len() > max_input_bytes {
+            return Err(limit_exceeded(
+                max_input_bytes,
+                Limit::InputBytes,
+                max_input_bytes,
+            ));
+        }
+
+        let token = self.scan_token()?;
+        if token.token_type == TokenType::Eof {
+            return Ok(token);
+        }
+
+        let options = &self.options;
+        self.tokens += 1;
+        if self.tokens > options.max_tokens {
+            return Err(limit_exceeded(
+                token.span.start,
+                Limit::Tokens,
+                options.max_tokens,
+            ));
+        }
+        let length = token.span.end - token.span.start;
+        match token.token_type {
+            TokenType::Argument if length > options.max_argument_length => Err(limit_exceeded(
+                token.span.start,
+                Limit::ArgumentLength,
+                options.max_argument_length,
+            )),
+            TokenType::Comment if length > options.max_comment_length => Err(limit_exceeded(
+                token.span.start,
+                Limit::CommentLength,
+                options.max_comment_length,
+            )),
+            _ => Ok(token),
+        }
+    }
+
+    /// Scans the next token without checking resource limits.
+    fn scan_token(&mut self) -> Result<Token, ConfError> {
         // Check for forbidden characters
         if let Some(c) = self.current_char() {
             if self.is_forbidden_char(c) {
                 return Err(ConfError::LexerError {
-                    position: self.position,
+                    span: Span::point(self.position),
                     message: format!("Forbidden character: U+{:04X}", c as u32),
                 });
             }
         }
 
-        // Skip whitespace
-        while self.position < self.input.len() && self.is_whitespace() && !self.is_newline() {
-            self.advance();
-        }
+        self.skip_whitespace();
 
         // Check for end of input
-        if self.position >= self.input.len() {
+        let bytes = self.input.as_bytes();
+        let Some(&byte) = bytes.get(self.position) else {
             return Ok(Token {
                 token_type: TokenType::Eof,
                 span: self.position..self.position,
                 is_quoted: false,
                 is_triple_quoted: false,
+                is_raw: false,
+                is_heredoc: false,
+                comment_style: None,
                 is_expression: false,
             });
-        }
+        };
 
         // Process comments
         if self.is_comment() {
             let start = self.position;
-            self.scan_comment()?;
+            let comment_style = self.scan_comment()?;
             return Ok(Token {
                 token_type: TokenType::Comment,
                 span: start..self.position,
                 is_quoted: false,
                 is_triple_quoted: false,
+                is_raw: false,
+                is_heredoc: false,
+                comment_style: Some(comment_style),
                 is_expression: false,
             });
         }
 
-        // Determine the token type based on the current character
+        // Determine the token type based on the current byte
         let start = self.position;
-        let (token_type, is_quoted, is_triple_quoted, is_expression) = match self.current_char() {
-            Some(c) if self.is_line_terminator(c) => {
-                self.advance();
-                // Handle CRLF as a single newline
-                if c == '\r' && self.current_char() == Some('\n') {
-                    self.advance();
-                }
-                (TokenType::Newline, false, false, false)
+        let mut token = Token {
+            token_type: TokenType::Argument,
+            span: start..start,
+            is_quoted: false,
+            is_triple_quoted: false,
+            is_raw: false,
+            is_heredoc: false,
+            comment_style: None,
+            is_expression: false,
+        };
+        match byte {
+            _ if self.is_newline() => {
+                // CRLF counts as a single newline
+                self.position += self.line_terminator_len(start).unwrap_or(1);
+                token.token_type = TokenType::Newline;
             }
-            Some(';') => {
-                self.advance();
-                (TokenType::Semicolon, false, false, false)
+            b';' => {
+                self.position += 1;
+                token.token_type = TokenType::Semicolon;
             }
-            Some('{') => {
-                self.advance();
-                (TokenType::LeftCurlyBrace, false, false, false)
+            b'{' => {
+                self.position += 1;
+                token.token_type = TokenType::LeftCurlyBrace;
             }
-            Some('}') => {
-                self.advance();
-                (TokenType::RightCurlyBrace, false, false, false)
+            b'}' => {
+                self.position += 1;
+                token.token_type = TokenType::RightCurlyBrace;
             }
-            Some('\\') => {
-                self.advance();
+            b'\\' => {
                 // Check if this is a line continuation
-                if self
-                    .current_char()
-                    .is_some_and(|c| self.is_line_terminator(c))
-                {
-                    let continuation_start = start;
-                    // Skip the newline
-                    self.advance();
-                    // Handle CRLF as a single newline
-                    if self.input.as_bytes().get(self.position - 1) == Some(&b'\r')
-                        && self.current_char() == Some('\n')
-                    {
-                        self.advance();
+                if let Some(length) = self.line_terminator_len(start + 1) {
+                    if !self.options.allow_line_continuations {
+                        return Err(self.line_continuations_disabled(start));
                     }
+                    self.position = start + 1 + length;
 
                     // Skip any whitespace after the line continuation
-                    while self.current_char().is_some_and(|_| self.is_whitespace()) {
-                        self.advance();
-                    }
+                    self.skip_whitespace();
 
                     // Return the continuation token
-                    return Ok(Token {
-                        token_type: TokenType::Continuation,
-                        span: continuation_start..continuation_start + 1, // Только обратный слеш
-                        is_quoted: false,
-                        is_triple_quoted: false,
-                        is_expression: false,
-                    });
-                } else {
-                    // This is a backslash that's part of an argument
-                    self.position = start; // Rewind
-                    let is_expression = self.scan_argument()?;
-                    (TokenType::Argument, false, false, is_expression)
+                    token.token_type = TokenType::Continuation;
+                    token.span = start..start + 1; // Только обратный слеш
+                    return Ok(token);
                 }
+
+                // This is a backslash that's part of an argument
+                token.is_expression = self.scan_argument()?;
             }
-            Some('"') => {
+            b'"' => {
                 let (is_triple_quoted, is_expression) = self.scan_quoted_argument()?;
-                (TokenType::Argument, true, is_triple_quoted, is_expression)
+                token.is_quoted = true;
+                token.is_triple_quoted = is_triple_quoted;
+                token.is_expression = is_expression;
+            }
+            b'r' if self.options.allow_raw_strings
+                && raw_string_hashes(&bytes[start..]).is_some() =>
+            {
+                token.is_quoted = true;
+                token.is_raw = true;
+                token.is_expression = self.scan_raw_string()?;
+            }
+            b'<' if self.options.allow_heredocs && heredoc_tag_len(&bytes[start..]).is_some() => {
+                token.is_heredoc = true;
+                self.scan_heredoc()?;
             }
             _ => {
-                let is_expression = self.scan_argument()?;
-                (TokenType::Argument, false, false, is_expression)
+                token.is_expression = self.scan_argument()?;
             }
-        };
+        }
 
-        Ok(Token {
-            token_type,
-            span: start..self.position,
-            is_quoted,
-            is_triple_quoted,
-            is_expression,
-        })
+        token.span = start..self.position;
+        Ok(token)
     }
 
     /// Returns the current character in the input string.
@@ -210,8 +317,27 @@ impl<'a> Lexer<'a> {
 
     /// Advances the position by one character.
     fn advance(&mut self) {
-        if let Some(c) = self.current_char() {
-            self.position += c.len_utf8();
+        // The input is valid UTF-8, so the leading byte gives the width
+        if let Some(&byte) = self.input.as_bytes().get(self.position) {
+            self.position += match byte {
+                0x00..=0x7F => 1,
+                0x80..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                _ => 4,
+            };
+        }
+    }
+
+    /// Skips whitespace other than line terminators.
+    fn skip_whitespace(&mut self) {
+        let bytes = self.input.as_bytes();
+        while let Some(&byte) = bytes.get(self.position) {
+            match byte {
+                b' ' | b'\t' => self.position += 1,
+                0x00..=0x7F => break,
+                _ if self.is_whitespace() => self.advance(),
+                _ => break,
+            }
         }
     }
 
@@ -236,10 +362,24 @@ impl<'a> Lexer<'a> {
         )
     }
 
+    /// Returns the length in bytes of the line terminator at `position`, if
+    /// there is one. CRLF counts as a single line terminator.
+    fn line_terminator_len(&self, position: usize) -> Option<usize> {
+        line_terminator_len(self.input.as_bytes(), position)
+    }
+
     /// Returns whether the current character is a newline character.
     fn is_newline(&self) -> bool {
-        self.current_char()
-            .is_some_and(|c| self.is_line_terminator(c))
+        self.line_terminator_len(self.position).is_some()
+    }
+
+    /// Returns the error for a line continuation when they are disabled.
+    fn line_continuations_disabled(&self, position: usize) -> ConfError {
+        ConfError::LexerError {
+            span: Span::point(position),
+            message: "Line continuations are not allowed (allow_line_continuations is disabled)"
+                .to_string(),
+        }
     }
 
     /// Returns whether the character is a forbidden character.
@@ -248,6 +388,11 @@ impl<'a> Lexer<'a> {
     /// with general category Control, Surrogate, and Unassigned, excluding characters
     /// with the Whitespace property.
     fn is_forbidden_char(&self, c: char) -> bool {
+        // ASCII needs no category lookup, and has no bidi characters
+        if c.is_ascii() {
+            return is_forbidden_ascii(c as u8);
+        }
+
         // Use unicode-general-category for proper Unicode category detection
         let category = get_general_category(c);
 
@@ -285,85 +430,168 @@ impl<'a> Lexer<'a> {
 
     /// Returns whether the current character is a comment character.
     fn is_comment(&self) -> bool {
-        self.current_char().is_some_and(|c| {
-            c == '#'
-                || (self.options.allow_c_style_comments
-                    && c == '/'
-                    && (self.next_char() == Some('*') || self.next_char() == Some('/')))
-        })
+        self.match_comment().is_some()
     }
 
-    /// Scans a comment.
-    fn scan_comment(&mut self) -> Result<(), ConfError> {
-        let start = self.position;
-        match self.current_char() {
-            Some('#') => {
-                // Single-line comment with #
-                self.advance();
-                while let Some(c) = self.current_char() {
-                    if self.is_line_terminator(c) {
-                        break;
-                    }
-                    if self.is_forbidden_char(c) {
-                        return Err(ConfError::LexerError {
-                            position: self.position,
-                            message: format!("Forbidden character in comment: U+{:04X}", c as u32),
-                        });
-                    }
-                    self.advance();
-                }
-            }
-            Some('/') if self.next_char() == Some('/') && self.options.allow_c_style_comments => {
-                // C-style single-line comment with //
-                self.advance(); // Skip first '/'
-                self.advance(); // Skip second '/'
-                while let Some(c) = self.current_char() {
-                    if self.is_line_terminator(c) {
-                        break;
-                    }
-                    if self.is_forbidden_char(c) {
-                        return Err(ConfError::LexerError {
-                            position: self.position,
-                            message: format!("Forbidden character in comment: U+{:04X}", c as u32),
-                        });
-                    }
-                    self.advance();
-                }
+    /// Returns the line prefixes in effect, with `//` for C-style comments.
+    fn line_prefixes(&self) -> impl Iterator<Item = &str> {
+        let c_style = self.options.allow_c_style_comments.then_some("//");
+        self.options
+            .comment_syntax
+            .line_prefixes
+            .iter()
+            .map(String::as_str)
+            .chain(c_style)
+    }
+
+    /// Returns the block comment delimiters in effect, with `/*` and `*/`
+    /// for C-style comments.
+    fn block_delimiters(&self) -> impl Iterator<Item = (&str, &str)> {
+        let c_style = self.options.allow_c_style_comments.then_some(("/*", "*/"));
+        self.options
+            .comment_syntax
+            .block_delimiters
+            .iter()
+            .map(|(open, close)| (open.as_str(), close.as_str()))
+            .chain(c_style)
+    }
+
+    /// Returns the longest comment delimiter at the current position and its
+    /// length.
+    fn match_comment(&self) -> Option<(CommentStyle, usize)> {
+        let rest = &self.input.as_bytes()[self.position..];
+        if !rest
+            .first()
+            .is_some_and(|&byte| self.comment_starts[byte as usize])
+        {
+            return None;
+        }
+
+        let lines = self
+            .line_prefixes()
+            .enumerate()
+            .map(|(index, prefix)| (CommentStyle::Line(index), prefix));
+        let blocks = self
+            .block_delimiters()
+            .enumerate()
+            .map(|(index, (open, _))| (CommentStyle::Block(index), open));
+        let mut longest: Option<(CommentStyle, usize)> = None;
+        for (style, delimiter) in lines.chain(blocks) {
+            if !delimiter.is_empty()
+                && rest.starts_with(delimiter.as_bytes())
+                && longest.is_none_or(|(_, length)| delimiter.len() > length)
+            {
+                longest = Some((style, delimiter.len()));
             }
-            Some('/') if self.next_char() == Some('*') && self.options.allow_c_style_comments => {
-                // Multi-line comment with /* */
-                self.advance(); // Skip '/'
-                self.advance(); // Skip '*'
-                let mut found_end = false;
-                while let Some(c) = self.current_char() {
-                    if self.is_forbidden_char(c) {
+        }
+        longest
+    }
+
+    /// Advances over plain text up to `end`, checking every character that is
+    /// not printable ASCII. Printable ASCII is skipped eight bytes at a time.
+    ///
+    /// Returns whether the scan stopped early at a line terminator, which
+    /// only happens with [`LineTerminators::Stop`].
+    fn skip_text(
+        &mut self,
+        end: usize,
+        context: &str,
+        line_terminators: LineTerminators,
+    ) -> Result<bool, ConfError> {
+        let bytes = self.input.as_bytes();
+        loop {
+            self.position += printable_ascii_len(&bytes[self.position..end]);
+            let Some(c) = self.current_char().filter(|_| self.position < end) else {
+                return Ok(false);
+            };
+
+            if self.is_line_terminator(c) {
+                match line_terminators {
+                    LineTerminators::Stop => return Ok(true),
+                    LineTerminators::Reject => {
                         return Err(ConfError::LexerError {
-                            position: self.position,
-                            message: format!("Forbidden character in comment: U+{:04X}", c as u32),
-                        });
+                            span: Span::point(self.position),
+                            message: "Newline in quoted string".to_string(),
+                        })
                     }
-                    if c == '*' && self.next_char() == Some('/') {
-                        self.advance(); // Skip '*'
-                        self.advance(); // Skip '/'
-                        found_end = true;
-                        break;
-                    }
-                    self.advance();
-                }
-                if !found_end {
-                    return Err(ConfError::LexerError {
-                        position: start,
-                        message: "Unterminated multi-line comment".to_string(),
-                    });
+                    LineTerminators::Allow => {}
                 }
+            } else if self.is_forbidden_char(c) {
+                return Err(ConfError::LexerError {
+                    span: Span::point(self.position),
+                    message: format!("Forbidden character in {}: U+{:04X}", context, c as u32),
+                });
             }
-            _ => {
+            self.advance();
+        }
+    }
+
+    /// Scans a comment, returning the delimiter that started it.
+    fn scan_comment(&mut self) -> Result<CommentStyle, ConfError> {
+        let start = self.position;
+        let Some((style, length)) = self.match_comment() else {
+            return Err(ConfError::LexerError {
+                span: Span::point(start),
+                message: "Expected comment".to_string(),
+            });
+        };
+        self.position += length;
+
+        let CommentStyle::Block(index) = style else {
+            self.skip_line_comment()?;
+            return Ok(style);
+        };
+        let mut depth = 1;
+        while depth > 0 {
+            let Some((delimiter, length, opens)) = self.next_block_delimiter(index) else {
+                // A forbidden character is reported before the missing end
+                let bytes = self.input.as_bytes();
+                self.skip_text(bytes.len(), "comment", LineTerminators::Allow)?;
                 return Err(ConfError::LexerError {
-                    position: start,
-                    message: "Expected comment".to_string(),
+                    span: Span::point(start),
+                    message: "Unterminated multi-line comment".to_string(),
                 });
+            };
+            self.skip_text(delimiter, "comment", LineTerminators::Allow)?;
+            self.position = delimiter + length;
+            if opens {
+                depth += 1;
+            } else {
+                depth -= 1;
+            }
+        }
+        Ok(style)
+    }
+
+    /// Finds the next delimiter of the block comment pair at `index`, giving
+    /// its position, its length and whether it opens a nested comment.
+    /// Opening delimiters are only looked for when blocks nest.
+    fn next_block_delimiter(&self, index: usize) -> Option<(usize, usize, bool)> {
+        let (open, close) = self.block_delimiters().nth(index)?;
+        let rest = &self.input.as_bytes()[self.position..];
+        let close_at = memchr::memmem::find(rest, close.as_bytes());
+        let open_at = if self.options.comment_syntax.nestable_blocks {
+            memchr::memmem::find(rest, open.as_bytes())
+        } else {
+            None
+        };
+        match (open_at, close_at) {
+            (Some(open_at), Some(close_at)) if open_at < close_at => {
+                Some((self.position + open_at, open.len(), true))
             }
+            (_, Some(close_at)) => Some((self.position + close_at, close.len(), false)),
+            _ => None,
         }
+    }
+
+    /// Skips the text of a single-line comment, up to its line terminator.
+    fn skip_line_comment(&mut self) -> Result<(), ConfError> {
+        // Jump to the next CR or LF; any other line terminator is found while
+        // checking the text before it
+        let bytes = self.input.as_bytes();
+        let end = memchr::memchr2(b'\n', b'\r', &bytes[self.position..])
+            .map_or(bytes.len(), |offset| self.position + offset);
+        self.skip_text(end, "comment", LineTerminators::Stop)?;
         Ok(())
     }
 
@@ -375,164 +603,623 @@ impl<'a> Lexer<'a> {
         // Check for triple quote
         let is_triple_quoted = self.current_char() == Some('"') && self.next_char() == Some('"');
         if is_triple_quoted {
-            self.advance(); // Skip second quote
-            self.advance(); // Skip third quote
+            if !self.options.allow_triple_quotes {
+                return Err(ConfError::LexerError {
+                    span: Span::point(start),
+                    message:
+                        "Triple-quoted strings are not allowed (allow_triple_quotes is disabled)"
+                            .to_string(),
+                });
+            }
+            self.position += 2; // Skip second and third quotes
         }
 
-        let mut found_end = false;
-        while let Some(c) = self.current_char() {
-            if self.is_forbidden_char(c) && !(is_triple_quoted && self.is_line_terminator(c)) {
+        // In triple-quoted strings, we allow line terminators
+        let line_terminators = if is_triple_quoted {
+            LineTerminators::Allow
+        } else {
+            LineTerminators::Reject
+        };
+
+        let bytes = self.input.as_bytes();
+        loop {
+            // Jump to the next quote or backslash, checking the text before it
+            let Some(offset) = memchr::memchr2(b'"', b'\\', &bytes[self.position..]) else {
+                self.skip_text(bytes.len(), "quoted argument", line_terminators)?;
                 return Err(ConfError::LexerError {
-                    position: self.position,
-                    message: format!("Forbidden character in quoted argument: U+{:04X}", c as u32),
+                    span: Span::point(start),
+                    message: if is_triple_quoted {
+                        "Unterminated triple-quoted string".to_string()
+                    } else {
+                        "Unterminated quoted string".to_string()
+                    },
                 });
-            }
+            };
+            let end = self.position + offset;
+            self.skip_text(end, "quoted argument", line_terminators)?;
 
-            if c == '\\' {
+            if bytes[end] == b'\\' {
                 // Handle escape sequence
-                self.advance(); // Skip backslash
-                if let Some(escaped) = self.current_char() {
+                self.position = end + 1; // Skip backslash
+                match self.line_terminator_len(self.position) {
                     // In quoted arguments, we allow escaping any character
                     // Line continuations are handled specially
-                    if is_triple_quoted && self.is_line_terminator(escaped) {
+                    Some(length) if is_triple_quoted => {
                         // Line continuation in triple-quoted string
-                        self.advance(); // Skip the line terminator
-                                        // Handle CRLF as a single newline
-                        if escaped == '\r' && self.current_char() == Some('\n') {
-                            self.advance();
+                        if !self.options.allow_line_continuations {
+                            return Err(self.line_continuations_disabled(end));
                         }
-                    } else {
-                        self.advance(); // Skip escaped character
+                        self.position += length;
+                    }
+                    _ if self.position < bytes.len() => self.advance(), // Skip escaped character
+                    _ => {
+                        return Err(ConfError::LexerError {
+                            span: Span::point(self.position),
+                            message: "Unterminated escape sequence".to_string(),
+                        });
                     }
-                } else {
-                    return Err(ConfError::LexerError {
-                        position: self.position,
-                        message: "Unterminated escape sequence".to_string(),
-                    });
                 }
-            } else if c == '"' {
-                if is_triple_quoted {
-                    // Check for triple quote end
-                    self.advance(); // Skip first quote
-                    if self.current_char() == Some('"') {
-                        self.advance(); // Skip second quote
-                        if self.current_char() == Some('"') {
-                            self.advance(); // Skip third quote
-                            found_end = true;
-                            break;
-                        }
+            } else if is_triple_quoted {
+                // Check for triple quote end; the quotes consumed before a
+                // shorter run are content
+                self.position = end + 1;
+                if bytes.get(self.position) == Some(&b'"') {
+                    self.position += 1;
+                    if bytes.get(self.position) == Some(&b'"') {
+                        self.position += 1;
+                        break;
                     }
-                    // Not a triple quote end, rewind position (using saturating_sub for safety)
-                    self.position = self.position.saturating_sub(1);
-                } else {
-                    self.advance(); // Skip closing quote
-                    found_end = true;
-                    break;
                 }
             } else {
-                // In triple-quoted strings, we allow line terminators
-                if !is_triple_quoted && self.is_line_terminator(c) {
-                    return Err(ConfError::LexerError {
-                        position: self.position,
-                        message: "Newline in quoted string".to_string(),
-                    });
-                }
-                self.advance();
+                self.position = end + 1; // Skip closing quote
+                break;
             }
         }
 
-        if !found_end {
-            return Err(ConfError::LexerError {
-                position: start,
-                message: if is_triple_quoted {
-                    "Unterminated triple-quoted string".to_string()
-                } else {
-                    "Unterminated quoted string".to_string()
-                },
-            });
-        }
-
         // Check if this is an expression argument
-        let is_expression = if self.options.allow_expression_arguments {
-            self.current_char() == Some('(')
-        } else {
-            false
-        };
+        let is_expression =
+            self.options.allow_expression_arguments && bytes.get(self.position) == Some(&b'(');
 
         Ok((is_triple_quoted, is_expression))
     }
 
-    /// Scans an argument.
-    fn scan_argument(&mut self) -> Result<bool, ConfError> {
+    /// Scans a raw string, `r"..."` or `r#"..."#` with any number of `#`.
+    ///
+    /// Backslashes are ordinary characters and line terminators are allowed.
+    /// The string ends at the first quote followed by as many `#` as opened it.
+    fn scan_raw_string(&mut self) -> Result<bool, ConfError> {
         let start = self.position;
-        while let Some(c) = self.current_char() {
-            // Arguments are terminated by whitespace, reserved punctuators, or EOF
-            if c.is_whitespace()
-                || c == ';'
-                || c == '{'
-                || c == '}'
-                || c == '('
-                || c == '"'
-                || c == '#'
-            {
+        let bytes = self.input.as_bytes();
+        let hashes = raw_string_hashes(&bytes[start..]).unwrap_or_default();
+        self.position += 2 + hashes; // Skip 'r', the hashes and the quote
+
+        loop {
+            let Some(offset) = memchr::memchr(b'"', &bytes[self.position..]) else {
+                self.skip_text(bytes.len(), "raw string", LineTerminators::Allow)?;
+                return Err(ConfError::LexerError {
+                    span: Span::point(start),
+                    message: "Unterminated raw string".to_string(),
+                });
+            };
+            let end = self.position + offset;
+            self.skip_text(end, "raw string", LineTerminators::Allow)?;
+            self.position = end + 1;
+
+            // A quote with fewer hashes than the opening is content
+            let closing = bytes[self.position..]
+                .iter()
+                .take(hashes)
+                .take_while(|&&byte| byte == b'#')
+                .count();
+            if closing == hashes {
+                self.position += hashes;
                 break;
             }
+        }
 
-            if self.is_forbidden_char(c) {
+        // Check if this is an expression argument
+        let is_expression =
+            self.options.allow_expression_arguments && bytes.get(self.position) == Some(&b'(');
+
+        Ok(is_expression)
+    }
+
+    /// Scans a heredoc: `<<TAG` at the end of a line, then every line up to
+    /// the first one holding only `TAG` after optional indentation. `TAG` may
+    /// be followed on that line by anything that cannot continue it, such as
+    /// `;` or `}`.
+    ///
+    /// Every non-blank line of the body must start with the indentation of
+    /// the closing `TAG`.
+    fn scan_heredoc(&mut self) -> Result<(), ConfError> {
+        let start = self.position;
+        let bytes = self.input.as_bytes();
+        let tag_len = heredoc_tag_len(&bytes[start..]).unwrap_or_default();
+        let tag = &self.input[start + 2..start + 2 + tag_len];
+        self.position += 2 + tag_len;
+
+        self.skip_whitespace();
+        let Some(length) = self.line_terminator_len(self.position) else {
+            return Err(ConfError::LexerError {
+                span: Span::point(self.position),
+                message: "Expected a line break after the heredoc delimiter".to_string(),
+            });
+        };
+        self.position += length;
+        let body = self.position;
+
+        // Find the closing line
+        let mut line_start = body;
+        let mut closing = None;
+        for (line, terminator) in split_lines(&self.input[body..]) {
+            if let Some(indent) = heredoc_closing_indent(line, tag) {
+                closing = Some((line_start, indent));
+                break;
+            }
+            line_start += line.len() + terminator.len();
+        }
+        let Some((closing_start, indent)) = closing else {
+            self.skip_text(bytes.len(), "heredoc", LineTerminators::Allow)?;
+            return Err(ConfError::LexerError {
+                span: Span::point(start),
+                message: format!("Unterminated heredoc: missing closing '{}'", tag),
+            });
+        };
+        self.skip_text(closing_start, "heredoc", LineTerminators::Allow)?;
+        // Errors below are reported past the closing line, which a reader
+        // that has seen only part of the input takes as a sign to read more
+        self.position = closing_start + indent + tag.len();
+
+        // Check the indentation of the body against the closing line
+        let indentation = &self.input[closing_start..closing_start + indent];
+        let mut line_start = body;
+        for (line, terminator) in split_lines(&self.input[body..closing_start]) {
+            let is_blank = line.bytes().all(|byte| byte == b' ' || byte == b'\t');
+            if !is_blank && !line.starts_with(indentation) {
                 return Err(ConfError::LexerError {
-                    position: self.position,
-                    message: format!("Forbidden character in argument: U+{:04X}", c as u32),
+                    span: Span::point(line_start),
+                    message: "Heredoc line is indented less than its closing delimiter".to_string(),
                 });
             }
+            line_start += line.len() + terminator.len();
+        }
+        Ok(())
+    }
 
-            if c == '\\' {
-                // Handle escape sequence
-                self.advance(); // Skip backslash
-                if let Some(escaped) = self.current_char() {
-                    if self.is_line_terminator(escaped) {
+    /// Scans an argument.
+    fn scan_argument(&mut self) -> Result<bool, ConfError> {
+        let start = self.position;
+        let bytes = self.input.as_bytes();
+        while let Some(&byte) = bytes.get(self.position) {
+            match byte {
+                // Arguments are terminated by whitespace, reserved punctuators, or EOF
+                b' ' | b'\t' | b'\n' | 0x0B | 0x0C | b'\r' | b';' | b'{' | b'}' | b'(' | b'"' => {
+                    break
+                }
+                b'#' if self.is_comment() => break,
+                b'\\' => {
+                    // Handle escape sequence
+                    let backslash = self.position;
+                    self.position += 1; // Skip backslash
+                    if let Some(length) = self.line_terminator_len(self.position) {
                         // Line continuation
-                        self.advance(); // Skip the line terminator
-                                        // Handle CRLF as a single newline
-                        if escaped == '\r' && self.current_char() == Some('\n') {
-                            self.advance();
+                        if !self.options.allow_line_continuations {
+                            return Err(self.line_continuations_disabled(backslash));
                         }
+                        self.position += length;
                         // Skip any whitespace after the line continuation
-                        while self.current_char().is_some_and(|_| self.is_whitespace()) {
-                            self.advance();
-                        }
-                    } else {
+                        self.skip_whitespace();
+                    } else if self.position < bytes.len() {
                         self.advance(); // Skip escaped character
+                    } else {
+                        return Err(ConfError::LexerError {
+                            span: Span::point(self.position),
+                            message: "Unterminated escape sequence".to_string(),
+                        });
                     }
-                } else {
+                }
+                0x00..=0x7F if is_forbidden_ascii(byte) => {
                     return Err(ConfError::LexerError {
-                        position: self.position,
-                        message: "Unterminated escape sequence".to_string(),
+                        span: Span::point(self.position),
+                        message: format!("Forbidden character in argument: U+{:04X}", byte),
                     });
                 }
-            } else {
-                self.advance();
+                0x00..=0x7F => self.position += 1,
+                _ => {
+                    // Non-ASCII characters get the full Unicode checks
+                    let c = self.current_char().unwrap_or_default();
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    if self.is_forbidden_char(c) {
+                        return Err(ConfError::LexerError {
+                            span: Span::point(self.position),
+                            message: format!("Forbidden character in argument: U+{:04X}", c as u32),
+                        });
+                    }
+                    self.advance();
+                }
             }
         }
 
         // If we didn't advance at all, this is an error
         if self.position == start {
             return Err(ConfError::LexerError {
-                position: start,
+                span: Span::point(start),
                 message: "Expected argument".to_string(),
             });
         }
 
         // Check if this is an expression argument
-        let is_expression = if self.options.allow_expression_arguments {
-            self.current_char() == Some('(')
-        } else {
-            false
-        };
+        let is_expression =
+            self.options.allow_expression_arguments && bytes.get(self.position) == Some(&b'(');
 
         Ok(is_expression)
     }
 }
 
+/// The role of a token in the source, as used for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// The first argument of a directive, which names it.
+    DirectiveName,
+    /// An unquoted argument.
+    Argument,
+    /// A quoted argument.
+    QuotedArgument,
+    /// A triple-quoted argument.
+    TripleQuotedArgument,
+    /// A raw string argument.
+    RawArgument,
+    /// A heredoc argument.
+    HeredocArgument,
+    /// An expression argument.
+    ExpressionArgument,
+    /// A comment opened by a line prefix.
+    LineComment,
+    /// A comment between block delimiters.
+    BlockComment,
+    /// A `;` ending a directive.
+    Semicolon,
+    /// A `{` opening a block.
+    OpenBrace,
+    /// A `}` closing a block.
+    CloseBrace,
+    /// A `\` continuing a directive on the next line.
+    Continuation,
+    /// A line terminator ending a directive or a blank line.
+    Newline,
+    /// Whitespace between tokens, including the line break after a continuation.
+    Whitespace,
+}
+
+impl TokenClass {
+    /// Returns the kebab-case name of the class, such as `directive-name`.
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenClass::DirectiveName => "directive-name",
+            TokenClass::Argument => "argument",
+            TokenClass::QuotedArgument => "quoted-argument",
+            TokenClass::TripleQuotedArgument => "triple-quoted-argument",
+            TokenClass::RawArgument => "raw-argument",
+            TokenClass::HeredocArgument => "heredoc-argument",
+            TokenClass::ExpressionArgument => "expression-argument",
+            TokenClass::LineComment => "line-comment",
+            TokenClass::BlockComment => "block-comment",
+            TokenClass::Semicolon => "semicolon",
+            TokenClass::OpenBrace => "open-brace",
+            TokenClass::CloseBrace => "close-brace",
+            TokenClass::Continuation => "continuation",
+            TokenClass::Newline => "newline",
+            TokenClass::Whitespace => "whitespace",
+        }
+    }
+
+    /// Returns whether the class is trivia, which does not affect the parsed
+    /// unit: whitespace, continuations and comments.
+    pub fn is_trivia(self) -> bool {
+        matches!(
+            self,
+            TokenClass::Whitespace
+                | TokenClass::Continuation
+                | TokenClass::LineComment
+                | TokenClass::BlockComment
+        )
+    }
+}
+
+/// A token and its class, as yielded by [`tokens`].
+#[derive(Debug, Clone)]
+pub struct ClassifiedToken {
+    /// The token. Whitespace has the type [`TokenType::Whitespace`].
+    pub token: Token,
+    /// The role of the token.
+    pub class: TokenClass,
+}
+
+/// An iterator over every token of an input, created by [`tokens`].
+pub struct Tokens<'a> {
+    /// The lexer producing the tokens.
+    lexer: Lexer<'a>,
+    /// The end of the last token yielded.
+    position: usize,
+    /// A token found after whitespace, yielded after the whitespace.
+    pending: Option<Token>,
+    /// Whether the next argument names a directive.
+    expects_name: bool,
+    /// Whether the input or an error has been reached.
+    done: bool,
+    /// An error in a leading pragma, yielded before any token.
+    pragma_error: Option<ConfError>,
+}
+
+/// Returns an iterator over every token of `input`, including the whitespace
+/// [`Lexer::next_token`] skips, each with its [`TokenClass`].
+///
+/// The spans of the tokens cover the input without gaps. A leading pragma
+/// comment adjusts the options as it does for the parser, and a pragma the
+/// parser would reject is yielded as the only item. The iterator ends after
+/// the first error.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::lexer::{tokens, TokenClass};
+/// use confetti_rs::ConfOptions;
+///
+/// let classes: Vec<TokenClass> = tokens("listen \"80\";", ConfOptions::default())
+///     .map(|token| token.unwrap().class)
+///     .collect();
+/// assert_eq!(
+///     classes,
+///     vec![
+///         TokenClass::DirectiveName,
+///         TokenClass::Whitespace,
+///         TokenClass::QuotedArgument,
+///         TokenClass::Semicolon,
+///     ]
+/// );
+/// ```
+pub fn tokens(input: &str, options: super::ConfOptions) -> Tokens<'_> {
+    let (options, pragma_error) = match crate::pragma::leading(input, options.clone()) {
+        Ok(options) => (options, None),
+        Err(error) => (options, Some(error)),
+    };
+    Tokens {
+        lexer: Lexer::new(input, options),
+        position: 0,
+        pending: None,
+        expects_name: true,
+        done: false,
+        pragma_error,
+    }
+}
+
+impl Tokens<'_> {
+    /// Classifies the next token and tracks whether a directive name follows.
+    fn classify(&mut self, token: &Token) -> TokenClass {
+        let class = match token.token_type {
+            TokenType::Argument if self.expects_name => TokenClass::DirectiveName,
+            TokenType::Argument if token.is_expression => TokenClass::ExpressionArgument,
+            TokenType::Argument if token.is_heredoc => TokenClass::HeredocArgument,
+            TokenType::Argument if token.is_raw => TokenClass::RawArgument,
+            TokenType::Argument if token.is_triple_quoted => TokenClass::TripleQuotedArgument,
+            TokenType::Argument if token.is_quoted => TokenClass::QuotedArgument,
+            TokenType::Argument => TokenClass::Argument,
+            TokenType::Comment => match token.comment_style {
+                Some(CommentStyle::Block(_)) => TokenClass::BlockComment,
+                _ => TokenClass::LineComment,
+            },
+            TokenType::Semicolon => TokenClass::Semicolon,
+            TokenType::LeftCurlyBrace => TokenClass::OpenBrace,
+            TokenType::RightCurlyBrace => TokenClass::CloseBrace,
+            TokenType::Continuation => TokenClass::Continuation,
+            TokenType::Newline => TokenClass::Newline,
+            TokenType::Whitespace | TokenType::Eof => TokenClass::Whitespace,
+        };
+        match token.token_type {
+            TokenType::Argument => self.expects_name = false,
+            TokenType::Semicolon
+            | TokenType::LeftCurlyBrace
+            | TokenType::RightCurlyBrace
+            | TokenType::Newline => self.expects_name = true,
+            _ => {}
+        }
+        class
+    }
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<ClassifiedToken, ConfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(error) = self.pragma_error.take() {
+            self.done = true;
+            return Some(Err(error));
+        }
+        let token = match self.pending.take() {
+            Some(token) => token,
+            None => match self.lexer.next_token() {
+                Ok(token) => token,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            },
+        };
+
+        if token.span.start > self.position {
+            let whitespace = Token {
+                token_type: TokenType::Whitespace,
+                span: self.position..token.span.start,
+                is_quoted: false,
+                is_triple_quoted: false,
+                is_raw: false,
+                is_heredoc: false,
+                comment_style: None,
+                is_expression: false,
+            };
+            self.position = token.span.start;
+            self.pending = Some(token);
+            return Some(Ok(ClassifiedToken {
+                token: whitespace,
+                class: TokenClass::Whitespace,
+            }));
+        }
+        if token.token_type == TokenType::Eof {
+            self.done = true;
+            return None;
+        }
+
+        self.position = token.span.end;
+        let class = self.classify(&token);
+        Some(Ok(ClassifiedToken { token, class }))
+    }
+}
+
+/// How [`Lexer::skip_text`] treats line terminators.
+#[derive(Clone, Copy)]
+enum LineTerminators {
+    /// Stop before a line terminator.
+    Stop,
+    /// Accept line terminators as text.
+    Allow,
+    /// Fail on a line terminator.
+    Reject,
+}
+
+/// Returns whether an ASCII byte is a forbidden control character.
+///
+/// These are the C0 controls and DEL, except the whitespace characters.
+fn is_forbidden_ascii(byte: u8) -> bool {
+    (byte < 0x20 && !matches!(byte, b'\t' | b'\n' | 0x0B | 0x0C | b'\r')) || byte == 0x7F
+}
+
+/// Returns the length of the longest prefix of `bytes` that is printable
+/// ASCII (`0x20..=0x7E`), testing eight bytes at a time.
+fn printable_ascii_len(bytes: &[u8]) -> usize {
+    const HIGH_BITS: u64 = 0x8080_8080_8080_8080;
+    const ADD_0X60: u64 = 0x6060_6060_6060_6060;
+    const ADD_0X01: u64 = 0x0101_0101_0101_0101;
+
+    let mut length = 0;
+    for chunk in bytes.chunks_exact(8) {
+        let word = u64::from_le_bytes(chunk.try_into().unwrap_or_default());
+        // Once every high bit is clear, no addition below carries between
+        // bytes: adding 0x60 sets the high bit of bytes from 0x20 up, and
+        // adding 0x01 sets it only for DEL
+        if word & HIGH_BITS != 0
+            || word.wrapping_add(ADD_0X60) & HIGH_BITS != HIGH_BITS
+            || word.wrapping_add(ADD_0X01) & HIGH_BITS != 0
+        {
+            break;
+        }
+        length += 8;
+    }
+    while bytes
+        .get(length)
+        .is_some_and(|byte| (0x20..0x7F).contains(byte))
+    {
+        length += 1;
+    }
+    length
+}
+
+/// Returns the number of `#` of a raw string opening at the start of
+/// `bytes`, or `None` if it does not start with one.
+fn raw_string_hashes(bytes: &[u8]) -> Option<usize> {
+    if bytes.first() != Some(&b'r') {
+        return None;
+    }
+    let hashes = bytes[1..].iter().take_while(|&&byte| byte == b'#').count();
+    (bytes.get(1 + hashes) == Some(&b'"')).then_some(hashes)
+}
+
+/// Returns the length of the tag of a heredoc opening at the start of
+/// `bytes`, or `None` if it does not start with one. Tags are made of ASCII
+/// letters, digits and `_`, and do not start with a digit.
+fn heredoc_tag_len(bytes: &[u8]) -> Option<usize> {
+    let tag = bytes.strip_prefix(b"<<")?;
+    if !tag
+        .first()
+        .is_some_and(|&byte| byte.is_ascii_alphabetic() || byte == b'_')
+    {
+        return None;
+    }
+    Some(tag.iter().take_while(|&&byte| is_tag_byte(byte)).count())
+}
+
+/// Returns whether the byte can be part of a heredoc tag.
+fn is_tag_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Returns the length of the indentation of `line` if it closes the heredoc
+/// with the given tag. Only whitespace, `;` or `}` may follow the tag, so a
+/// line such as `EOF is here` is part of the body.
+pub(crate) fn heredoc_closing_indent(line: &str, tag: &str) -> Option<usize> {
+    let indent = line
+        .bytes()
+        .take_while(|&byte| byte == b' ' || byte == b'\t')
+        .count();
+    let rest = line[indent..].strip_prefix(tag)?.trim_start();
+    (rest.is_empty() || rest.starts_with([';', '}'])).then_some(indent)
+}
+
+/// Returns the length in bytes of the line terminator at `position`, if
+/// there is one. CRLF counts as a single line terminator.
+pub(crate) fn line_terminator_len(bytes: &[u8], position: usize) -> Option<usize> {
+    match *bytes.get(position)? {
+        b'\r' if bytes.get(position + 1) == Some(&b'\n') => Some(2),
+        b'\n' | 0x0B | 0x0C | b'\r' => Some(1),
+        // NEL
+        0xC2 if bytes.get(position + 1) == Some(&0x85) => Some(2),
+        // LS and PS
+        0xE2 if bytes.get(position + 1) == Some(&0x80)
+            && matches!(bytes.get(position + 2), Some(0xA8) | Some(0xA9)) =>
+        {
+            Some(3)
+        }
+        _ => None,
+    }
+}
+
+/// Splits text into lines, each paired with its line terminator, which is
+/// empty for a last line without one.
+pub(crate) fn split_lines(text: &str) -> impl Iterator<Item = (&str, &str)> {
+    let bytes = text.as_bytes();
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        if start >= text.len() {
+            return None;
+        }
+        let mut end = start;
+        while end < text.len() {
+            if let Some(length) = line_terminator_len(bytes, end) {
+                let line = (&text[start..end], &text[end..end + length]);
+                start = end + length;
+                return Some(line);
+            }
+            end += 1;
+        }
+        let line = (&text[start..], "");
+        start = text.len();
+        Some(line)
+    })
+}
+
+/// Returns the error for an exceeded resource limit.
+pub(crate) fn limit_exceeded(position: usize, limit: Limit, max: usize) -> ConfError {
+    ConfError::LimitExceeded {
+        span: Span::point(position),
+        limit,
+        max,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -644,6 +1331,72 @@ mod tests {
         assert!(lexer.scan_comment().is_err());
     }
 
+    #[test]
+    fn test_lexer_custom_comment_syntax() {
+        let options = super::super::ConfOptions {
+            comment_syntax: super::super::CommentSyntax::lua(),
+            ..Default::default()
+        };
+        // The longest delimiter wins, and # is no longer a comment
+        let input = "--[[ block\n]] -- line\na#b";
+        let mut lexer = Lexer::new(input, options);
+        let block = lexer.next_token().unwrap();
+        assert_eq!(block.span, 0..13);
+        assert_eq!(block.comment_style, Some(CommentStyle::Block(0)));
+        assert_eq!(lexer.next_token().unwrap().span, 14..21);
+        assert_eq!(lexer.next_token().unwrap().token_type, TokenType::Newline);
+        let argument = lexer.next_token().unwrap();
+        assert_eq!(argument.span, 22..25);
+        assert_eq!(argument.comment_style, None);
+
+        // C-style delimiters come after the configured ones
+        let options = super::super::ConfOptions {
+            allow_c_style_comments: true,
+            comment_syntax: super::super::CommentSyntax::ini(),
+            ..Default::default()
+        };
+        let styles: Vec<Option<CommentStyle>> = ["; a", "# b", "// c", "/* d */"]
+            .iter()
+            .map(|input| {
+                let mut lexer = Lexer::new(input, options.clone());
+                lexer.next_token().unwrap().comment_style
+            })
+            .collect();
+        assert_eq!(
+            styles,
+            vec![
+                Some(CommentStyle::Line(0)),
+                Some(CommentStyle::Line(1)),
+                Some(CommentStyle::Line(2)),
+                Some(CommentStyle::Block(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lexer_nested_block_comments() {
+        let input = "/* a /* b */ c */ d";
+        let mut options = super::super::ConfOptions {
+            allow_c_style_comments: true,
+            ..Default::default()
+        };
+        let mut lexer = Lexer::new(input, options.clone());
+        assert_eq!(lexer.next_token().unwrap().span, 0..12);
+
+        options.comment_syntax.nestable_blocks = true;
+        let mut lexer = Lexer::new(input, options.clone());
+        assert_eq!(lexer.next_token().unwrap().span, 0..17);
+
+        let mut lexer = Lexer::new("/* a /* b */", options);
+        match lexer.next_token() {
+            Err(ConfError::LexerError { span, message }) => {
+                assert_eq!(span.start(), 0);
+                assert!(message.contains("Unterminated"));
+            }
+            other => panic!("Expected an error, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_lexer_scan_quoted_argument() {
         let input = "\"test\"";
@@ -839,6 +1592,85 @@ mod tests {
         assert!(!token.is_expression);
     }
 
+    #[test]
+    fn test_lexer_triple_quoted_argument_with_inner_quotes() {
+        let input = "\"\"\"a \"b\" \"\"c\"\"\"";
+        let options = super::super::ConfOptions::default();
+        let mut lexer = Lexer::new(input, options.clone());
+        let token = lexer.next_token().unwrap();
+        assert_eq!(token.span, 0..input.len());
+
+        // A lone quote before the end of input must not loop forever
+        let mut lexer = Lexer::new("\"\"\"a \"", options);
+        assert!(lexer.next_token().is_err());
+    }
+
+    #[test]
+    fn test_lexer_raw_string() {
+        let options = super::super::ConfOptions {
+            allow_raw_strings: true,
+            ..Default::default()
+        };
+        for input in [
+            r#"r"C:\temp\""#,
+            r##"r#"a "quoted" \d+"#"##,
+            "r\"multi\nline\"",
+        ] {
+            let mut lexer = Lexer::new(input, options.clone());
+            let token = lexer.next_token().unwrap();
+            assert_eq!(token.span, 0..input.len(), "{}", input);
+            assert!(token.is_quoted && token.is_raw);
+            assert!(!token.is_triple_quoted);
+        }
+
+        // A quote with too few hashes does not end the string
+        let mut lexer = Lexer::new(r###"r##"a"#b"##"###, options.clone());
+        assert_eq!(lexer.next_token().unwrap().span, 0..11);
+        let mut lexer = Lexer::new(r##"r#"a"b"##, options.clone());
+        assert!(lexer.next_token().is_err());
+
+        // Without the option, the r is a separate argument
+        let mut lexer = Lexer::new(r#"r"a\""#, super::super::ConfOptions::default());
+        assert_eq!(lexer.next_token().unwrap().span, 0..1);
+        assert!(lexer.next_token().is_err());
+    }
+
+    #[test]
+    fn test_lexer_heredoc() {
+        let options = super::super::ConfOptions {
+            allow_heredocs: true,
+            ..Default::default()
+        };
+        let input = "<<EOF\n  echo \\\n\n  EOFX\n  EOF is here\n  EOF;";
+        let mut lexer = Lexer::new(input, options.clone());
+        let token = lexer.next_token().unwrap();
+        assert_eq!(token.token_type, TokenType::Argument);
+        assert!(token.is_heredoc && !token.is_quoted);
+        assert_eq!(token.span, 0..input.len() - 1);
+        assert_eq!(lexer.next_token().unwrap().token_type, TokenType::Semicolon);
+
+        let errors = [
+            ("<<EOF x\nEOF", 6, "line break"),
+            ("<<EOF\na\nEO", 0, "Unterminated heredoc"),
+            ("<<EOF\n    a\n  b\n    EOF", 12, "indented less"),
+            ("<<EOF\n\u{0}\nEOF", 6, "Forbidden character"),
+        ];
+        for (input, expected_position, expected_message) in errors {
+            let mut lexer = Lexer::new(input, options.clone());
+            match lexer.next_token() {
+                Err(ConfError::LexerError { span, message }) => {
+                    assert_eq!(span.start(), expected_position, "{:?}", input);
+                    assert!(message.contains(expected_message), "{}", message);
+                }
+                other => panic!("Expected an error for {:?}, got {:?}", input, other),
+            }
+        }
+
+        // Without the option, <<EOF is a plain argument
+        let mut lexer = Lexer::new("<<EOF", super::super::ConfOptions::default());
+        assert!(!lexer.next_token().unwrap().is_heredoc);
+    }
+
     #[test]
     fn test_lexer_next_token_argument() {
         let input = "test";
@@ -867,4 +1699,170 @@ mod tests {
         assert!(!token.is_triple_quoted);
         assert!(token.is_expression);
     }
+
+    #[test]
+    fn test_printable_ascii_len() {
+        assert_eq!(printable_ascii_len(b""), 0);
+        assert_eq!(printable_ascii_len(b"hello world, this is ASCII"), 26);
+        assert_eq!(printable_ascii_len(b"0123456789\tabc"), 10);
+        assert_eq!(printable_ascii_len(b"01234567\x7f"), 8);
+        assert_eq!(printable_ascii_len("0123456789ab\u{e9}".as_bytes()), 12);
+        assert_eq!(printable_ascii_len(b" ~\x1f"), 2);
+    }
+
+    #[test]
+    fn test_lexer_long_text_checks() {
+        let options = super::super::ConfOptions {
+            allow_c_style_comments: true,
+            ..Default::default()
+        };
+        let tokens = |input: &str| {
+            let mut lexer = Lexer::new(input, options.clone());
+            let mut tokens = Vec::new();
+            loop {
+                let token = lexer.next_token()?;
+                if token.token_type == TokenType::Eof {
+                    return Ok(tokens);
+                }
+                tokens.push((token.token_type, token.span));
+            }
+        };
+
+        // Forbidden characters deep inside long comments and strings
+        let comment = format!("# {}\u{7}", "x".repeat(40));
+        match tokens(&comment) {
+            Err(ConfError::LexerError { span, message }) => {
+                assert_eq!(span.start(), 42);
+                assert!(message.contains("in comment"));
+            }
+            other => panic!("Expected LexerError, got {:?}", other),
+        }
+        let quoted = format!("\"{}\u{202E}\"", "y".repeat(20));
+        assert!(tokens(&quoted).is_err());
+
+        // Line terminators other than CR and LF end single-line comments
+        let result = tokens("# caf\u{e9}\u{2028}a // b\u{85}c").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (TokenType::Comment, 0..7),
+                (TokenType::Newline, 7..10),
+                (TokenType::Argument, 10..11),
+                (TokenType::Comment, 12..16),
+                (TokenType::Newline, 16..18),
+                (TokenType::Argument, 18..19),
+            ]
+        );
+
+        // Block comments and strings spanning lines
+        let result = tokens("/* a\r\n * b */ \"\"\"x\r\ny\"\"\"").unwrap();
+        assert_eq!(
+            result,
+            vec![(TokenType::Comment, 0..13), (TokenType::Argument, 14..24)]
+        );
+
+        // CRLF after a line continuation
+        let result = tokens("a \\\r\n  b").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (TokenType::Argument, 0..1),
+                (TokenType::Continuation, 2..3),
+                (TokenType::Argument, 7..8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_classified_tokens_cover_the_input() {
+        let input = "# c\nserver \"a\" {\n  listen 80 \\\n    ssl;  /* x\n */\n}\n";
+        let options = super::super::ConfOptions {
+            allow_c_style_comments: true,
+            ..super::super::ConfOptions::default()
+        };
+        let classified: Vec<ClassifiedToken> = super::tokens(input, options)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let text: String = classified
+            .iter()
+            .map(|token| &input[token.token.span.clone()])
+            .collect();
+        assert_eq!(text, input);
+
+        let significant: Vec<(TokenClass, &str)> = classified
+            .iter()
+            .filter(|token| !token.class.is_trivia() && token.class != TokenClass::Newline)
+            .map(|token| (token.class, &input[token.token.span.clone()]))
+            .collect();
+        assert_eq!(
+            significant,
+            vec![
+                (TokenClass::DirectiveName, "server"),
+                (TokenClass::QuotedArgument, "\"a\""),
+                (TokenClass::OpenBrace, "{"),
+                (TokenClass::DirectiveName, "listen"),
+                (TokenClass::Argument, "80"),
+                // A continued directive keeps its arguments
+                (TokenClass::Argument, "ssl"),
+                (TokenClass::Semicolon, ";"),
+                (TokenClass::CloseBrace, "}"),
+            ]
+        );
+        let comments: Vec<TokenClass> = classified
+            .iter()
+            .map(|token| token.class)
+            .filter(|class| matches!(class, TokenClass::LineComment | TokenClass::BlockComment))
+            .collect();
+        assert_eq!(
+            comments,
+            [TokenClass::LineComment, TokenClass::BlockComment]
+        );
+    }
+
+    #[test]
+    fn test_classified_tokens_stop_at_an_error() {
+        let mut stream = super::tokens("a \"b", super::super::ConfOptions::default());
+        assert_eq!(
+            stream.next().unwrap().unwrap().class,
+            TokenClass::DirectiveName
+        );
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+
+        // Classes of the extensions, enabled by a pragma
+        let input = "# confetti: raw-strings, heredocs\nx r\"y\" \"\"\"z\"\"\" <<E\nE\n";
+        let options = super::super::ConfOptions {
+            pragma_policy: crate::PragmaPolicy::Allow,
+            ..super::super::ConfOptions::default()
+        };
+        let classes: Vec<TokenClass> = super::tokens(input, options)
+            .map(|token| token.unwrap().class)
+            .filter(|class| !class.is_trivia())
+            .collect();
+        assert_eq!(
+            classes,
+            [
+                TokenClass::Newline,
+                TokenClass::DirectiveName,
+                TokenClass::RawArgument,
+                TokenClass::TripleQuotedArgument,
+                TokenClass::HeredocArgument,
+                TokenClass::Newline,
+            ]
+        );
+
+        // A pragma the parser rejects is the only item
+        let options = super::super::ConfOptions {
+            pragma_policy: crate::PragmaPolicy::Allow,
+            ..super::super::ConfOptions::default()
+        };
+        let input = "# confetti: colour
+x;
+";
+        assert!(crate::parse(input, options.clone()).is_err());
+        let mut stream = super::tokens(input, options);
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+    }
 }
@@ -8,6 +8,26 @@ A configuration language and parser library for Rust, with a flexible mapper for
 - Simple, intuitive configuration syntax
 - A powerful parser with customizable options
 - Automatic mapping between configuration and Rust structs
+- Typed accessors (`child`, `arg::<T>`, `get::<T>("database.port")`) with span-located errors
+- Path queries for selecting directives (`server[listen=443]/ssl_cert`)
+- Visitors and folds over the directive tree, with depth and ancestor context
+- A fluent directive builder and a `confetti!` macro checked at compile time
+- `Display` for the AST that prints confetti parsing back to the same tree
+- Format-preserving edits of configuration files
+- Structural diffs between configuration versions
+- Streaming event reader for very large inputs
+- Incremental reparsing of the block affected by a text edit
+- An arena representation with interned directive names for large configurations
+- A versioned binary cache of parsed units, invalidated when the source changes
+- Source maps over several files, with compact file-aware spans resolved to lines and columns
+- A language server (`confetti-lsp`) for editor support
+- Classified token stream with ANSI terminal and HTML highlighters
+- Reference-format printer and runner for the upstream conformance suite
+- Parsing from bytes with BOM handling and UTF-16 transcoding
+- Raw strings and heredoc arguments as opt-in extensions
+- Configurable comment syntax, including nested block comments
+- In-file pragmas that enable parser extensions, under a caller-chosen policy
+- Opt-in Unicode security lint for invisible characters, mixed scripts and confusables
 - Support for custom data types
 - Comprehensive error handling
 
@@ -16,9 +36,10 @@ A configuration language and parser library for Rust, with a flexible mapper for
 Here's a simple example of how to use Confetti-rs:
 
 ```
+use confetti_rs::builder::Directive;
 use confetti_rs::{
-    parse, from_str, to_string, ConfOptions, ConfDirective, ConfArgument,
-    FromConf, ToConf, MapperError, ValueConverter,
+    parse, from_str, to_string, ConfOptions, ConfDirective,
+    FromConf, ToConf, MapperError,
 };
 
 // Define a configuration structure
@@ -31,57 +52,20 @@ struct ServerConfig {
 // Implement FromConf to deserialize from config
 impl FromConf for ServerConfig {
     fn from_directive(directive: &ConfDirective) -> Result<Self, MapperError> {
-        let host = directive.children.iter()
-            .find(|d| d.name.value == "host")
-            .and_then(|d| d.arguments.first())
-            .map(|a| confetti_rs::__private::strip_quotes(&a.value))
-            .ok_or_else(|| MapperError::MissingField("host".into()))?;
-
-        let port = directive.children.iter()
-            .find(|d| d.name.value == "port")
-            .and_then(|d| d.arguments.first())
-            .map(|a| i32::from_conf_value(&a.value))
-            .transpose()?
-            .ok_or_else(|| MapperError::MissingField("port".into()))?;
-
-        Ok(ServerConfig { host, port })
+        Ok(ServerConfig {
+            host: directive.value("host")?,
+            port: directive.value("port")?,
+        })
     }
 }
 
 // Implement ToConf to serialize to config
 impl ToConf for ServerConfig {
     fn to_directive(&self) -> Result<ConfDirective, MapperError> {
-        Ok(ConfDirective {
-            name: ConfArgument {
-                value: "ServerConfig".to_string(),
-                span: 0..0, is_quoted: false, is_triple_quoted: false, is_expression: false,
-            },
-            arguments: vec![],
-            children: vec![
-                ConfDirective {
-                    name: ConfArgument {
-                        value: "host".to_string(),
-                        span: 0..0, is_quoted: false, is_triple_quoted: false, is_expression: false,
-                    },
-                    arguments: vec![ConfArgument {
-                        value: self.host.clone(),
-                        span: 0..0, is_quoted: true, is_triple_quoted: false, is_expression: false,
-                    }],
-                    children: vec![],
-                },
-                ConfDirective {
-                    name: ConfArgument {
-                        value: "port".to_string(),
-                        span: 0..0, is_quoted: false, is_triple_quoted: false, is_expression: false,
-                    },
-                    arguments: vec![ConfArgument {
-                        value: self.port.to_string(),
-                        span: 0..0, is_quoted: false, is_triple_quoted: false, is_expression: false,
-                    }],
-                    children: vec![],
-                },
-            ],
-        })
+        Ok(Directive::new("ServerConfig")
+            .child(Directive::new("host").quoted(&self.host))
+            .child(Directive::new("port").arg(self.port.to_string()))
+            .build())
     }
 }
 
@@ -128,14 +112,32 @@ For more examples and detailed documentation, please visit:
 
 use std::error::Error;
 use std::fmt;
-use std::ops::Range;
-
+use std::hash::{Hash, Hasher};
+
+pub mod arena;
+pub mod borrowed;
+pub mod builder;
+pub mod cache;
+pub mod conformance;
+pub mod diff;
+mod display;
+pub mod edit;
+pub mod encoding;
+pub mod highlight;
+pub mod incremental;
 pub mod lexer;
+#[cfg(feature = "lint")]
+pub mod lint;
 pub mod mapper;
 pub mod parser;
+mod pragma;
+pub mod query;
+pub mod reader;
+pub mod source_map;
+pub mod visit;
 
 #[cfg(feature = "derive")]
-pub use confetti_derive::ConfMap;
+pub use confetti_derive::{confetti, ConfMap};
 
 // Private module for derive macro implementation details
 #[doc(hidden)]
@@ -156,6 +158,40 @@ pub mod __private {
         }
     }
 
+    /// Returns an argument from its source text, for `confetti!`.
+    pub fn argument(
+        value: &str,
+        is_quoted: bool,
+        is_triple_quoted: bool,
+        is_raw: bool,
+    ) -> crate::ConfArgument {
+        crate::ConfArgument {
+            value: value.to_string(),
+            span: crate::Span::default(),
+            is_quoted,
+            is_triple_quoted,
+            is_raw,
+            is_heredoc: false,
+            is_expression: false,
+        }
+    }
+
+    /// Returns a directive holding one field's value, for the `ConfMap` derive.
+    pub fn field_directive<T: crate::ValueConverter>(
+        name: &str,
+        value: &T,
+    ) -> Result<crate::ConfDirective, crate::MapperError> {
+        let text = value.to_conf_value()?;
+        let argument = if value.requires_quotes() {
+            crate::builder::quoted(text)
+        } else {
+            crate::builder::argument(text)
+        };
+        Ok(crate::builder::Directive::new(name)
+            .argument(argument)
+            .build())
+    }
+
     pub fn strip_quotes(value: &str) -> String {
         let result = value.to_string();
         // Add length check to prevent panic on strings shorter than 2 characters
@@ -210,22 +246,31 @@ pub fn process_escapes(input: &str) -> String {
 }
 
 /// Represents a configuration argument.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ConfArgument {
     /// The value of the argument.
     pub value: String,
     /// The span of the argument in the source text.
-    pub span: Range<usize>,
+    pub span: Span,
     /// Whether the argument is quoted.
     pub is_quoted: bool,
     /// Whether the argument is a triple-quoted string.
     pub is_triple_quoted: bool,
+    /// Whether the argument is a raw string, such as `r"C:\temp"`.
+    pub is_raw: bool,
+    /// Whether the argument is a heredoc, such as `<<EOF ... EOF`.
+    pub is_heredoc: bool,
     /// Whether the argument is an expression.
     pub is_expression: bool,
 }
 
 /// Represents a configuration directive.
-#[derive(Debug, Clone)]
+///
+/// Dropping, cloning, comparing and hashing a directive walk its children
+/// with an explicit stack, so deep trees cannot overflow the call stack.
+/// Because the type implements `Drop`, its fields cannot be moved out by
+/// destructuring; use [`std::mem::take`] instead.
+#[derive(Debug)]
 pub struct ConfDirective {
     /// The name of the directive.
     pub name: ConfArgument,
@@ -235,6 +280,219 @@ pub struct ConfDirective {
     pub children: Vec<ConfDirective>,
 }
 
+// Equality and hashing compare content only; spans are ignored so that the
+// same configuration parsed from differently formatted sources compares equal.
+
+impl PartialEq for ConfArgument {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+            && self.is_quoted == other.is_quoted
+            && self.is_triple_quoted == other.is_triple_quoted
+            && self.is_raw == other.is_raw
+            && self.is_heredoc == other.is_heredoc
+            && self.is_expression == other.is_expression
+    }
+}
+
+impl Eq for ConfArgument {}
+
+impl Hash for ConfArgument {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+        self.is_quoted.hash(state);
+        self.is_triple_quoted.hash(state);
+        self.is_raw.hash(state);
+        self.is_heredoc.hash(state);
+        self.is_expression.hash(state);
+    }
+}
+
+impl ConfArgument {
+    /// Returns the text the argument stands for.
+    ///
+    /// Quotes are removed, and escape sequences and line continuations are
+    /// resolved. Raw strings keep their content verbatim, and heredocs lose
+    /// their delimiter lines and the indentation of the closing delimiter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use confetti_rs::{parse, ConfOptions};
+    ///
+    /// let input = "path r\"C:\\temp\";\nscript <<EOF\n    echo \"hi\"\n      exit\n    EOF\n";
+    /// let unit = parse(input, ConfOptions::all_extensions()).unwrap();
+    ///
+    /// assert_eq!(unit.directives[0].arguments[0].text(), "C:\\temp");
+    /// assert_eq!(unit.directives[1].arguments[0].text(), "echo \"hi\"\n  exit");
+    /// ```
+    pub fn text(&self) -> String {
+        argument_text(
+            &self.value,
+            self.is_quoted,
+            self.is_triple_quoted,
+            self.is_raw,
+            self.is_heredoc,
+        )
+    }
+
+    /// Returns whether the argument stands for `text`, without building the
+    /// text of bare arguments.
+    pub(crate) fn has_text(&self, text: &str) -> bool {
+        if self.is_quoted || self.is_raw || self.is_heredoc || self.value.contains('\\') {
+            self.text() == text
+        } else {
+            self.value == text
+        }
+    }
+}
+
+/// Returns the text an argument with the given value and kind stands for.
+pub(crate) fn argument_text(
+    value: &str,
+    is_quoted: bool,
+    is_triple_quoted: bool,
+    is_raw: bool,
+    is_heredoc: bool,
+) -> String {
+    if is_raw {
+        let hashes = value
+            .strip_prefix('r')
+            .unwrap_or(value)
+            .bytes()
+            .take_while(|&byte| byte == b'#')
+            .count();
+        return value
+            .get(2 + hashes..value.len().saturating_sub(1 + hashes))
+            .unwrap_or_default()
+            .to_string();
+    }
+    if is_heredoc {
+        return heredoc_text(value);
+    }
+
+    let inner = if is_triple_quoted && value.len() >= 6 {
+        value.get(3..value.len() - 3)
+    } else if is_quoted && value.len() >= 2 {
+        value.get(1..value.len() - 1)
+    } else {
+        None
+    };
+    let inner = inner.unwrap_or(value);
+    resolve_escapes(inner)
+}
+
+/// Returns the body of a heredoc without the indentation of its closing line.
+fn heredoc_text(value: &str) -> String {
+    let mut lines: Vec<(&str, &str)> = lexer::split_lines(value).skip(1).collect();
+    let Some((closing, _)) = lines.pop() else {
+        return String::new();
+    };
+    let indentation = &closing[..closing.len() - closing.trim_start_matches([' ', '\t']).len()];
+
+    let mut text = String::with_capacity(value.len());
+    let count = lines.len();
+    for (index, (line, terminator)) in lines.into_iter().enumerate() {
+        // Blank lines may be indented less than the closing line
+        let line = line
+            .strip_prefix(indentation)
+            .unwrap_or_else(|| line.trim_start_matches([' ', '\t']));
+        text.push_str(line);
+        if index + 1 < count {
+            text.push_str(terminator);
+        }
+    }
+    text
+}
+
+/// Removes escape backslashes, and line continuations along with their line
+/// terminators.
+fn resolve_escapes(text: &str) -> String {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next() {
+            // A line continuation is removed along with its line terminator
+            Some('\r') => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+            }
+            Some('\n') | Some('\u{000B}') | Some('\u{000C}') | Some('\u{0085}')
+            | Some('\u{2028}') | Some('\u{2029}') => {}
+            Some(escaped) => value.push(escaped),
+            None => value.push('\\'),
+        }
+    }
+    value
+}
+
+impl PartialEq for ConfDirective {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((left, right)) = stack.pop() {
+            if left.name != right.name
+                || left.arguments != right.arguments
+                || left.children.len() != right.children.len()
+            {
+                return false;
+            }
+            stack.extend(left.children.iter().zip(&right.children));
+        }
+        true
+    }
+}
+
+impl Eq for ConfDirective {}
+
+impl Hash for ConfDirective {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Directives are hashed in preorder, each with its number of children
+        let mut stack = vec![self];
+        while let Some(directive) = stack.pop() {
+            directive.name.hash(state);
+            directive.arguments.hash(state);
+            directive.children.len().hash(state);
+            stack.extend(directive.children.iter().rev());
+        }
+    }
+}
+
+impl Clone for ConfDirective {
+    fn clone(&self) -> Self {
+        borrowed::map_tree(
+            self,
+            |directive| (directive, directive.children.iter().collect()),
+            |directive, children| ConfDirective {
+                name: directive.name.clone(),
+                arguments: directive.arguments.clone(),
+                children,
+            },
+        )
+    }
+}
+
+impl Drop for ConfDirective {
+    fn drop(&mut self) {
+        // Tear deep trees down iteratively so dropping cannot overflow the stack
+        let mut stack = std::mem::take(&mut self.children);
+        while let Some(mut child) = stack.pop() {
+            stack.append(&mut child.children);
+        }
+    }
+}
+
+impl PartialEq for ConfComment {
+    fn eq(&self, other: &Self) -> bool {
+        self.content == other.content && self.is_multi_line == other.is_multi_line
+    }
+}
+
+impl Eq for ConfComment {}
+
 /// Represents a configuration unit.
 #[derive(Debug, Clone)]
 pub struct ConfUnit {
@@ -250,7 +508,7 @@ pub struct ConfComment {
     /// The content of the comment.
     pub content: String,
     /// The span of the comment in the source text.
-    pub span: Range<usize>,
+    pub span: Span,
     /// Whether the comment is a multi-line comment.
     pub is_multi_line: bool,
 }
@@ -260,18 +518,111 @@ pub struct ConfComment {
 pub enum ConfError {
     /// An error occurred during lexing.
     LexerError {
-        /// The position in the source text where the error occurred.
-        position: usize,
+        /// Where in the source the error occurred.
+        span: Span,
         /// A description of the error.
         message: String,
     },
     /// An error occurred during parsing.
     ParserError {
-        /// The position in the source text where the error occurred.
-        position: usize,
+        /// Where in the source the error occurred.
+        span: Span,
         /// A description of the error.
         message: String,
     },
+    /// An error occurred while reading the input.
+    IoError(std::io::Error),
+    /// The input is not valid in its encoding.
+    EncodingError {
+        /// Where in the input the first invalid sequence starts.
+        span: Span,
+        /// The 1-based line of the invalid sequence.
+        line: usize,
+        /// A description of the error.
+        message: String,
+    },
+    /// The input exceeded one of the resource limits in [`ConfOptions`].
+    LimitExceeded {
+        /// Where in the source the limit was exceeded.
+        span: Span,
+        /// The limit that was exceeded.
+        limit: Limit,
+        /// The configured maximum.
+        max: usize,
+    },
+}
+
+/// A resource limit configured in [`ConfOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The total size of the input in bytes (`max_input_bytes`).
+    InputBytes,
+    /// The total number of directives, including nested ones (`max_directives`).
+    Directives,
+    /// The number of arguments of a single directive (`max_arguments`).
+    Arguments,
+    /// The length of a single argument in bytes (`max_argument_length`).
+    ArgumentLength,
+    /// The length of a single comment in bytes (`max_comment_length`).
+    CommentLength,
+    /// The total number of tokens (`max_tokens`).
+    Tokens,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Limit::InputBytes => "input size in bytes",
+            Limit::Directives => "number of directives",
+            Limit::Arguments => "number of arguments per directive",
+            Limit::ArgumentLength => "argument length",
+            Limit::CommentLength => "comment length",
+            Limit::Tokens => "number of tokens",
+        };
+        f.write_str(description)
+    }
+}
+
+impl ConfError {
+    /// Returns the byte offset in the input where the error occurred, if the
+    /// error has one.
+    pub fn position(&self) -> Option<usize> {
+        self.span().map(Span::start)
+    }
+
+    /// Returns where in the source the error occurred, if the error has a
+    /// location.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ConfError::LexerError { span, .. }
+            | ConfError::ParserError { span, .. }
+            | ConfError::EncodingError { span, .. }
+            | ConfError::LimitExceeded { span, .. } => Some(*span),
+            ConfError::IoError(_) => None,
+        }
+    }
+
+    /// Returns the error with its span moved to another file.
+    pub fn in_file(self, file: FileId) -> Self {
+        self.map_span(|span| span.in_file(file))
+    }
+
+    /// Returns the error with its span moved forward by `offset`, for errors
+    /// reported by a lexer or parser running over a slice of the input.
+    pub(crate) fn shifted(self, offset: usize) -> Self {
+        self.map_span(|span| span.shifted(offset as isize))
+    }
+
+    fn map_span(mut self, f: impl FnOnce(Span) -> Span) -> Self {
+        match &mut self {
+            ConfError::LexerError { span, .. }
+            | ConfError::ParserError { span, .. }
+            | ConfError::EncodingError { span, .. }
+            | ConfError::LimitExceeded { span, .. } => *span = f(*span),
+            ConfError::IoError(_) => {}
+        }
+        self
+    }
 }
 
 impl Error for ConfError {}
@@ -279,24 +630,128 @@ impl Error for ConfError {}
 impl fmt::Display for ConfError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ConfError::LexerError { position, message } => {
-                write!(f, "Lexer error at position {}: {}", position, message)
+            ConfError::LexerError { span, message } => {
+                write!(f, "Lexer error at position {}: {}", span.start(), message)
             }
-            ConfError::ParserError { position, message } => {
-                write!(f, "Parser error at position {}: {}", position, message)
+            ConfError::ParserError { span, message } => {
+                write!(f, "Parser error at position {}: {}", span.start(), message)
             }
+            ConfError::IoError(error) => write!(f, "I/O error: {}", error),
+            ConfError::EncodingError {
+                span,
+                line,
+                message,
+            } => write!(
+                f,
+                "Encoding error at position {} (line {}): {}",
+                span.start(),
+                line,
+                message
+            ),
+            ConfError::LimitExceeded { span, limit, max } => write!(
+                f,
+                "Limit exceeded at position {}: {} is limited to {}",
+                span.start(),
+                limit,
+                max
+            ),
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfError {
+    fn from(error: std::io::Error) -> Self {
+        ConfError::IoError(error)
+    }
+}
+
+/// The delimiters that start and end comments.
+///
+/// When several delimiters match at the same position, the longest wins, so
+/// `--[[` can open a block comment while `--` opens a line comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommentSyntax {
+    /// Prefixes that start a comment running to the end of the line.
+    pub line_prefixes: Vec<String>,
+    /// Opening and closing delimiters of block comments, which may span lines.
+    pub block_delimiters: Vec<(String, String)>,
+    /// Whether block comments nest, so that `/* /* */ */` is a single comment.
+    pub nestable_blocks: bool,
+}
+
+impl Default for CommentSyntax {
+    /// Line comments starting with `#`, as in the Confetti specification.
+    fn default() -> Self {
+        Self {
+            line_prefixes: vec!["#".to_string()],
+            block_delimiters: Vec::new(),
+            nestable_blocks: false,
+        }
+    }
+}
+
+impl CommentSyntax {
+    /// `#` and `//` line comments and `/* */` block comments.
+    pub fn c_style() -> Self {
+        Self {
+            line_prefixes: vec!["#".to_string(), "//".to_string()],
+            block_delimiters: vec![("/*".to_string(), "*/".to_string())],
+            nestable_blocks: false,
+        }
+    }
+
+    /// `;` and `#` line comments, as in INI files.
+    ///
+    /// A `;` then always starts a comment, so directives can only be
+    /// terminated by line breaks and blocks.
+    pub fn ini() -> Self {
+        Self {
+            line_prefixes: vec![";".to_string(), "#".to_string()],
+            block_delimiters: Vec::new(),
+            nestable_blocks: false,
+        }
+    }
+
+    /// `--` line comments and `--[[ ]]` block comments, as in Lua.
+    pub fn lua() -> Self {
+        Self {
+            line_prefixes: vec!["--".to_string()],
+            block_delimiters: vec![("--[[".to_string(), "]]".to_string())],
+            nestable_blocks: false,
         }
     }
 }
 
+/// How the parser treats a leading pragma comment such as
+/// `# confetti: c-comments, require-semicolons`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PragmaPolicy {
+    /// Pragmas are ordinary comments.
+    Ignore,
+    /// Pragmas may only make the options stricter, by disabling extensions or
+    /// requiring semicolons. A pragma that would relax them is an error.
+    TightenOnly,
+    /// Pragmas may enable and disable any option they name.
+    Allow,
+}
+
 /// Options for parsing configuration.
 #[derive(Debug, Clone)]
 pub struct ConfOptions {
     /// Whether to allow C-style comments (/* */ and //).
+    ///
+    /// This adds `//` to the line prefixes and `/*`, `*/` to the block
+    /// delimiters of [`comment_syntax`](Self::comment_syntax).
     pub allow_c_style_comments: bool,
+    /// The delimiters of comments.
+    pub comment_syntax: CommentSyntax,
     /// Whether to allow expression arguments.
     pub allow_expression_arguments: bool,
     /// The maximum depth of nested directives.
+    ///
+    /// Parsing, dropping, cloning and comparing trees do not recurse, so the
+    /// limit is bounded only by memory for them. Printing a tree with
+    /// `Display` or `Debug` does recurse.
     pub max_depth: usize,
     /// Whether to forbid bidirectional formatting characters.
     /// When true (default), bidi characters will cause a lexer error.
@@ -307,18 +762,126 @@ pub struct ConfOptions {
     pub allow_triple_quotes: bool,
     /// Whether to allow line continuations with backslash.
     pub allow_line_continuations: bool,
+    /// Whether to allow raw strings (`r"..."`, `r#"..."#`), in which
+    /// backslashes are not escapes.
+    pub allow_raw_strings: bool,
+    /// Whether to allow heredoc arguments (`<<EOF` up to a line holding only
+    /// `EOF`), whose lines lose the indentation of the closing delimiter.
+    pub allow_heredocs: bool,
+    /// How to treat a pragma comment at the start of the input, which names
+    /// options such as `c-comments` to enable, or `no-c-comments` to disable.
+    /// Pragmas are ordinary comments unless the caller opts in.
+    pub pragma_policy: PragmaPolicy,
+    /// The maximum size of the input in bytes. Inputs are never longer than
+    /// [`source_map::MAX_OFFSET`], the largest offset a span can hold.
+    pub max_input_bytes: usize,
+    /// The maximum total number of directives, including nested ones.
+    pub max_directives: usize,
+    /// The maximum number of arguments of a single directive, excluding its name.
+    pub max_arguments: usize,
+    /// The maximum length of a single argument in bytes, including quotes.
+    pub max_argument_length: usize,
+    /// The maximum length of a single comment in bytes.
+    pub max_comment_length: usize,
+    /// The maximum total number of tokens.
+    pub max_tokens: usize,
 }
 
 impl Default for ConfOptions {
     fn default() -> Self {
         Self {
             allow_c_style_comments: false,
+            comment_syntax: CommentSyntax::default(),
             allow_expression_arguments: false,
             max_depth: 100,
             forbid_bidi_characters: true, // Default: forbid bidi characters for security
             require_semicolons: false,
             allow_triple_quotes: true,
             allow_line_continuations: true,
+            allow_raw_strings: false,
+            allow_heredocs: false,
+            pragma_policy: PragmaPolicy::Ignore,
+            max_input_bytes: usize::MAX,
+            max_directives: usize::MAX,
+            max_arguments: usize::MAX,
+            max_argument_length: usize::MAX,
+            max_comment_length: usize::MAX,
+            max_tokens: usize::MAX,
+        }
+    }
+}
+
+impl ConfOptions {
+    /// Options for the core Confetti specification with every extension disabled.
+    ///
+    /// Unlike [`Default`], this preset is pinned to the specification and will
+    /// not pick up new extensions.
+    pub fn spec_strict() -> Self {
+        Self {
+            allow_c_style_comments: false,
+            comment_syntax: CommentSyntax::default(),
+            allow_expression_arguments: false,
+            max_depth: 100,
+            forbid_bidi_characters: true,
+            require_semicolons: false,
+            allow_triple_quotes: true,
+            allow_line_continuations: true,
+            allow_raw_strings: false,
+            allow_heredocs: false,
+            pragma_policy: PragmaPolicy::Ignore,
+            max_input_bytes: usize::MAX,
+            max_directives: usize::MAX,
+            max_arguments: usize::MAX,
+            max_argument_length: usize::MAX,
+            max_comment_length: usize::MAX,
+            max_tokens: usize::MAX,
+        }
+    }
+
+    /// Returns the comment syntax in effect: [`comment_syntax`](Self::comment_syntax),
+    /// followed by the C-style delimiters when `allow_c_style_comments` is set.
+    ///
+    /// The indexes of [`CommentStyle`](lexer::CommentStyle) refer to this syntax.
+    pub fn effective_comment_syntax(&self) -> CommentSyntax {
+        let mut syntax = self.comment_syntax.clone();
+        if self.allow_c_style_comments {
+            syntax.line_prefixes.push("//".to_string());
+            syntax
+                .block_delimiters
+                .push(("/*".to_string(), "*/".to_string()));
+        }
+        syntax
+    }
+
+    /// Returns the options parsing `input` uses: these options, adjusted by a
+    /// leading pragma comment in the input as allowed by
+    /// [`pragma_policy`](Self::pragma_policy).
+    ///
+    /// Tools that run a [`Lexer`](lexer::Lexer) over a file should use these
+    /// options so that they agree with the parser.
+    pub fn for_input(&self, input: &str) -> Result<Self, ConfError> {
+        pragma::leading(input, self.clone())
+    }
+
+    /// Options with every language extension enabled.
+    pub fn all_extensions() -> Self {
+        Self {
+            allow_c_style_comments: true,
+            allow_expression_arguments: true,
+            allow_raw_strings: true,
+            allow_heredocs: true,
+            ..Self::spec_strict()
+        }
+    }
+
+    /// Options for nginx-style files: every simple directive ends with `;`,
+    /// and triple-quoted strings and line continuations are rejected.
+    pub fn nginx_like() -> Self {
+        Self {
+            require_semicolons: true,
+            allow_triple_quotes: false,
+            allow_line_continuations: false,
+            ..Self::spec_strict()
         }
     }
 }
@@ -349,8 +912,82 @@ pub fn parse(input: &str, options: ConfOptions) -> Result<ConfUnit, ConfError> {
     parser.parse()
 }
 
+/// Parses a configuration file given as bytes.
+///
+/// The bytes are decoded with [`encoding::decode`]: a UTF-8 byte order mark is
+/// removed, and UTF-16 input with a byte order mark is transcoded. Spans in
+/// the result refer to the decoded text. Invalid input fails with
+/// [`ConfError::EncodingError`], giving the byte offset and line of the first
+/// invalid sequence.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse_bytes, ConfOptions};
+///
+/// let unit = parse_bytes(b"\xEF\xBB\xBFport 80;", ConfOptions::default()).unwrap();
+/// assert_eq!(unit.directives[0].name.value, "port");
+/// ```
+pub fn parse_bytes(input: &[u8], options: ConfOptions) -> Result<ConfUnit, ConfError> {
+    let text = encoding::decode(input)?;
+    parse(&text, options)
+}
+
+/// Reads a configuration file to its end and parses it like [`parse_bytes`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs::File;
+/// use confetti_rs::{parse_reader, ConfOptions};
+///
+/// let file = File::open("server.conf").unwrap();
+/// let unit = parse_reader(file, ConfOptions::default()).unwrap();
+/// ```
+pub fn parse_reader<R: std::io::Read>(
+    mut reader: R,
+    options: ConfOptions,
+) -> Result<ConfUnit, ConfError> {
+    let mut input = Vec::new();
+    reader.read_to_end(&mut input)?;
+    parse_bytes(&input, options)
+}
+
+/// Parses a configuration string without copying names, arguments or comments.
+///
+/// The returned unit borrows its text from `input`. Use
+/// [`borrowed::ConfUnit::into_owned`] to convert it to a [`ConfUnit`].
+///
+/// # Arguments
+///
+/// * `input` - The configuration string to parse.
+/// * `options` - The options for parsing.
+///
+/// # Returns
+///
+/// A `Result` containing either the borrowed configuration unit or an error.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse_borrowed, ConfOptions};
+///
+/// let input = "server {\n  listen 80;\n}";
+/// let unit = parse_borrowed(input, ConfOptions::default()).unwrap();
+/// assert_eq!(unit.directives[0].children[0].arguments[0].value, "80");
+/// ```
+pub fn parse_borrowed(
+    input: &str,
+    options: ConfOptions,
+) -> Result<borrowed::ConfUnit<'_>, ConfError> {
+    let mut parser = parser::Parser::new(input, options)?;
+    parser.parse_borrowed()
+}
+
 // Re-export key traits from mapper module
 pub use crate::mapper::{FromConf, MapperError, MapperOptions, ToConf, ValueConverter};
+pub use crate::query::{Query, QueryError};
+pub use crate::source_map::{FileId, Span};
 
 // Create convenience wrappers for common operations
 /// Load configuration from a file into a struct.
@@ -361,7 +998,7 @@ pub use crate::mapper::{FromConf, MapperError, MapperOptions, ToConf, ValueConve
 /// # Example
 ///
 /// ```no_run
-/// use confetti_rs::{from_file, FromConf, ConfDirective, MapperError, ValueConverter};
+/// use confetti_rs::{from_file, FromConf, ConfDirective, MapperError};
 ///
 /// #[derive(Debug)]
 /// struct ServerConfig {
@@ -371,20 +1008,10 @@ pub use crate::mapper::{FromConf, MapperError, MapperOptions, ToConf, ValueConve
 ///
 /// impl FromConf for ServerConfig {
 ///     fn from_directive(directive: &ConfDirective) -> Result<Self, MapperError> {
-///         let port = directive.children.iter()
-///             .find(|d| d.name.value == "port")
-///             .and_then(|d| d.arguments.first())
-///             .map(|a| i32::from_conf_value(&a.value))
-///             .transpose()?
-///             .ok_or_else(|| MapperError::MissingField("port".into()))?;
-///
-///         let host = directive.children.iter()
-///             .find(|d| d.name.value == "host")
-///             .and_then(|d| d.arguments.first())
-///             .map(|a| confetti_rs::__private::strip_quotes(&a.value))
-///             .ok_or_else(|| MapperError::MissingField("host".into()))?;
-///
-///         Ok(ServerConfig { port, host })
+///         Ok(ServerConfig {
+///             port: directive.value("port")?,
+///             host: directive.value("host")?,
+///         })
 ///     }
 /// }
 ///
@@ -405,7 +1032,7 @@ pub fn from_file<T: FromConf, P: AsRef<std::path::Path>>(
 /// # Example
 ///
 /// ```
-/// use confetti_rs::{from_str, FromConf, ConfDirective, MapperError, ValueConverter};
+/// use confetti_rs::{from_str, FromConf, ConfDirective, MapperError};
 ///
 /// #[derive(Debug, PartialEq)]
 /// struct ServerConfig {
@@ -415,20 +1042,10 @@ pub fn from_file<T: FromConf, P: AsRef<std::path::Path>>(
 ///
 /// impl FromConf for ServerConfig {
 ///     fn from_directive(directive: &ConfDirective) -> Result<Self, MapperError> {
-///         let port = directive.children.iter()
-///             .find(|d| d.name.value == "port")
-///             .and_then(|d| d.arguments.first())
-///             .map(|a| i32::from_conf_value(&a.value))
-///             .transpose()?
-///             .ok_or_else(|| MapperError::MissingField("port".into()))?;
-///
-///         let host = directive.children.iter()
-///             .find(|d| d.name.value == "host")
-///             .and_then(|d| d.arguments.first())
-///             .map(|a| confetti_rs::__private::strip_quotes(&a.value))
-///             .ok_or_else(|| MapperError::MissingField("host".into()))?;
-///
-///         Ok(ServerConfig { port, host })
+///         Ok(ServerConfig {
+///             port: directive.value("port")?,
+///             host: directive.value("host")?,
+///         })
 ///     }
 /// }
 ///
@@ -455,7 +1072,8 @@ pub fn from_str<T: FromConf>(s: &str) -> Result<T, mapper::MapperError> {
 /// # Example
 ///
 /// ```
-/// use confetti_rs::{to_string, ToConf, ConfDirective, ConfArgument, MapperError, ValueConverter};
+/// use confetti_rs::builder::Directive;
+/// use confetti_rs::{to_string, ToConf, ConfDirective, MapperError, ValueConverter};
 ///
 /// #[derive(Debug)]
 /// struct ServerConfig {
@@ -465,52 +1083,10 @@ pub fn from_str<T: FromConf>(s: &str) -> Result<T, mapper::MapperError> {
 ///
 /// impl ToConf for ServerConfig {
 ///     fn to_directive(&self) -> Result<ConfDirective, MapperError> {
-///         Ok(ConfDirective {
-///             name: ConfArgument {
-///                 value: "ServerConfig".to_string(),
-///                 span: 0..0,
-///                 is_quoted: false,
-///                 is_triple_quoted: false,
-///                 is_expression: false,
-///             },
-///             arguments: vec![],
-///             children: vec![
-///                 ConfDirective {
-///                     name: ConfArgument {
-///                         value: "port".to_string(),
-///                         span: 0..0,
-///                         is_quoted: false,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     },
-///                     arguments: vec![ConfArgument {
-///                         value: self.port.to_conf_value()?,
-///                         span: 0..0,
-///                         is_quoted: false,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     }],
-///                     children: vec![],
-///                 },
-///                 ConfDirective {
-///                     name: ConfArgument {
-///                         value: "host".to_string(),
-///                         span: 0..0,
-///                         is_quoted: false,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     },
-///                     arguments: vec![ConfArgument {
-///                         value: self.host.to_conf_value()?,
-///                         span: 0..0,
-///                         is_quoted: true,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     }],
-///                     children: vec![],
-///                 },
-///             ],
-///         })
+///         Ok(Directive::new("ServerConfig")
+///             .child(Directive::new("port").arg(self.port.to_conf_value()?))
+///             .child(Directive::new("host").quoted(self.host.to_conf_value()?))
+///             .build())
 ///     }
 /// }
 ///
@@ -535,7 +1111,8 @@ pub fn to_string<T: ToConf>(value: &T) -> Result<String, mapper::MapperError> {
 /// # Example
 ///
 /// ```no_run
-/// use confetti_rs::{to_file, ToConf, ConfDirective, ConfArgument, MapperError, ValueConverter};
+/// use confetti_rs::builder::Directive;
+/// use confetti_rs::{to_file, ToConf, ConfDirective, MapperError, ValueConverter};
 ///
 /// #[derive(Debug)]
 /// struct ServerConfig {
@@ -545,52 +1122,10 @@ pub fn to_string<T: ToConf>(value: &T) -> Result<String, mapper::MapperError> {
 ///
 /// impl ToConf for ServerConfig {
 ///     fn to_directive(&self) -> Result<ConfDirective, MapperError> {
-///         Ok(ConfDirective {
-///             name: ConfArgument {
-///                 value: "ServerConfig".to_string(),
-///                 span: 0..0,
-///                 is_quoted: false,
-///                 is_triple_quoted: false,
-///                 is_expression: false,
-///             },
-///             arguments: vec![],
-///             children: vec![
-///                 ConfDirective {
-///                     name: ConfArgument {
-///                         value: "port".to_string(),
-///                         span: 0..0,
-///                         is_quoted: false,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     },
-///                     arguments: vec![ConfArgument {
-///                         value: self.port.to_conf_value()?,
-///                         span: 0..0,
-///                         is_quoted: false,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     }],
-///                     children: vec![],
-///                 },
-///                 ConfDirective {
-///                     name: ConfArgument {
-///                         value: "host".to_string(),
-///                         span: 0..0,
-///                         is_quoted: false,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     },
-///                     arguments: vec![ConfArgument {
-///                         value: self.host.to_conf_value()?,
-///                         span: 0..0,
-///                         is_quoted: true,
-///                         is_triple_quoted: false,
-///                         is_expression: false,
-///                     }],
-///                     children: vec![],
-///                 },
-///             ],
-///         })
+///         Ok(Directive::new("ServerConfig")
+///             .child(Directive::new("port").arg(self.port.to_conf_value()?))
+///             .child(Directive::new("host").quoted(self.host.to_conf_value()?))
+///             .build())
 ///     }
 /// }
 ///
@@ -615,7 +1150,7 @@ mod tests {
     #[test]
     fn test_conf_error_display() {
         let lexer_error = ConfError::LexerError {
-            position: 10,
+            span: Span::point(10),
             message: "Invalid character".to_string(),
         };
         assert_eq!(
@@ -624,7 +1159,7 @@ mod tests {
         );
 
         let parser_error = ConfError::ParserError {
-            position: 20,
+            span: Span::point(20),
             message: "Unexpected token".to_string(),
         };
         assert_eq!(
@@ -731,6 +1266,277 @@ mod tests {
         assert_eq!(conf_unit.directives[0].arguments[0].value, "example.com");
     }
 
+    #[test]
+    fn test_disabled_triple_quotes() {
+        let options = ConfOptions {
+            allow_triple_quotes: false,
+            ..ConfOptions::default()
+        };
+        let result = parse("server \"\"\"text\"\"\";", options.clone());
+        match result {
+            Err(ConfError::LexerError { span, message }) => {
+                assert_eq!(span.start(), 7);
+                assert!(message.contains("allow_triple_quotes"));
+            }
+            other => panic!("Expected LexerError, got {:?}", other),
+        }
+
+        // Ordinary and empty quoted strings are unaffected
+        assert!(parse("server \"\" \"text\";", options).is_ok());
+    }
+
+    #[test]
+    fn test_disabled_line_continuations() {
+        let options = ConfOptions {
+            allow_line_continuations: false,
+            ..ConfOptions::default()
+        };
+        for input in [
+            "server \\\nexample.com;",
+            "server exa\\\nmple.com;",
+            "server \"\"\"a\\\nb\"\"\";",
+        ] {
+            match parse(input, options.clone()) {
+                Err(ConfError::LexerError { message, .. }) => {
+                    assert!(message.contains("allow_line_continuations"), "{}", message);
+                }
+                other => panic!("Expected LexerError for {:?}, got {:?}", input, other),
+            }
+        }
+
+        // Other escapes still work
+        assert!(parse("server a\\;b;", options).is_ok());
+    }
+
+    #[test]
+    fn test_require_semicolons() {
+        let options = ConfOptions {
+            require_semicolons: true,
+            ..ConfOptions::default()
+        };
+        assert!(parse("a 1;\nb {\n  c;\n}\n", options.clone()).is_ok());
+        for input in ["a 1\nb;", "a 1", "b { c }"] {
+            match parse(input, options.clone()) {
+                Err(ConfError::ParserError { message, .. }) => {
+                    assert!(message.contains("require_semicolons"), "{}", message);
+                }
+                other => panic!("Expected ParserError for {:?}, got {:?}", input, other),
+            }
+        }
+        assert!(parse("a 1\nb", ConfOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_option_presets() {
+        // Without C-style comments, `// note` is a directive
+        let input = "// note\nserver 80\n";
+        let strict = parse(input, ConfOptions::spec_strict()).unwrap();
+        assert_eq!(strict.directives.len(), 2);
+        let extended = parse(input, ConfOptions::all_extensions()).unwrap();
+        assert_eq!(extended.directives.len(), 1);
+        assert_eq!(extended.comments.len(), 1);
+        assert!(parse(input, ConfOptions::nginx_like()).is_err());
+
+        let nginx = "server {\n  listen 80;\n  server_name example.com;\n}\n";
+        assert!(parse(nginx, ConfOptions::nginx_like()).is_ok());
+        assert!(parse("a \"\"\"b\"\"\";", ConfOptions::nginx_like()).is_err());
+    }
+
+    #[test]
+    fn test_raw_strings_and_heredocs() {
+        let input = "regex r\"\\w+\\.conf\";\nsql <<END\n  SELECT *\n\n    FROM t\n  END\n";
+        let unit = parse(input, ConfOptions::all_extensions()).unwrap();
+        let regex = &unit.directives[0].arguments[0];
+        assert!(regex.is_raw && regex.is_quoted);
+        assert_eq!(regex.value, "r\"\\w+\\.conf\"");
+        assert_eq!(regex.text(), "\\w+\\.conf");
+
+        let sql = &unit.directives[1].arguments[0];
+        assert!(sql.is_heredoc);
+        assert_eq!(sql.text(), "SELECT *\n\n  FROM t");
+        assert_eq!(unit.directives.len(), 2);
+
+        // Both are opt-in; without them the same text means something else
+        let plain = parse(input, ConfOptions::default()).unwrap();
+        assert_eq!(plain.directives[0].arguments[0].value, "r");
+        assert_eq!(plain.directives[1].arguments[0].value, "<<END");
+        let strict = ConfOptions::spec_strict();
+        assert!(!strict.allow_raw_strings && !strict.allow_heredocs);
+        // Hand-built values without their delimiters do not panic
+        for value in ["", "\u{e9}", "r", "\"\u{e9}"] {
+            let argument = ConfArgument {
+                value: value.to_string(),
+                span: Span::default(),
+                is_quoted: true,
+                is_triple_quoted: false,
+                is_raw: true,
+                is_heredoc: false,
+                is_expression: false,
+            };
+            argument.text();
+            ConfArgument {
+                is_raw: false,
+                ..argument
+            }
+            .text();
+        }
+    }
+
+    #[test]
+    fn test_comment_syntax() {
+        let ini = "; settings\n[server]\nport 80\n; port 8080\n";
+        let options = ConfOptions {
+            comment_syntax: CommentSyntax::ini(),
+            ..ConfOptions::default()
+        };
+        let unit = parse(ini, options).unwrap();
+        assert_eq!(unit.directives.len(), 2);
+        assert_eq!(unit.directives[1].arguments.len(), 1);
+        assert_eq!(unit.comments.len(), 2);
+
+        let lua = "--[[ header\n]]\nname x\n-- name y\n";
+        let options = ConfOptions {
+            comment_syntax: CommentSyntax::lua(),
+            ..ConfOptions::default()
+        };
+        let unit = parse(lua, options.clone()).unwrap();
+        assert_eq!(unit.directives.len(), 1);
+        assert!(unit.comments[0].is_multi_line);
+        assert!(!unit.comments[1].is_multi_line);
+
+        let syntax = ConfOptions {
+            allow_c_style_comments: true,
+            ..options
+        }
+        .effective_comment_syntax();
+        assert_eq!(syntax.line_prefixes, vec!["--", "//"]);
+        assert_eq!(syntax.block_delimiters.len(), 2);
+    }
+
+    #[test]
+    fn test_argument_text() {
+        let unit = parse(
+            "a \"x\\\"y\" \"\"\"m\\\nn\"\"\" p\\;q",
+            ConfOptions::default(),
+        )
+        .unwrap();
+        let texts: Vec<String> = unit.directives[0]
+            .arguments
+            .iter()
+            .map(ConfArgument::text)
+            .collect();
+        assert_eq!(texts, vec!["x\"y", "mn", "p;q"]);
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let input = "# a comment\nserver a b c {\n  listen 80;\n}\nlast \"quoted\";\n";
+        assert!(parse(input, ConfOptions::default()).is_ok());
+
+        let cases = [
+            (
+                ConfOptions {
+                    max_input_bytes: 10,
+                    ..ConfOptions::default()
+                },
+                Limit::InputBytes,
+                10,
+            ),
+            (
+                ConfOptions {
+                    max_directives: 2,
+                    ..ConfOptions::default()
+                },
+                Limit::Directives,
+                input.find("last").unwrap(),
+            ),
+            (
+                ConfOptions {
+                    max_arguments: 2,
+                    ..ConfOptions::default()
+                },
+                Limit::Arguments,
+                input.find(" c {").unwrap() + 1,
+            ),
+            (
+                ConfOptions {
+                    max_argument_length: 6,
+                    ..ConfOptions::default()
+                },
+                Limit::ArgumentLength,
+                input.find("\"quoted").unwrap(),
+            ),
+            (
+                ConfOptions {
+                    max_comment_length: 5,
+                    ..ConfOptions::default()
+                },
+                Limit::CommentLength,
+                0,
+            ),
+            (
+                ConfOptions {
+                    max_tokens: 5,
+                    ..ConfOptions::default()
+                },
+                Limit::Tokens,
+                input.find(" c {").unwrap() + 1,
+            ),
+        ];
+        for (options, expected_limit, expected_position) in cases {
+            match parse(input, options) {
+                Err(ConfError::LimitExceeded { span, limit, .. }) => {
+                    assert_eq!(limit, expected_limit);
+                    assert_eq!(span.start(), expected_position, "{:?}", limit);
+                }
+                other => panic!("Expected {:?} limit, got {:?}", expected_limit, other),
+            }
+        }
+
+        let error = parse(
+            "a b c",
+            ConfOptions {
+                max_arguments: 1,
+                ..ConfOptions::default()
+            },
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Limit exceeded at position 4: number of arguments per directive is limited to 1"
+        );
+    }
+
+    #[test]
+    fn test_parse_bytes_and_reader() {
+        let text = "server caf\u{e9} {\n  listen 80;\n}\n";
+        let expected = parse(text, ConfOptions::default()).unwrap();
+
+        let mut utf8_bom = b"\xEF\xBB\xBF".to_vec();
+        utf8_bom.extend_from_slice(text.as_bytes());
+        let mut utf16_le = vec![0xFF, 0xFE];
+        let mut utf16_be = vec![0xFE, 0xFF];
+        for unit in text.encode_utf16() {
+            utf16_le.extend_from_slice(&unit.to_le_bytes());
+            utf16_be.extend_from_slice(&unit.to_be_bytes());
+        }
+
+        for input in [text.as_bytes(), &utf8_bom, &utf16_le, &utf16_be] {
+            let unit = parse_bytes(input, ConfOptions::default()).unwrap();
+            assert_eq!(unit.directives, expected.directives);
+            assert_eq!(unit.directives[0].name.span.range(), 0..6);
+
+            let unit = parse_reader(input, ConfOptions::default()).unwrap();
+            assert_eq!(unit.directives, expected.directives);
+        }
+
+        let error = parse_bytes(b"a;\nb \xFF;", ConfOptions::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Encoding error at position 5 (line 2): Invalid UTF-8"
+        );
+    }
+
     // Phase 5: Comprehensive tests
 
     #[test]
@@ -882,6 +1688,29 @@ mod tests {
         assert_eq!(conf_unit.directives.len(), 2);
     }
 
+    #[test]
+    fn test_equality_ignores_spans() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let a = parse("server {\n  listen 80;\n}", ConfOptions::default()).unwrap();
+        let b = parse("server { listen   80; }", ConfOptions::default()).unwrap();
+        let c = parse("server { listen 81; }", ConfOptions::default()).unwrap();
+        assert_eq!(a.directives, b.directives);
+        assert_ne!(a.directives, c.directives);
+
+        let hash = |d: &ConfDirective| {
+            let mut hasher = DefaultHasher::new();
+            d.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&a.directives[0]), hash(&b.directives[0]));
+
+        // Quoting is part of the value
+        let quoted = parse("a \"x\";", ConfOptions::default()).unwrap();
+        let bare = parse("a x;", ConfOptions::default()).unwrap();
+        assert_ne!(quoted.directives, bare.directives);
+    }
+
     #[test]
     fn test_mixed_line_endings() {
         let input = "server localhost;\nport 8080;\r\nhost example.com;";
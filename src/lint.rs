@@ -0,0 +1,261 @@
+//! Opt-in Unicode security checks for parsed configuration.
+//!
+//! The lexer rejects bidirectional formatting characters when
+//! [`ConfOptions::forbid_bidi_characters`](crate::ConfOptions::forbid_bidi_characters)
+//! is set. The checks here go further, but report warnings instead of failing
+//! the parse: invisible characters in unquoted arguments, directive names
+//! that mix scripts, and directive names that only differ from a known name
+//! by confusable characters, following
+//! [UTS #39](https://www.unicode.org/reports/tr39/).
+//!
+//! This module requires the `lint` feature.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use unicode_security::confusable_detection::skeleton;
+use unicode_security::MixedScript;
+
+use crate::{ConfArgument, ConfDirective, ConfUnit, Span};
+
+/// The kind of a Unicode security warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// An unquoted argument contains an invisible character, such as a
+    /// zero-width space or joiner.
+    InvisibleCharacter,
+    /// A directive name mixes characters from several scripts.
+    MixedScript,
+    /// A directive name is not a known name, but looks like one.
+    Confusable,
+}
+
+/// A Unicode security warning located in the source text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// The kind of the warning.
+    pub kind: WarningKind,
+    /// The span of the offending text in the source.
+    pub span: Span,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Warning at position {}: {}",
+            self.span.start(),
+            self.message
+        )
+    }
+}
+
+/// Options for the Unicode security checks.
+#[derive(Debug, Clone, Default)]
+pub struct LintOptions {
+    /// Directive names expected by the schema. Names that are not in this
+    /// list but are confusable with one of its entries are reported.
+    pub known_names: Vec<String>,
+}
+
+/// Checks a unit for invisible characters and mixed-script directive names.
+///
+/// Spans point into the source the unit was parsed from.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse, ConfOptions};
+/// use confetti_rs::lint::{check, WarningKind};
+///
+/// // The "а" in "аdmin" is CYRILLIC SMALL LETTER A
+/// let unit = parse("\u{430}dmin on;\nuser bob\u{200B};", ConfOptions::default()).unwrap();
+///
+/// let warnings = check(&unit);
+/// assert_eq!(warnings.len(), 2);
+/// assert_eq!(warnings[0].kind, WarningKind::MixedScript);
+/// assert_eq!(warnings[1].kind, WarningKind::InvisibleCharacter);
+/// assert_eq!(warnings[1].span.range(), 19..22);
+/// ```
+pub fn check(unit: &ConfUnit) -> Vec<Warning> {
+    check_with_options(unit, &LintOptions::default())
+}
+
+/// Checks a unit with options, additionally reporting directive names that
+/// are confusable with [`LintOptions::known_names`].
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse, ConfOptions};
+/// use confetti_rs::lint::{check_with_options, LintOptions, WarningKind};
+///
+/// // The "о" in "lоg" is CYRILLIC SMALL LETTER O
+/// let unit = parse("l\u{43e}g debug;", ConfOptions::default()).unwrap();
+/// let options = LintOptions {
+///     known_names: vec!["log".to_string()],
+/// };
+///
+/// let warnings = check_with_options(&unit, &options);
+/// assert_eq!(warnings.len(), 2);
+/// assert_eq!(warnings[1].kind, WarningKind::Confusable);
+/// ```
+pub fn check_with_options(unit: &ConfUnit, options: &LintOptions) -> Vec<Warning> {
+    let known: HashMap<String, &str> = options
+        .known_names
+        .iter()
+        .map(|name| (skeleton(name).collect(), name.as_str()))
+        .collect();
+
+    let mut warnings = Vec::new();
+    let mut stack: Vec<&ConfDirective> = unit.directives.iter().rev().collect();
+    while let Some(directive) = stack.pop() {
+        check_name(&directive.name, &known, &mut warnings);
+        check_invisible(&directive.name, &mut warnings);
+        for argument in &directive.arguments {
+            check_invisible(argument, &mut warnings);
+        }
+        stack.extend(directive.children.iter().rev());
+    }
+    warnings
+}
+
+/// Reports mixed-script names and names confusable with a known name.
+fn check_name(name: &ConfArgument, known: &HashMap<String, &str>, warnings: &mut Vec<Warning>) {
+    if name.is_quoted || (name.value.is_ascii() && known.is_empty()) {
+        return;
+    }
+
+    if !name.value.as_str().is_single_script() {
+        warnings.push(Warning {
+            kind: WarningKind::MixedScript,
+            span: name.span,
+            message: format!(
+                "Directive name \"{}\" mixes characters from several scripts",
+                name.value.escape_default()
+            ),
+        });
+    }
+
+    let name_skeleton: String = skeleton(&name.value).collect();
+    if let Some(&similar) = known.get(&name_skeleton) {
+        if similar != name.value {
+            warnings.push(Warning {
+                kind: WarningKind::Confusable,
+                span: name.span,
+                message: format!(
+                    "Directive name \"{}\" is confusable with \"{}\"",
+                    name.value.escape_default(),
+                    similar
+                ),
+            });
+        }
+    }
+}
+
+/// Reports invisible characters in an unquoted argument, one warning each.
+fn check_invisible(argument: &ConfArgument, warnings: &mut Vec<Warning>) {
+    if argument.is_quoted || argument.is_heredoc || argument.value.is_ascii() {
+        return;
+    }
+
+    for (index, c) in argument.value.char_indices() {
+        if is_invisible(c) {
+            let start = argument.span.start() + index;
+            warnings.push(Warning {
+                kind: WarningKind::InvisibleCharacter,
+                span: Span::new(argument.span.file(), start..start + c.len_utf8()),
+                message: format!(
+                    "Invisible character U+{:04X} in unquoted argument \"{}\"",
+                    c as u32,
+                    argument.value.escape_default()
+                ),
+            });
+        }
+    }
+}
+
+/// Returns whether the character renders as nothing in most contexts.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}' | // SOFT HYPHEN
+        '\u{034F}' | // COMBINING GRAPHEME JOINER
+        '\u{115F}' | // HANGUL CHOSEONG FILLER
+        '\u{1160}' | // HANGUL JUNGSEONG FILLER
+        '\u{180E}' | // MONGOLIAN VOWEL SEPARATOR
+        '\u{200B}' | // ZERO WIDTH SPACE
+        '\u{200C}' | // ZERO WIDTH NON-JOINER
+        '\u{200D}' | // ZERO WIDTH JOINER
+        '\u{2060}' | // WORD JOINER
+        '\u{2061}' | // FUNCTION APPLICATION
+        '\u{2062}' | // INVISIBLE TIMES
+        '\u{2063}' | // INVISIBLE SEPARATOR
+        '\u{2064}' | // INVISIBLE PLUS
+        '\u{3164}' | // HANGUL FILLER
+        '\u{FEFF}' | // ZERO WIDTH NO-BREAK SPACE
+        '\u{FFA0}' // HALFWIDTH HANGUL FILLER
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, ConfOptions};
+
+    fn kinds(warnings: &[Warning]) -> Vec<WarningKind> {
+        warnings.iter().map(|warning| warning.kind).collect()
+    }
+
+    #[test]
+    fn test_clean_input_has_no_warnings() {
+        let input = "server caf\u{e9} {\n  name \"\u{200B}\";\n  \u{43f}\u{440}\u{438}\u{432}\u{435}\u{442} 1;\n}";
+        let unit = parse(input, ConfOptions::default()).unwrap();
+        assert!(check(&unit).is_empty());
+    }
+
+    #[test]
+    fn test_invisible_characters() {
+        let input = "a {\n  user ro\u{200D}ot\u{FEFF};\n}\nb\u{200C} \"quoted\u{200B}\";";
+        let unit = parse(input, ConfOptions::default()).unwrap();
+        let warnings = check(&unit);
+        assert_eq!(kinds(&warnings), vec![WarningKind::InvisibleCharacter; 3]);
+
+        let spans: Vec<&str> = warnings
+            .iter()
+            .map(|warning| &input[warning.span.range()])
+            .collect();
+        assert_eq!(spans, vec!["\u{200D}", "\u{FEFF}", "\u{200C}"]);
+        assert!(warnings[0].message.contains("U+200D"));
+    }
+
+    #[test]
+    fn test_mixed_script_names() {
+        // Cyrillic "а" in "admin", Greek "ο" in "port", nested in a block
+        let input = "\u{430}dmin on;\nserver {\n  p\u{3bf}rt 80;\n}";
+        let unit = parse(input, ConfOptions::default()).unwrap();
+        let warnings = check(&unit);
+        assert_eq!(kinds(&warnings), vec![WarningKind::MixedScript; 2]);
+        assert_eq!(&input[warnings[1].span.range()], "p\u{3bf}rt");
+    }
+
+    #[test]
+    fn test_confusable_names() {
+        // Entirely Cyrillic, so not mixed-script, but it looks like "ssl"
+        let input = "\u{455}\u{455}\u{406} on;\nssl off;\nlisten 80;";
+        let unit = parse(input, ConfOptions::default()).unwrap();
+        let options = LintOptions {
+            known_names: vec!["ssl".to_string(), "listen".to_string()],
+        };
+
+        let warnings = check_with_options(&unit, &options);
+        assert_eq!(kinds(&warnings), vec![WarningKind::Confusable]);
+        assert_eq!(warnings[0].span.range(), 0..6);
+        assert!(warnings[0].message.contains("\"ssl\""));
+
+        // Without known names there is nothing to compare against
+        assert!(check(&unit).is_empty());
+    }
+}
@@ -4,7 +4,7 @@ use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
-use crate::{parse, ConfDirective, ConfOptions};
+use crate::{parse, ConfArgument, ConfDirective, ConfOptions, ConfUnit, Span};
 
 /// Error type for mapping operations
 #[derive(Debug)]
@@ -19,6 +19,30 @@ pub enum MapperError {
     ConversionError(String),
     /// Error when a required field is missing
     MissingField(String),
+    /// An error about a directive or argument, with its span in the source text
+    Located {
+        /// The span of the directive or argument at fault.
+        span: Span,
+        /// The error.
+        error: Box<MapperError>,
+    },
+}
+
+impl MapperError {
+    /// Returns the span of the directive or argument the error is about, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            MapperError::Located { span, .. } => Some(*span),
+            _ => None,
+        }
+    }
+
+    fn at(self, span: Span) -> Self {
+        MapperError::Located {
+            span,
+            error: Box::new(self),
+        }
+    }
 }
 
 impl Error for MapperError {}
@@ -31,6 +55,9 @@ impl fmt::Display for MapperError {
             MapperError::IoError(err) => write!(f, "I/O error: {}", err),
             MapperError::ConversionError(msg) => write!(f, "Conversion error: {}", msg),
             MapperError::MissingField(name) => write!(f, "Missing required field: {}", name),
+            MapperError::Located { span, error } => {
+                write!(f, "{} at {}..{}", error, span.start(), span.end())
+            }
         }
     }
 }
@@ -65,8 +92,12 @@ pub trait FromConf: Sized {
     }
 
     /// Create an instance from a file
+    ///
+    /// The file is decoded like [`crate::parse_bytes`], so a byte order mark
+    /// is handled and invalid UTF-8 is reported with its offset and line.
     fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, MapperError> {
-        let content = fs::read_to_string(path)?;
+        let bytes = fs::read(path)?;
+        let content = crate::encoding::decode(&bytes)?;
         Self::from_str(&content)
     }
 }
@@ -95,6 +126,172 @@ pub trait ToConf {
     }
 }
 
+// Typed access to the values of directives, which is also what the `ConfMap`
+// derive generates code against.
+
+impl ConfDirective {
+    /// Returns the first child with a name.
+    pub fn child(&self, name: &str) -> Option<&ConfDirective> {
+        self.children.iter().find(|child| child.name.has_text(name))
+    }
+
+    /// Returns the children with a name.
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ConfDirective> {
+        named(&self.children, name)
+    }
+
+    /// Converts an argument, excluding the name, to a typed value.
+    ///
+    /// The value is the [text](ConfArgument::text) of the argument, so quotes
+    /// and escapes are removed first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use confetti_rs::{parse, ConfOptions};
+    ///
+    /// let unit = parse("listen 127.0.0.1 \"8080\";", ConfOptions::default()).unwrap();
+    /// let listen = &unit.directives[0];
+    /// assert_eq!(listen.arg::<u16>(1).unwrap(), 8080);
+    ///
+    /// let error = listen.arg::<u16>(0).unwrap_err();
+    /// assert_eq!(error.span().map(|span| span.range()), Some(7..16));
+    /// ```
+    pub fn arg<T: ValueConverter>(&self, index: usize) -> Result<T, MapperError> {
+        match self.arguments.get(index) {
+            Some(argument) => convert(argument),
+            None => Err(MapperError::MissingField(format!(
+                "argument {} of {}",
+                index + 1,
+                self.name.value
+            ))
+            .at(self.span())),
+        }
+    }
+
+    /// Converts every argument, excluding the name, to a typed value.
+    pub fn args<T: ValueConverter>(&self) -> Result<Vec<T>, MapperError> {
+        self.arguments.iter().map(convert).collect()
+    }
+
+    /// Converts the first argument of the first child with a name.
+    pub fn value<T: ValueConverter>(&self, name: &str) -> Result<T, MapperError> {
+        match self.child(name) {
+            Some(child) => child.arg(0),
+            None => Err(MapperError::MissingField(name.to_string()).at(self.span())),
+        }
+    }
+
+    /// Converts the first argument of the descendant at a path of child
+    /// names separated by dots, such as `database.port`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use confetti_rs::{parse, ConfOptions};
+    ///
+    /// let unit = parse("app {\n  database { port 5432; }\n}", ConfOptions::default()).unwrap();
+    /// let app = &unit.directives[0];
+    /// assert_eq!(app.get::<u16>("database.port").unwrap(), 5432);
+    /// assert!(app.get::<u16>("database.host").is_err());
+    /// ```
+    pub fn get<T: ValueConverter>(&self, path: &str) -> Result<T, MapperError> {
+        descend(self, path.split('.'), path)?.arg(0)
+    }
+
+    /// Returns whether a child with a name turns a flag on: it has no
+    /// arguments, or its first argument is a true boolean such as `on`.
+    pub fn has_flag(&self, name: &str) -> bool {
+        is_flag_on(self.child(name))
+    }
+
+    /// Returns the span from the start of the name to the end of the last
+    /// argument.
+    fn span(&self) -> Span {
+        let end = self
+            .arguments
+            .last()
+            .map_or(self.name.span, |argument| argument.span);
+        self.name.span.to(end)
+    }
+}
+
+impl ConfUnit {
+    /// Returns the first top-level directive with a name.
+    pub fn child(&self, name: &str) -> Option<&ConfDirective> {
+        self.directives
+            .iter()
+            .find(|directive| directive.name.has_text(name))
+    }
+
+    /// Returns the top-level directives with a name.
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ConfDirective> {
+        named(&self.directives, name)
+    }
+
+    /// Converts the first argument of the first top-level directive with a name.
+    pub fn value<T: ValueConverter>(&self, name: &str) -> Result<T, MapperError> {
+        match self.child(name) {
+            Some(directive) => directive.arg(0),
+            None => Err(MapperError::MissingField(name.to_string())),
+        }
+    }
+
+    /// Converts the first argument of the directive at a path of names
+    /// separated by dots, such as `database.port`.
+    pub fn get<T: ValueConverter>(&self, path: &str) -> Result<T, MapperError> {
+        let mut names = path.split('.');
+        let first = names.next().unwrap_or_default();
+        let directive = self
+            .child(first)
+            .ok_or_else(|| MapperError::MissingField(path.to_string()))?;
+        descend(directive, names, path)?.arg(0)
+    }
+
+    /// Returns whether a top-level directive with a name turns a flag on: it
+    /// has no arguments, or its first argument is a true boolean such as `on`.
+    pub fn has_flag(&self, name: &str) -> bool {
+        is_flag_on(self.child(name))
+    }
+}
+
+fn named<'a>(
+    directives: &'a [ConfDirective],
+    name: &'a str,
+) -> impl Iterator<Item = &'a ConfDirective> {
+    directives
+        .iter()
+        .filter(move |directive| directive.name.has_text(name))
+}
+
+/// Compares a name by its text, so quoted names match too.
+fn convert<T: ValueConverter>(argument: &ConfArgument) -> Result<T, MapperError> {
+    T::from_conf_value(&argument.text()).map_err(|error| error.at(argument.span))
+}
+
+/// Follows child names from a directive, reporting a missing child as the
+/// whole `path` at the span of its parent.
+fn descend<'a, 'b>(
+    mut directive: &'a ConfDirective,
+    names: impl Iterator<Item = &'b str>,
+    path: &str,
+) -> Result<&'a ConfDirective, MapperError> {
+    for name in names {
+        directive = directive
+            .child(name)
+            .ok_or_else(|| MapperError::MissingField(path.to_string()).at(directive.span()))?;
+    }
+    Ok(directive)
+}
+
+fn is_flag_on(directive: Option<&ConfDirective>) -> bool {
+    match directive {
+        Some(directive) if directive.arguments.is_empty() => true,
+        Some(directive) => directive.arg::<bool>(0).unwrap_or(false),
+        None => false,
+    }
+}
+
 /// Options for mapper configuration
 #[derive(Debug, Clone)]
 pub struct MapperOptions {
@@ -132,7 +329,10 @@ fn serialize_directive(
     // Write arguments
     for arg in &directive.arguments {
         output.push(' ');
-        if arg.is_quoted {
+        if arg.is_raw || arg.is_heredoc {
+            // The value holds the delimiters, which have no escapes to add
+            output.push_str(&arg.value);
+        } else if arg.is_quoted {
             output.push('"');
             // Remove quotes if they already exist in the value (with length safety check)
             let mut value =
@@ -550,25 +750,31 @@ mod tests {
         let directive = ConfDirective {
             name: ConfArgument {
                 value: "TestConfig".to_string(),
-                span: 0..0,
+                span: Span::default(),
                 is_quoted: false,
                 is_triple_quoted: false,
+                is_raw: false,
+                is_heredoc: false,
                 is_expression: false,
             },
             arguments: vec![],
             children: vec![ConfDirective {
                 name: ConfArgument {
                     value: "host".to_string(),
-                    span: 0..0,
+                    span: Span::default(),
                     is_quoted: false,
                     is_triple_quoted: false,
+                    is_raw: false,
+                    is_heredoc: false,
                     is_expression: false,
                 },
                 arguments: vec![ConfArgument {
                     value: "127.0.0.1,".to_string(),
-                    span: 0..0,
+                    span: Span::default(),
                     is_quoted: true,
                     is_triple_quoted: false,
+                    is_raw: false,
+                    is_heredoc: false,
                     is_expression: false,
                 }],
                 children: vec![],
@@ -584,31 +790,53 @@ mod tests {
         assert!(!output.contains("\"127.0.0.1,\""));
     }
 
+    #[test]
+    fn test_serialize_raw_strings_and_heredocs() {
+        let input = "app {\n  pattern r#\"^\\d+ \"x\"$\"#;\n  script <<SH\n    echo \\n\n      done\n    SH\n}\n";
+        let options = ConfOptions::all_extensions();
+        let unit = crate::parse(input, options.clone()).unwrap();
+
+        let mut output = String::new();
+        serialize_directive(&unit.directives[0], &mut output, 0).unwrap();
+        let reparsed = crate::parse(&output, options).unwrap();
+        assert_eq!(reparsed.directives, unit.directives);
+
+        let children = &reparsed.directives[0].children;
+        assert_eq!(children[0].arguments[0].text(), r#"^\d+ "x"$"#);
+        assert_eq!(children[1].arguments[0].text(), "echo \\n\n  done");
+    }
+
     #[test]
     fn test_serialize_numeric_without_quotes() {
         // Create a test directive with a numeric value
         let directive = ConfDirective {
             name: ConfArgument {
                 value: "TestConfig".to_string(),
-                span: 0..0,
+                span: Span::default(),
                 is_quoted: false,
                 is_triple_quoted: false,
+                is_raw: false,
+                is_heredoc: false,
                 is_expression: false,
             },
             arguments: vec![],
             children: vec![ConfDirective {
                 name: ConfArgument {
                     value: "port".to_string(),
-                    span: 0..0,
+                    span: Span::default(),
                     is_quoted: false,
                     is_triple_quoted: false,
+                    is_raw: false,
+                    is_heredoc: false,
                     is_expression: false,
                 },
                 arguments: vec![ConfArgument {
                     value: "3000".to_string(),
-                    span: 0..0,
+                    span: Span::default(),
                     is_quoted: false,
                     is_triple_quoted: false,
+                    is_raw: false,
+                    is_heredoc: false,
                     is_expression: false,
                 }],
                 children: vec![],
@@ -630,9 +858,11 @@ mod tests {
         let directive = ConfDirective {
             name: ConfArgument {
                 value: "ServerConfig".to_string(),
-                span: 0..0,
+                span: Span::default(),
                 is_quoted: false,
                 is_triple_quoted: false,
+                is_raw: false,
+                is_heredoc: false,
                 is_expression: false,
             },
             arguments: vec![],
@@ -640,16 +870,20 @@ mod tests {
                 ConfDirective {
                     name: ConfArgument {
                         value: "host".to_string(),
-                        span: 0..0,
+                        span: Span::default(),
                         is_quoted: false,
                         is_triple_quoted: false,
+                        is_raw: false,
+                        is_heredoc: false,
                         is_expression: false,
                     },
                     arguments: vec![ConfArgument {
                         value: "127.0.0.1,".to_string(),
-                        span: 0..0,
+                        span: Span::default(),
                         is_quoted: true,
                         is_triple_quoted: false,
+                        is_raw: false,
+                        is_heredoc: false,
                         is_expression: false,
                     }],
                     children: vec![],
@@ -657,16 +891,20 @@ mod tests {
                 ConfDirective {
                     name: ConfArgument {
                         value: "port".to_string(),
-                        span: 0..0,
+                        span: Span::default(),
                         is_quoted: false,
                         is_triple_quoted: false,
+                        is_raw: false,
+                        is_heredoc: false,
                         is_expression: false,
                     },
                     arguments: vec![ConfArgument {
                         value: "3000".to_string(),
-                        span: 0..0,
+                        span: Span::default(),
                         is_quoted: false,
                         is_triple_quoted: false,
+                        is_raw: false,
+                        is_heredoc: false,
                         is_expression: false,
                     }],
                     children: vec![],
@@ -684,6 +922,34 @@ mod tests {
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn test_from_file_decodes_bytes() {
+        struct Name(String);
+
+        impl FromConf for Name {
+            fn from_directive(directive: &ConfDirective) -> Result<Self, MapperError> {
+                Ok(Name(directive.name.value.clone()))
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("confetti-from-file-{}", std::process::id()));
+
+        // A UTF-8 byte order mark is not part of the directive name
+        fs::write(&path, b"\xEF\xBB\xBFserver {\n  port 80;\n}\n").unwrap();
+        assert_eq!(Name::from_file(&path).unwrap().0, "server");
+
+        // Invalid UTF-8 is reported with its offset and line
+        fs::write(&path, b"server {\n  port \xC0;\n}\n").unwrap();
+        let error = Name::from_file(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        match error {
+            MapperError::ParseError(message) => {
+                assert!(message.contains("position 16 (line 2)"), "{}", message);
+            }
+            other => panic!("Expected ParseError, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_to_conf_value_string_with_quotes() {
         // Test that string values with existing quotes have them removed
@@ -717,4 +983,54 @@ mod tests {
         let bool_value = true;
         assert!(!bool_value.requires_quotes());
     }
+
+    const ACCESSOR_INPUT: &str = "app {\n  listen 80 \"8080\";\n  listen 443;\n  \"log level\" debug;\n  database {\n    port five;\n  }\n  debug;\n  verbose off;\n}\ntimeout 30;\n";
+
+    #[test]
+    fn test_accessors() {
+        let unit = crate::parse(ACCESSOR_INPUT, ConfOptions::default()).unwrap();
+        let app = unit.child("app").unwrap();
+
+        assert_eq!(app.children_named("listen").count(), 2);
+        assert_eq!(
+            app.child("listen").unwrap().args::<u16>().unwrap(),
+            [80, 8080]
+        );
+        assert_eq!(app.value::<String>("log level").unwrap(), "debug");
+        assert_eq!(unit.value::<u32>("timeout").unwrap(), 30);
+        assert!(app.has_flag("debug"));
+        assert!(!app.has_flag("verbose"));
+        assert!(!app.has_flag("missing"));
+        assert!(unit.child("listen").is_none());
+    }
+
+    #[test]
+    fn test_accessor_errors_carry_spans() {
+        let unit = crate::parse(ACCESSOR_INPUT, ConfOptions::default()).unwrap();
+
+        // A value that does not convert points at the argument
+        let error = unit.get::<u16>("app.database.port").unwrap_err();
+        let span = error.span().unwrap();
+        assert_eq!(&ACCESSOR_INPUT[span.range()], "five");
+        assert!(matches!(
+            error,
+            MapperError::Located { ref error, .. } if matches!(**error, MapperError::ConversionError(_))
+        ));
+
+        // A missing directive points at its parent and names the whole path
+        let error = unit.get::<u16>("app.database.host").unwrap_err();
+        assert_eq!(&ACCESSOR_INPUT[error.span().unwrap().range()], "database");
+        assert!(error.to_string().contains("app.database.host"));
+
+        // A missing argument points at the directive
+        let listen = unit.child("app").unwrap().child("listen").unwrap();
+        let error = listen.arg::<u16>(2).unwrap_err();
+        assert_eq!(
+            &ACCESSOR_INPUT[error.span().unwrap().range()],
+            "listen 80 \"8080\""
+        );
+
+        // There is no directive to point at for a missing top-level name
+        assert!(unit.get::<u16>("server.port").unwrap_err().span().is_none());
+    }
 }
@@ -1,7 +1,13 @@
-use crate::lexer::{Lexer, Token, TokenType};
-use crate::{ConfArgument, ConfComment, ConfDirective, ConfError, ConfOptions, ConfUnit};
+use std::borrow::Cow;
+
+use crate::borrowed::{ConfArgument, ConfComment, ConfDirective, ConfUnit};
+use crate::lexer::{limit_exceeded, CommentStyle, Lexer, Token, TokenType};
+use crate::{ConfError, ConfOptions, FileId, Limit, Span};
 
 /// Parser for the configuration language.
+///
+/// The parser keeps open blocks on an explicit stack rather than recursing,
+/// so nesting is bounded only by [`ConfOptions::max_depth`] and memory.
 pub struct Parser<'a> {
     /// The lexer used to tokenize the input.
     lexer: Lexer<'a>,
@@ -9,13 +15,176 @@ pub struct Parser<'a> {
     current_token: Token,
     /// The options for the parser.
     options: ConfOptions,
-    /// The current depth of nested directives.
-    current_depth: usize,
+    /// The number of directives parsed so far.
+    directives: usize,
+    /// The depth of the block whose body is being parsed, or 0 for a whole
+    /// unit.
+    depth: usize,
+    /// The file the spans of the unit and of errors belong to.
+    file: FileId,
+}
+
+/// A block directive whose closing brace has not been reached yet.
+struct OpenBlock<'a, T: Tree<'a>> {
+    /// The name of the directive.
+    name: T::Argument,
+    /// The arguments of the directive.
+    arguments: T::Arguments,
+    /// The child directives parsed so far.
+    children: T::Children,
+}
+
+/// The kind of tree a parser builds, so that an owned tree or an arena is
+/// built directly instead of being converted from a borrowed one.
+///
+/// The tree collects the arguments of a directive and the children of a
+/// block itself, so that it can store them without a vector per directive.
+pub(crate) trait Tree<'a> {
+    type Argument;
+    type Arguments: Default;
+    type Directive;
+    type Children: Default;
+    type Comment;
+    type Unit;
+
+    fn argument(&mut self, argument: ConfArgument<'a>) -> Self::Argument;
+    fn push_argument(&mut self, arguments: &mut Self::Arguments, argument: Self::Argument);
+    fn directive(
+        &mut self,
+        name: Self::Argument,
+        arguments: Self::Arguments,
+        children: Self::Children,
+    ) -> Self::Directive;
+    fn push_child(&mut self, children: &mut Self::Children, child: Self::Directive);
+    fn comment(&mut self, comment: ConfComment<'a>) -> Self::Comment;
+    fn unit(&mut self, directives: Self::Children, comments: Vec<Self::Comment>) -> Self::Unit;
+}
+
+/// Builds a tree that borrows from the input.
+struct Borrowed;
+
+impl<'a> Tree<'a> for Borrowed {
+    type Argument = ConfArgument<'a>;
+    type Arguments = Vec<ConfArgument<'a>>;
+    type Directive = ConfDirective<'a>;
+    type Children = Vec<ConfDirective<'a>>;
+    type Comment = ConfComment<'a>;
+    type Unit = ConfUnit<'a>;
+
+    fn argument(&mut self, argument: ConfArgument<'a>) -> Self::Argument {
+        argument
+    }
+
+    fn push_argument(&mut self, arguments: &mut Self::Arguments, argument: Self::Argument) {
+        arguments.push(argument);
+    }
+
+    fn directive(
+        &mut self,
+        name: Self::Argument,
+        arguments: Self::Arguments,
+        children: Self::Children,
+    ) -> Self::Directive {
+        ConfDirective {
+            name,
+            arguments,
+            children,
+        }
+    }
+
+    fn push_child(&mut self, children: &mut Self::Children, child: Self::Directive) {
+        children.push(child);
+    }
+
+    fn comment(&mut self, comment: ConfComment<'a>) -> Self::Comment {
+        comment
+    }
+
+    fn unit(&mut self, directives: Self::Children, comments: Vec<Self::Comment>) -> Self::Unit {
+        ConfUnit {
+            directives,
+            comments,
+        }
+    }
+}
+
+/// Builds an owned tree.
+struct Owned;
+
+impl<'a> Tree<'a> for Owned {
+    type Argument = crate::ConfArgument;
+    type Arguments = Vec<crate::ConfArgument>;
+    type Directive = crate::ConfDirective;
+    type Children = Vec<crate::ConfDirective>;
+    type Comment = crate::ConfComment;
+    type Unit = crate::ConfUnit;
+
+    fn argument(&mut self, argument: ConfArgument<'a>) -> Self::Argument {
+        argument.into_owned()
+    }
+
+    fn push_argument(&mut self, arguments: &mut Self::Arguments, argument: Self::Argument) {
+        arguments.push(argument);
+    }
+
+    fn directive(
+        &mut self,
+        name: Self::Argument,
+        arguments: Self::Arguments,
+        children: Self::Children,
+    ) -> Self::Directive {
+        crate::ConfDirective {
+            name,
+            arguments,
+            children,
+        }
+    }
+
+    fn push_child(&mut self, children: &mut Self::Children, child: Self::Directive) {
+        children.push(child);
+    }
+
+    fn comment(&mut self, comment: ConfComment<'a>) -> Self::Comment {
+        comment.into_owned()
+    }
+
+    fn unit(&mut self, directives: Self::Children, comments: Vec<Self::Comment>) -> Self::Unit {
+        crate::ConfUnit {
+            directives,
+            comments,
+        }
+    }
 }
 
 impl<'a> Parser<'a> {
     /// Creates a new parser.
+    ///
+    /// A leading pragma comment in the input adjusts the options as allowed
+    /// by [`ConfOptions::pragma_policy`].
     pub fn new(input: &'a str, options: ConfOptions) -> Result<Self, ConfError> {
+        let options = crate::pragma::leading(input, options)?;
+        let mut lexer = Lexer::new(input, options.clone());
+        let current_token = lexer.next_token()?;
+
+        Ok(Self {
+            lexer,
+            current_token,
+            options,
+            directives: 0,
+            depth: 0,
+            file: FileId::default(),
+        })
+    }
+
+    /// Creates a parser for the body of a block at the given depth, parsed
+    /// as the directives between its braces would be. The input runs from
+    /// after the opening brace up to and including the closing brace, which
+    /// must end it. No pragma is applied.
+    pub(crate) fn for_block(
+        input: &'a str,
+        options: ConfOptions,
+        depth: usize,
+    ) -> Result<Self, ConfError> {
         let mut lexer = Lexer::new(input, options.clone());
         let current_token = lexer.next_token()?;
 
@@ -23,24 +192,40 @@ impl<'a> Parser<'a> {
             lexer,
             current_token,
             options,
-            current_depth: 0,
+            directives: 0,
+            depth,
+            file: FileId::default(),
         })
     }
 
+    /// Sets the file the spans of the parsed unit and of errors belong to.
+    pub fn in_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Returns the span of the current token.
+    fn token_span(&self) -> Span {
+        Span::new(self.file, self.current_token.span.clone())
+    }
+
     /// Advances to the next token.
     fn advance(&mut self) -> Result<(), ConfError> {
-        self.current_token = self.lexer.next_token()?;
+        self.current_token = self
+            .lexer
+            .next_token()
+            .map_err(|error| error.in_file(self.file))?;
         Ok(())
     }
 
     /// Safely extracts text from a span, returning an error if the span is invalid.
-    fn get_text_from_span(&self, span: &std::ops::Range<usize>) -> Result<&str, ConfError> {
+    fn get_text_from_span(&self, span: &std::ops::Range<usize>) -> Result<&'a str, ConfError> {
         let input = self.lexer.input();
         if span.start <= span.end && span.end <= input.len() {
             Ok(&input[span.clone()])
         } else {
             Err(ConfError::ParserError {
-                position: span.start,
+                span: Span::new(self.file, span.clone()),
                 message: format!(
                     "Invalid span {}..{} for input of length {}",
                     span.start,
@@ -52,60 +237,127 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses a configuration unit.
-    pub fn parse(&mut self) -> Result<ConfUnit, ConfError> {
-        let mut directives = Vec::new();
+    pub fn parse(&mut self) -> Result<crate::ConfUnit, ConfError> {
+        self.parse_tree(&mut Owned)
+    }
+
+    /// Parses a configuration unit whose names, arguments and comments borrow
+    /// from the input.
+    pub fn parse_borrowed(&mut self) -> Result<ConfUnit<'a>, ConfError> {
+        self.parse_tree(&mut Borrowed)
+    }
+
+    /// Parses a configuration unit into a tree of the given kind.
+    pub(crate) fn parse_tree<T: Tree<'a>>(&mut self, tree: &mut T) -> Result<T::Unit, ConfError> {
+        let mut directives = T::Children::default();
         let mut comments = Vec::new();
+        let mut blocks: Vec<OpenBlock<'a, T>> = Vec::new();
 
-        while self.current_token.token_type != TokenType::Eof {
+        loop {
             match self.current_token.token_type {
+                TokenType::Eof => {
+                    if !blocks.is_empty() || self.depth > 0 {
+                        return Err(ConfError::ParserError {
+                            span: self.token_span(),
+                            message: "Expected '}'".to_string(),
+                        });
+                    }
+                    break;
+                }
                 TokenType::Comment => {
                     let comment = self.parse_comment()?;
-                    comments.push(comment);
+                    // Comments inside blocks are not kept
+                    if blocks.is_empty() && self.depth == 0 {
+                        comments.push(tree.comment(comment));
+                    }
                 }
-                TokenType::Newline | TokenType::Whitespace | TokenType::Continuation => {
+                TokenType::Newline | TokenType::Whitespace => {
                     self.advance()?;
                 }
+                TokenType::Continuation if blocks.is_empty() && self.depth == 0 => {
+                    self.advance()?;
+                }
+                TokenType::RightCurlyBrace if blocks.is_empty() && self.depth > 0 => {
+                    self.advance()?; // Skip the closing '}' of the body
+                    if self.current_token.token_type != TokenType::Eof {
+                        return Err(ConfError::ParserError {
+                            span: self.token_span(),
+                            message: "Expected the end of the block".to_string(),
+                        });
+                    }
+                    break;
+                }
+                TokenType::RightCurlyBrace if !blocks.is_empty() => {
+                    self.advance()?; // Skip '}'
+                    let block = blocks.pop().expect("the match guard checks for a block");
+                    let directive = tree.directive(block.name, block.arguments, block.children);
+                    match blocks.last_mut() {
+                        Some(parent) => tree.push_child(&mut parent.children, directive),
+                        None => tree.push_child(&mut directives, directive),
+                    }
+                }
                 _ => {
-                    let directive = self.parse_directive()?;
-                    directives.push(directive);
+                    let (name, arguments) =
+                        self.parse_directive_head(tree, self.depth + blocks.len())?;
+                    if self.current_token.token_type == TokenType::LeftCurlyBrace {
+                        self.advance()?; // Skip '{'
+                        blocks.push(OpenBlock {
+                            name,
+                            arguments,
+                            children: T::Children::default(),
+                        });
+                        continue;
+                    }
+
+                    self.parse_terminator()?;
+                    let directive = tree.directive(name, arguments, T::Children::default());
+                    match blocks.last_mut() {
+                        Some(parent) => tree.push_child(&mut parent.children, directive),
+                        None => tree.push_child(&mut directives, directive),
+                    }
                 }
             }
         }
 
-        Ok(ConfUnit {
-            directives,
-            comments,
-        })
+        Ok(tree.unit(directives, comments))
     }
 
     /// Parses a comment.
-    fn parse_comment(&mut self) -> Result<ConfComment, ConfError> {
+    fn parse_comment(&mut self) -> Result<ConfComment<'a>, ConfError> {
         if self.current_token.token_type != TokenType::Comment {
             return Err(ConfError::ParserError {
-                position: self.current_token.span.start,
+                span: self.token_span(),
                 message: "Expected comment".to_string(),
             });
         }
 
         let span = self.current_token.span.clone();
-        let content = self.get_text_from_span(&span)?.to_string();
-        let is_multi_line = content.starts_with("/*");
+        let content = self.get_text_from_span(&span)?;
+        let is_multi_line = matches!(
+            self.current_token.comment_style,
+            Some(CommentStyle::Block(_))
+        );
 
         self.advance()?;
 
         Ok(ConfComment {
-            content,
-            span,
+            content: Cow::Borrowed(content),
+            span: Span::new(self.file, span),
             is_multi_line,
         })
     }
 
-    /// Parses a directive.
-    fn parse_directive(&mut self) -> Result<ConfDirective, ConfError> {
+    /// Parses the name and arguments of a directive at the given depth,
+    /// stopping at the token that ends them.
+    fn parse_directive_head<T: Tree<'a>>(
+        &mut self,
+        tree: &mut T,
+        depth: usize,
+    ) -> Result<(T::Argument, T::Arguments), ConfError> {
         // Check max depth
-        if self.current_depth >= self.options.max_depth {
+        if depth >= self.options.max_depth {
             return Err(ConfError::ParserError {
-                position: self.current_token.span.start,
+                span: self.token_span(),
                 message: format!(
                     "Maximum directive depth of {} exceeded",
                     self.options.max_depth
@@ -116,25 +368,38 @@ impl<'a> Parser<'a> {
         // Parse the directive name
         if self.current_token.token_type != TokenType::Argument {
             return Err(ConfError::ParserError {
-                position: self.current_token.span.start,
+                span: self.token_span(),
                 message: "Expected directive name".to_string(),
             });
         }
 
+        self.directives += 1;
+        if self.directives > self.options.max_directives {
+            return Err(limit_exceeded(
+                self.current_token.span.start,
+                Limit::Directives,
+                self.options.max_directives,
+            )
+            .in_file(self.file));
+        }
+
         let name_span = self.current_token.span.clone();
-        let name_value = self.get_text_from_span(&name_span)?.to_string();
-        let name = ConfArgument {
-            value: name_value,
-            span: name_span,
+        let name_value = self.get_text_from_span(&name_span)?;
+        let name = tree.argument(ConfArgument {
+            value: Cow::Borrowed(name_value),
+            span: Span::new(self.file, name_span),
             is_quoted: self.current_token.is_quoted,
             is_triple_quoted: self.current_token.is_triple_quoted,
+            is_raw: self.current_token.is_raw,
+            is_heredoc: self.current_token.is_heredoc,
             is_expression: self.current_token.is_expression,
-        };
+        });
 
         self.advance()?;
 
         // Parse arguments
-        let mut arguments = Vec::new();
+        let mut arguments = T::Arguments::default();
+        let mut count = 0;
         while self.current_token.token_type == TokenType::Argument
             || self.current_token.token_type == TokenType::Continuation
         {
@@ -145,76 +410,52 @@ impl<'a> Parser<'a> {
             }
 
             let arg_span = self.current_token.span.clone();
-            let arg_value = self.get_text_from_span(&arg_span)?.to_string();
-            let argument = ConfArgument {
-                value: arg_value,
-                span: arg_span,
+            if count >= self.options.max_arguments {
+                return Err(limit_exceeded(
+                    arg_span.start,
+                    Limit::Arguments,
+                    self.options.max_arguments,
+                )
+                .in_file(self.file));
+            }
+            let arg_value = self.get_text_from_span(&arg_span)?;
+            let argument = tree.argument(ConfArgument {
+                value: Cow::Borrowed(arg_value),
+                span: Span::new(self.file, arg_span),
                 is_quoted: self.current_token.is_quoted,
                 is_triple_quoted: self.current_token.is_triple_quoted,
+                is_raw: self.current_token.is_raw,
+                is_heredoc: self.current_token.is_heredoc,
                 is_expression: self.current_token.is_expression,
-            };
+            });
 
-            arguments.push(argument);
+            tree.push_argument(&mut arguments, argument);
+            count += 1;
             self.advance()?;
         }
 
-        // Parse child directives if this is a block directive
-        let mut children = Vec::new();
-        if self.current_token.token_type == TokenType::LeftCurlyBrace {
-            self.advance()?; // Skip '{'
-            self.current_depth += 1;
-
-            // Skip newlines after opening brace
-            while self.current_token.token_type == TokenType::Newline {
-                self.advance()?;
-            }
-
-            // Parse child directives
-            while self.current_token.token_type != TokenType::RightCurlyBrace
-                && self.current_token.token_type != TokenType::Eof
-            {
-                match self.current_token.token_type {
-                    TokenType::Comment => {
-                        let _comment = self.parse_comment()?;
-                        // We don't add comments to children, they go to the ConfUnit
-                    }
-                    TokenType::Newline | TokenType::Whitespace => {
-                        self.advance()?;
-                    }
-                    _ => {
-                        let directive = self.parse_directive()?;
-                        children.push(directive);
-                    }
-                }
-            }
-
-            // Expect closing brace
-            if self.current_token.token_type != TokenType::RightCurlyBrace {
-                return Err(ConfError::ParserError {
-                    position: self.current_token.span.start,
-                    message: "Expected '}'".to_string(),
-                });
-            }
+        Ok((name, arguments))
+    }
 
-            self.advance()?; // Skip '}'
-            self.current_depth -= 1;
-        } else if self.current_token.token_type == TokenType::Semicolon {
+    /// Parses the end of a directive without a block.
+    fn parse_terminator(&mut self) -> Result<(), ConfError> {
+        if self.current_token.token_type == TokenType::Semicolon {
             self.advance()?; // Skip ';'
+        } else if self.options.require_semicolons {
+            return Err(ConfError::ParserError {
+                span: self.token_span(),
+                message: "Expected ';' or '{' (require_semicolons is enabled)".to_string(),
+            });
         } else if self.current_token.token_type != TokenType::Newline
             && self.current_token.token_type != TokenType::Eof
             && self.current_token.token_type != TokenType::Continuation
         {
             return Err(ConfError::ParserError {
-                position: self.current_token.span.start,
+                span: self.token_span(),
                 message: "Expected ';', '{', or newline".to_string(),
             });
         }
-
-        Ok(ConfDirective {
-            name,
-            arguments,
-            children,
-        })
+        Ok(())
     }
 }
 
@@ -305,4 +546,43 @@ mod tests {
             panic!("Expected ParserError");
         }
     }
+
+    #[test]
+    fn test_parser_deep_nesting_on_small_stack() {
+        const DEPTH: usize = 100_000;
+
+        let handle = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let input = "a {".repeat(DEPTH) + &"}".repeat(DEPTH);
+                let options = ConfOptions {
+                    max_depth: DEPTH,
+                    ..Default::default()
+                };
+
+                let unit = crate::parse(&input, options.clone()).unwrap();
+                let mut depth = 1;
+                let mut directive = &unit.directives[0];
+                while let Some(child) = directive.children.first() {
+                    directive = child;
+                    depth += 1;
+                }
+                assert_eq!(depth, DEPTH);
+                let copy = unit.clone();
+                assert!(copy.directives == unit.directives);
+                drop(copy);
+                drop(unit);
+                let borrowed = crate::parse_borrowed(&input, options.clone()).unwrap();
+                drop(borrowed.clone());
+                drop(borrowed);
+
+                // One level too deep, and unclosed blocks
+                let deeper = "a {".repeat(DEPTH + 1) + &"}".repeat(DEPTH + 1);
+                assert!(crate::parse(&deeper, options.clone()).is_err());
+                let unclosed = "a {".repeat(DEPTH);
+                assert!(crate::parse_borrowed(&unclosed, options).is_err());
+            })
+            .unwrap();
+        handle.join().unwrap();
+    }
 }
@@ -0,0 +1,202 @@
+//! Leading pragma comments that adjust the parser options.
+//!
+//! A file may start with a comment such as
+//! `# confetti: c-comments, expressions, require-semicolons` naming the
+//! options it needs. Each name enables an option, and `no-` before a name
+//! disables it. [`ConfOptions::pragma_policy`] decides whether a pragma may
+//! relax the caller's options or only tighten them.
+
+use crate::lexer::{CommentStyle, Lexer, TokenType};
+use crate::{ConfError, ConfOptions, PragmaPolicy, Span};
+
+/// The text that marks a comment as a pragma.
+const MARKER: &str = "confetti:";
+
+/// Returns the options to parse `input` with, after applying its leading
+/// pragma, if any.
+pub(crate) fn leading(input: &str, options: ConfOptions) -> Result<ConfOptions, ConfError> {
+    if options.pragma_policy == PragmaPolicy::Ignore {
+        return Ok(options);
+    }
+
+    // Errors in the first token are left for the parser to report
+    let mut lexer = Lexer::new(input, options.clone());
+    match lexer.next_token() {
+        Ok(token) if token.token_type == TokenType::Comment => match token.comment_style {
+            Some(style) => apply(
+                &input[token.span.clone()],
+                style,
+                token.span.start,
+                &options,
+            ),
+            None => Ok(options),
+        },
+        _ => Ok(options),
+    }
+}
+
+/// Applies the pragma in a leading comment, returning the options unchanged
+/// when the comment is not a pragma.
+///
+/// `comment` is the text of the comment, delimiters included, and `position`
+/// its offset in the input.
+pub(crate) fn apply(
+    comment: &str,
+    style: CommentStyle,
+    position: usize,
+    options: &ConfOptions,
+) -> Result<ConfOptions, ConfError> {
+    let mut result = options.clone();
+    if options.pragma_policy == PragmaPolicy::Ignore {
+        return Ok(result);
+    }
+    let Some(names) = comment_body(comment, style, options)
+        .trim()
+        .strip_prefix(MARKER)
+    else {
+        return Ok(result);
+    };
+
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let (option, enable) = match name.strip_prefix("no-") {
+            Some(option) => (option, false),
+            None => (name, true),
+        };
+        let (field, enabling_relaxes) = match option {
+            "c-comments" => (&mut result.allow_c_style_comments, true),
+            "expressions" => (&mut result.allow_expression_arguments, true),
+            "triple-quotes" => (&mut result.allow_triple_quotes, true),
+            "line-continuations" => (&mut result.allow_line_continuations, true),
+            "raw-strings" => (&mut result.allow_raw_strings, true),
+            "heredocs" => (&mut result.allow_heredocs, true),
+            "require-semicolons" => (&mut result.require_semicolons, false),
+            _ => {
+                return Err(ConfError::ParserError {
+                    span: Span::point(position),
+                    message: format!("Unknown pragma '{}'", name),
+                })
+            }
+        };
+
+        let relaxes = *field != enable && enable == enabling_relaxes;
+        if relaxes && options.pragma_policy == PragmaPolicy::TightenOnly {
+            return Err(ConfError::ParserError {
+                span: Span::point(position),
+                message: format!(
+                    "Pragma '{}' would relax the parser options (pragma_policy is TightenOnly)",
+                    name
+                ),
+            });
+        }
+        *field = enable;
+    }
+    Ok(result)
+}
+
+/// Returns the text of a comment without its delimiters.
+fn comment_body<'a>(comment: &'a str, style: CommentStyle, options: &ConfOptions) -> &'a str {
+    let syntax = options.effective_comment_syntax();
+    match style {
+        CommentStyle::Line(index) => syntax
+            .line_prefixes
+            .get(index)
+            .and_then(|prefix| comment.strip_prefix(prefix.as_str()))
+            .unwrap_or(comment),
+        CommentStyle::Block(index) => syntax
+            .block_delimiters
+            .get(index)
+            .and_then(|(open, close)| {
+                comment
+                    .strip_prefix(open.as_str())?
+                    .strip_suffix(close.as_str())
+            })
+            .unwrap_or(comment),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn allow() -> ConfOptions {
+        ConfOptions {
+            pragma_policy: PragmaPolicy::Allow,
+            ..ConfOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_pragma_enables_extensions() {
+        let input = "# confetti: c-comments, raw-strings\n/* a; b */\npath r\"C:\\\";\n";
+        let unit = parse(input, allow()).unwrap();
+        assert_eq!(unit.directives.len(), 1);
+        assert!(unit.directives[0].arguments[0].is_raw);
+        assert_eq!(unit.comments.len(), 2);
+
+        // Without the pragma the same body is rejected
+        let body = &input[input.find('\n').unwrap() + 1..];
+        assert!(parse(body, allow()).is_err());
+
+        // By default pragmas are ordinary comments
+        assert!(parse(input, ConfOptions::default()).is_err());
+        let unit = parse("# confetti: colour\na;\n", ConfOptions::default()).unwrap();
+        assert_eq!(unit.comments.len(), 1);
+    }
+
+    #[test]
+    fn test_pragma_policies() {
+        let relaxing = "# confetti: raw-strings\npath r\"C:\\\";\n";
+        let tightening = "# confetti: require-semicolons, no-triple-quotes\na\n";
+
+        let tighten_only = ConfOptions {
+            pragma_policy: PragmaPolicy::TightenOnly,
+            ..ConfOptions::default()
+        };
+        match parse(relaxing, tighten_only.clone()) {
+            Err(ConfError::ParserError { span, message }) => {
+                assert_eq!(span.start(), 0);
+                assert!(message.contains("would relax"), "{}", message);
+            }
+            other => panic!("Expected a pragma error, got {:?}", other),
+        }
+        assert!(parse(tightening, tighten_only.clone()).is_err());
+        assert!(parse("# confetti: require-semicolons\na;\n", tighten_only).is_ok());
+
+        // Ignored pragmas are ordinary comments
+        let ignore = ConfOptions {
+            pragma_policy: PragmaPolicy::Ignore,
+            ..ConfOptions::default()
+        };
+        assert!(parse(relaxing, ignore.clone()).is_err());
+        assert!(parse(tightening, ignore).is_ok());
+    }
+
+    #[test]
+    fn test_pragma_placement_and_errors() {
+        // Only a comment before anything else is a pragma
+        let late = "\n# confetti: c-comments\n// note\n";
+        assert_eq!(parse(late, allow()).unwrap().directives.len(), 1);
+        assert!(parse("# confetti:\na\n", allow()).is_ok());
+        assert!(parse("# not a pragma: c-comments\n", allow()).is_ok());
+
+        match parse("#confetti: c-comments, colour\n", allow()) {
+            Err(ConfError::ParserError { message, .. }) => {
+                assert_eq!(message, "Unknown pragma 'colour'");
+            }
+            other => panic!("Expected a pragma error, got {:?}", other),
+        }
+
+        // Any comment delimiter in effect can hold the pragma
+        let options = ConfOptions {
+            comment_syntax: crate::CommentSyntax::lua(),
+            ..allow()
+        };
+        let unit = parse("--[[ confetti: heredocs ]]\nx <<E\n E\n", options).unwrap();
+        assert!(unit.directives[0].arguments[0].is_heredoc);
+    }
+}
@@ -0,0 +1,673 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{ConfDirective, ConfUnit};
+
+/// Error type for query parsing
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryError {
+    /// The position in the query string where the error occurred.
+    pub position: usize,
+    /// A description of the error.
+    pub message: String,
+}
+
+impl Error for QueryError {}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Query error at position {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+/// Matches directive names in a query step.
+#[derive(Debug, Clone, PartialEq)]
+enum NameTest {
+    /// Matches directives with the given name.
+    Name(String),
+    /// Matches any directive (`*`).
+    Any,
+    /// Matches the current directive and all of its descendants (`**`).
+    Descendants,
+}
+
+/// Selects which value a predicate compares against.
+#[derive(Debug, Clone, PartialEq)]
+enum Key {
+    /// A child directive with the given name.
+    Child(String),
+    /// The argument at the given index (`@N`).
+    Argument(usize),
+}
+
+/// A filter applied to the directives matched by a query step.
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    /// Keeps the Nth match (`[N]`).
+    Index(usize),
+    /// Keeps matches where the key exists and optionally has the given value
+    /// (`[key]` or `[key=value]`).
+    Has(Key, Option<String>),
+}
+
+/// A single step of a query path.
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    test: NameTest,
+    predicates: Vec<Predicate>,
+}
+
+/// A compiled path query over the directive tree.
+///
+/// A query is a list of steps separated by `/`. Each step selects child
+/// directives of the directives matched by the previous step:
+///
+/// - `name` matches directives named `name`; names containing reserved
+///   characters can be written as quoted strings (`"my/name"`).
+/// - `*` matches any directive.
+/// - `**` matches the current directive and all of its descendants, so
+///   `**/proxy_pass` finds `proxy_pass` at any depth.
+///
+/// Each step can be followed by predicates in square brackets:
+///
+/// - `[N]` keeps the Nth (zero-based) match within each parent.
+/// - `[child]` keeps directives that have a child directive named `child`.
+/// - `[child=value]` keeps directives that have a child named `child` with an
+///   argument equal to `value`.
+/// - `[@N]` and `[@N=value]` test the Nth argument of the directive itself.
+///
+/// Names and values are compared against the text arguments stand for
+/// (see [`ConfArgument::text`](crate::ConfArgument::text)), so quoting and
+/// escapes in the file don't matter. Values may be quoted themselves.
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::{parse, ConfOptions};
+///
+/// let input = r#"
+/// server {
+///     listen 80;
+///     location / { proxy_pass http://a; }
+/// }
+/// server {
+///     listen 443;
+///     ssl_cert "/etc/cert.pem";
+/// }
+/// "#;
+/// let unit = parse(input, ConfOptions::default()).unwrap();
+///
+/// let certs = unit.select("server[listen=443]/ssl_cert").unwrap();
+/// assert_eq!(certs.len(), 1);
+/// assert_eq!(certs[0].arguments[0].value, "\"/etc/cert.pem\"");
+///
+/// let proxies = unit.select("**/proxy_pass").unwrap();
+/// assert_eq!(proxies[0].arguments[0].value, "http://a");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    steps: Vec<Step>,
+}
+
+impl Query {
+    /// Parses a query string.
+    pub fn parse(path: &str) -> Result<Self, QueryError> {
+        QueryParser::new(path).parse()
+    }
+
+    /// Returns the directives matched by this query, searching from the given
+    /// list of top-level directives. Results are in document order.
+    pub fn select<'a>(&self, directives: &'a [ConfDirective]) -> Vec<&'a ConfDirective> {
+        self.evaluate(directives)
+            .into_iter()
+            .map(|(_, directive)| directive)
+            .collect()
+    }
+
+    /// Returns mutable references to the directives matched by this query.
+    ///
+    /// A match nested inside another match cannot be borrowed mutably at the
+    /// same time as its ancestor, so only the outermost matches are returned.
+    pub fn select_mut<'a>(
+        &self,
+        directives: &'a mut [ConfDirective],
+    ) -> Vec<&'a mut ConfDirective> {
+        let mut paths: Vec<Vec<usize>> = self
+            .evaluate(directives)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        paths.sort();
+
+        // Drop matches that are nested inside an earlier match. Sorted paths
+        // put a match's descendants right after it, so only the last kept
+        // path can be an ancestor.
+        let mut outermost: Vec<Vec<usize>> = Vec::with_capacity(paths.len());
+        for path in paths {
+            if !outermost.last().is_some_and(|p| path.starts_with(p)) {
+                outermost.push(path);
+            }
+        }
+
+        let mut result = Vec::with_capacity(outermost.len());
+        let paths: Vec<&[usize]> = outermost.iter().map(|p| p.as_slice()).collect();
+        collect_mut(directives, &paths, &mut result);
+        result
+    }
+
+    /// Evaluates the query, returning the index path and reference of every
+    /// matched directive in document order.
+    fn evaluate<'a>(&self, roots: &'a [ConfDirective]) -> Vec<(Vec<usize>, &'a ConfDirective)> {
+        // `None` stands for the root context, whose children are `roots`
+        let mut contexts: Vec<(Vec<usize>, Option<&'a ConfDirective>)> = vec![(Vec::new(), None)];
+
+        for step in &self.steps {
+            let mut next: Vec<(Vec<usize>, Option<&'a ConfDirective>)> = Vec::new();
+
+            if step.test == NameTest::Descendants {
+                for (path, directive) in contexts {
+                    let children = directive.map_or(roots, |d| &d.children);
+                    let mut matched = vec![(path.clone(), directive)];
+                    push_descendants(children, &path, &mut matched);
+                    apply_predicates(&step.predicates, &mut matched);
+                    next.extend(matched);
+                }
+            } else {
+                for (path, directive) in contexts {
+                    let children = directive.map_or(roots, |d| &d.children);
+                    let mut matched: Vec<(Vec<usize>, Option<&'a ConfDirective>)> = children
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, child)| match &step.test {
+                            NameTest::Name(name) => child.name.has_text(name),
+                            _ => true,
+                        })
+                        .map(|(i, child)| {
+                            let mut child_path = path.clone();
+                            child_path.push(i);
+                            (child_path, Some(child))
+                        })
+                        .collect();
+                    apply_predicates(&step.predicates, &mut matched);
+                    next.extend(matched);
+                }
+            }
+
+            // `**` can reach the same directive through several contexts
+            next.sort_by(|a, b| a.0.cmp(&b.0));
+            next.dedup_by(|a, b| a.0 == b.0);
+            contexts = next;
+        }
+
+        contexts
+            .into_iter()
+            .filter_map(|(path, directive)| directive.map(|d| (path, d)))
+            .collect()
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Query::parse(s)
+    }
+}
+
+/// Appends every descendant of `directives` in document order.
+fn push_descendants<'a>(
+    directives: &'a [ConfDirective],
+    prefix: &[usize],
+    out: &mut Vec<(Vec<usize>, Option<&'a ConfDirective>)>,
+) {
+    for (i, directive) in directives.iter().enumerate() {
+        let mut path = prefix.to_vec();
+        path.push(i);
+        out.push((path.clone(), Some(directive)));
+        push_descendants(&directive.children, &path, out);
+    }
+}
+
+/// Filters the matches of a single context by the step predicates.
+fn apply_predicates(
+    predicates: &[Predicate],
+    matched: &mut Vec<(Vec<usize>, Option<&ConfDirective>)>,
+) {
+    for predicate in predicates {
+        match predicate {
+            Predicate::Index(index) => {
+                if *index < matched.len() {
+                    let kept = matched.swap_remove(*index);
+                    matched.clear();
+                    matched.push(kept);
+                } else {
+                    matched.clear();
+                }
+            }
+            Predicate::Has(key, value) => {
+                matched.retain(|(_, directive)| {
+                    directive.is_some_and(|d| matches_key(d, key, value.as_deref()))
+                });
+            }
+        }
+    }
+}
+
+/// Returns whether a directive satisfies a `[key]` or `[key=value]` predicate.
+fn matches_key(directive: &ConfDirective, key: &Key, value: Option<&str>) -> bool {
+    match key {
+        Key::Argument(index) => directive
+            .arguments
+            .get(*index)
+            .is_some_and(|arg| value.is_none_or(|v| arg.has_text(v))),
+        Key::Child(name) => directive
+            .children
+            .iter()
+            .filter(|child| child.name.has_text(name))
+            .any(|child| match value {
+                None => true,
+                Some(v) => child.arguments.iter().any(|arg| arg.has_text(v)),
+            }),
+    }
+}
+
+/// Resolves sorted, non-overlapping index paths into mutable references.
+fn collect_mut<'a>(
+    directives: &'a mut [ConfDirective],
+    mut paths: &[&[usize]],
+    out: &mut Vec<&'a mut ConfDirective>,
+) {
+    let mut directives = directives.iter_mut().enumerate();
+    while let Some(&first) = paths.first().and_then(|path| path.first()) {
+        // Paths through the same directive are adjacent once sorted
+        let count = paths.iter().take_while(|path| path[0] == first).count();
+        let (group, rest) = paths.split_at(count);
+        paths = rest;
+
+        let Some((_, directive)) = directives.find(|(i, _)| *i == first) else {
+            return;
+        };
+        if group[0].len() == 1 {
+            out.push(directive);
+        } else {
+            let below: Vec<&[usize]> = group.iter().map(|path| &path[1..]).collect();
+            collect_mut(&mut directive.children, &below, out);
+        }
+    }
+}
+
+/// Parser for the query syntax.
+struct QueryParser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    fn error(&self, message: impl Into<String>) -> QueryError {
+        QueryError {
+            position: self.position,
+            message: message.into(),
+        }
+    }
+
+    fn current_char(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn advance(&mut self) {
+        if let Some(c) = self.current_char() {
+            self.position += c.len_utf8();
+        }
+    }
+
+    fn parse(mut self) -> Result<Query, QueryError> {
+        let mut steps = Vec::new();
+
+        // A leading '/' is allowed and has no effect
+        if self.current_char() == Some('/') {
+            self.advance();
+        }
+
+        loop {
+            steps.push(self.parse_step()?);
+            match self.current_char() {
+                Some('/') => self.advance(),
+                None => break,
+                Some(c) => return Err(self.error(format!("Unexpected character '{}'", c))),
+            }
+        }
+
+        Ok(Query { steps })
+    }
+
+    fn parse_step(&mut self) -> Result<Step, QueryError> {
+        let test = if self.input[self.position..].starts_with("**") {
+            self.advance();
+            self.advance();
+            NameTest::Descendants
+        } else if self.current_char() == Some('*') {
+            self.advance();
+            NameTest::Any
+        } else {
+            // A quoted name may be empty
+            let quoted = self.current_char() == Some('"');
+            let name = self.parse_word(&['/', '['])?;
+            if name.is_empty() && !quoted {
+                return Err(self.error("Expected directive name"));
+            }
+            NameTest::Name(name)
+        };
+
+        let mut predicates = Vec::new();
+        while self.current_char() == Some('[') {
+            self.advance();
+            predicates.push(self.parse_predicate()?);
+            if self.current_char() != Some(']') {
+                return Err(self.error("Expected ']'"));
+            }
+            self.advance();
+        }
+
+        Ok(Step { test, predicates })
+    }
+
+    fn parse_predicate(&mut self) -> Result<Predicate, QueryError> {
+        let start = self.position;
+        let key = self.parse_word(&['=', ']'])?;
+        if key.is_empty() {
+            return Err(self.error("Expected predicate"));
+        }
+
+        if self.current_char() != Some('=') {
+            if key.bytes().all(|b| b.is_ascii_digit()) {
+                let index = key.parse().map_err(|_| QueryError {
+                    position: start,
+                    message: format!("Invalid index '{}'", key),
+                })?;
+                return Ok(Predicate::Index(index));
+            }
+            return Ok(Predicate::Has(self.parse_key(&key, start)?, None));
+        }
+
+        self.advance(); // Skip '='
+        let value = self.parse_word(&[']'])?;
+        Ok(Predicate::Has(self.parse_key(&key, start)?, Some(value)))
+    }
+
+    fn parse_key(&self, key: &str, start: usize) -> Result<Key, QueryError> {
+        match key.strip_prefix('@') {
+            Some(index) => index.parse().map(Key::Argument).map_err(|_| QueryError {
+                position: start,
+                message: format!("Invalid argument index '{}'", index),
+            }),
+            None => Ok(Key::Child(key.to_string())),
+        }
+    }
+
+    /// Parses a bare or double-quoted word, stopping at any of `terminators`.
+    fn parse_word(&mut self, terminators: &[char]) -> Result<String, QueryError> {
+        let mut word = String::new();
+
+        if self.current_char() == Some('"') {
+            let start = self.position;
+            self.advance();
+            loop {
+                match self.current_char() {
+                    Some('"') => {
+                        self.advance();
+                        return Ok(word);
+                    }
+                    Some('\\') => {
+                        self.advance();
+                        match self.current_char() {
+                            Some(c) => {
+                                word.push(c);
+                                self.advance();
+                            }
+                            None => break,
+                        }
+                    }
+                    Some(c) => {
+                        word.push(c);
+                        self.advance();
+                    }
+                    None => break,
+                }
+            }
+            return Err(QueryError {
+                position: start,
+                message: "Unterminated quoted string".to_string(),
+            });
+        }
+
+        while let Some(c) = self.current_char() {
+            if terminators.contains(&c) || c == ']' {
+                break;
+            }
+            word.push(c);
+            self.advance();
+        }
+        Ok(word)
+    }
+}
+
+impl ConfUnit {
+    /// Returns the directives matching a path query.
+    ///
+    /// See [`Query`] for the path syntax.
+    pub fn select(&self, path: &str) -> Result<Vec<&ConfDirective>, QueryError> {
+        Ok(Query::parse(path)?.select(&self.directives))
+    }
+
+    /// Returns mutable references to the directives matching a path query.
+    ///
+    /// See [`Query::select_mut`] for how nested matches are handled.
+    pub fn select_mut(&mut self, path: &str) -> Result<Vec<&mut ConfDirective>, QueryError> {
+        Ok(Query::parse(path)?.select_mut(&mut self.directives))
+    }
+
+    /// Returns the first directive matching a path query, if any.
+    pub fn select_first(&self, path: &str) -> Result<Option<&ConfDirective>, QueryError> {
+        Ok(self.select(path)?.into_iter().next())
+    }
+}
+
+impl ConfDirective {
+    /// Returns the descendants of this directive matching a path query.
+    ///
+    /// The path is evaluated relative to this directive's children.
+    pub fn select(&self, path: &str) -> Result<Vec<&ConfDirective>, QueryError> {
+        Ok(Query::parse(path)?.select(&self.children))
+    }
+
+    /// Returns mutable references to the descendants matching a path query.
+    pub fn select_mut(&mut self, path: &str) -> Result<Vec<&mut ConfDirective>, QueryError> {
+        Ok(Query::parse(path)?.select_mut(&mut self.children))
+    }
+
+    /// Returns the first descendant matching a path query, if any.
+    pub fn select_first(&self, path: &str) -> Result<Option<&ConfDirective>, QueryError> {
+        Ok(self.select(path)?.into_iter().next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, ConfOptions};
+
+    const INPUT: &str = r#"
+server {
+    listen 80;
+    location / {
+        proxy_pass http://backend;
+    }
+    location /api {
+        proxy_pass http://api;
+    }
+}
+server {
+    listen 443 ssl;
+    ssl_cert "/etc/cert.pem";
+    location / {
+        root /var/www;
+    }
+}
+"#;
+
+    fn unit() -> ConfUnit {
+        parse(INPUT, ConfOptions::default()).unwrap()
+    }
+
+    fn first_args(directives: &[&ConfDirective]) -> Vec<String> {
+        directives
+            .iter()
+            .map(|d| d.arguments[0].value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_select_simple_path() {
+        let unit = unit();
+        let listens = unit.select("server/listen").unwrap();
+        assert_eq!(first_args(&listens), vec!["80", "443"]);
+    }
+
+    #[test]
+    fn test_select_index() {
+        let unit = unit();
+        let proxies = unit.select("server/location[1]/proxy_pass").unwrap();
+        assert_eq!(first_args(&proxies), vec!["http://api"]);
+
+        let second = unit.select("server[1]/listen").unwrap();
+        assert_eq!(first_args(&second), vec!["443"]);
+
+        assert!(unit.select("server[5]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_select_child_predicate() {
+        let unit = unit();
+        let certs = unit.select("server[listen=443]/ssl_cert").unwrap();
+        assert_eq!(first_args(&certs), vec!["\"/etc/cert.pem\""]);
+
+        let with_cert = unit.select("server[ssl_cert]").unwrap();
+        assert_eq!(with_cert.len(), 1);
+
+        // Any argument of the child can match
+        assert_eq!(unit.select("server[listen=ssl]").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_select_argument_predicate() {
+        let unit = unit();
+        let api = unit.select("server/location[@0=/api]/proxy_pass").unwrap();
+        assert_eq!(first_args(&api), vec!["http://api"]);
+
+        // Quotes are ignored when comparing values
+        let quoted = unit.select("server/ssl_cert[@0=/etc/cert.pem]").unwrap();
+        assert_eq!(quoted.len(), 1);
+    }
+
+    #[test]
+    fn test_select_wildcard() {
+        let unit = unit();
+        let all = unit.select("server/*").unwrap();
+        assert_eq!(all.len(), 6);
+        assert_eq!(all[0].name.value, "listen");
+        assert_eq!(all[3].name.value, "listen");
+    }
+
+    #[test]
+    fn test_select_recursive_descent() {
+        let unit = unit();
+        let proxies = unit.select("**/proxy_pass").unwrap();
+        assert_eq!(first_args(&proxies), vec!["http://backend", "http://api"]);
+
+        let roots = unit.select("**/root").unwrap();
+        assert_eq!(first_args(&roots), vec!["/var/www"]);
+
+        // Results are not duplicated when several contexts reach the same node
+        let locations = unit.select("**/**/location").unwrap();
+        assert_eq!(locations.len(), 3);
+    }
+
+    #[test]
+    fn test_select_relative_to_directive() {
+        let unit = unit();
+        let server = unit.select_first("server[1]").unwrap().unwrap();
+        let roots = server.select("location/root").unwrap();
+        assert_eq!(first_args(&roots), vec!["/var/www"]);
+    }
+
+    #[test]
+    fn test_select_mut() {
+        let mut unit = unit();
+        for listen in unit.select_mut("server/listen").unwrap() {
+            listen.arguments[0].value = "8080".to_string();
+        }
+        let listens = unit.select("server/listen").unwrap();
+        assert_eq!(first_args(&listens), vec!["8080", "8080"]);
+    }
+
+    #[test]
+    fn test_select_mut_returns_outermost_matches() {
+        let mut unit = parse("a { a { a; } } b { a; }", ConfOptions::default()).unwrap();
+        let matches = unit.select_mut("**/a").unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].children.len(), 1);
+        assert!(matches[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_quoted_step_name() {
+        let unit = parse("\"my/name\" value;", ConfOptions::default()).unwrap();
+        assert_eq!(unit.select("\"my/name\"").unwrap().len(), 1);
+        assert!(unit.select("my").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_names_and_values_match_by_text() {
+        let input = "\"a\\\"b\" x;\nc\\ d \"\"\"one\ntwo\"\"\";\ne { f \"p q\"; }\n";
+        let unit = parse(input, ConfOptions::all_extensions()).unwrap();
+        assert_eq!(unit.select("\"a\\\"b\"").unwrap().len(), 1);
+        assert_eq!(unit.select("c d").unwrap().len(), 1);
+        assert_eq!(unit.select("c d[@0=\"one\ntwo\"]").unwrap().len(), 1);
+        assert_eq!(unit.select("e[f=p q]").unwrap().len(), 1);
+        assert!(unit.select("e[f=\"\\\"p q\\\"\"]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_select_mut_resolves_siblings_and_nested_matches() {
+        let mut unit = parse("a { b; c { b; } b; } b; a { b; }", ConfOptions::default()).unwrap();
+        for directive in unit.select_mut("**/b").unwrap() {
+            directive.arguments.push(crate::builder::argument("x"));
+        }
+        assert_eq!(unit.select("**/b[@0=x]").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert!(Query::parse("").is_err());
+        assert!(Query::parse("server/").is_err());
+        assert!(Query::parse("server[0").is_err());
+        assert!(Query::parse("server[@x=1]").is_err());
+        assert!(Query::parse("\"unterminated").is_err());
+
+        let err = Query::parse("server[]").unwrap_err();
+        assert_eq!(err.position, 7);
+        assert_eq!(
+            err.to_string(),
+            "Query error at position 7: Expected predicate"
+        );
+    }
+}
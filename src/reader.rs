@@ -0,0 +1,859 @@
+use std::io::BufRead;
+use std::ops::Range;
+
+use crate::encoding::line_at;
+use crate::lexer::{limit_exceeded, CommentStyle, Lexer, Token, TokenType};
+use crate::pragma;
+use crate::source_map::MAX_OFFSET;
+use crate::{ConfArgument, ConfComment, ConfError, ConfOptions, FileId, Limit, Span};
+
+/// Once this many bytes of the window have been consumed, they are discarded.
+const COMPACT_THRESHOLD: usize = 8 * 1024;
+
+/// An event produced by the streaming [`Reader`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The name of a new directive.
+    StartDirective(ConfArgument),
+    /// An argument of the current directive.
+    Argument(ConfArgument),
+    /// The `{` opening the block of the current directive.
+    StartBlock(Span),
+    /// The `}` closing the innermost open block.
+    EndBlock(Span),
+    /// A comment.
+    Comment(ConfComment),
+    /// The end of the input.
+    Eof,
+}
+
+/// A pull-based reader producing [`Event`]s without building a tree.
+///
+/// The reader works over any [`BufRead`] source and decodes UTF-8
+/// incrementally, so memory use is bounded by the longest token rather than
+/// the input size. Spans are byte offsets from the start of the input, in the
+/// file set with [`in_file`](Reader::in_file). It enforces the same syntax rules and limits as [`parse`](crate::parse).
+///
+/// # Examples
+///
+/// ```
+/// use confetti_rs::reader::{Event, Reader};
+/// use confetti_rs::ConfOptions;
+///
+/// let input = "server {\n  listen 80;\n}\n";
+/// let mut names = Vec::new();
+/// for event in Reader::from_str(input, ConfOptions::default()) {
+///     if let Event::StartDirective(name) = event.unwrap() {
+///         names.push(name.value);
+///     }
+/// }
+/// assert_eq!(names, vec!["server", "listen"]);
+/// ```
+///
+/// Reading a file in bounded memory:
+///
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use confetti_rs::reader::Reader;
+/// use confetti_rs::ConfOptions;
+///
+/// let file = BufReader::new(File::open("huge.conf").unwrap());
+/// let mut reader = Reader::new(file, ConfOptions::default());
+/// let mut count = 0;
+/// for event in &mut reader {
+///     event.unwrap();
+///     count += 1;
+/// }
+/// ```
+pub struct Reader<R> {
+    /// The underlying byte source.
+    source: R,
+    /// The options for lexing and parsing.
+    options: ConfOptions,
+    /// Decoded text that has not been discarded yet.
+    window: String,
+    /// Bytes read from the source that do not form a complete character yet.
+    pending: Vec<u8>,
+    /// The absolute offset of the start of `window`.
+    window_offset: usize,
+    /// The number of line breaks in the text before `window`.
+    discarded_lines: usize,
+    /// The position in `window` of the next token.
+    position: usize,
+    /// Whether the source is exhausted.
+    source_done: bool,
+    /// Whether a directive is open and can take more arguments.
+    in_directive: bool,
+    /// The number of open blocks.
+    depth: usize,
+    /// Whether `Eof` has been returned.
+    finished: bool,
+    /// The number of tokens read so far, excluding `Eof`.
+    tokens: usize,
+    /// The number of directives started so far.
+    directives: usize,
+    /// The number of arguments of the current directive.
+    arguments: usize,
+    /// The file the spans of events and errors belong to.
+    file: FileId,
+}
+
+impl<'a> Reader<&'a [u8]> {
+    /// Creates a reader over a string.
+    pub fn from_str(input: &'a str, options: ConfOptions) -> Self {
+        Reader::new(input.as_bytes(), options)
+    }
+}
+
+impl<R: BufRead> Reader<R> {
+    /// Creates a reader over a buffered byte source.
+    pub fn new(source: R, options: ConfOptions) -> Self {
+        Self {
+            source,
+            options,
+            window: String::new(),
+            pending: Vec::new(),
+            window_offset: 0,
+            discarded_lines: 0,
+            position: 0,
+            source_done: false,
+            in_directive: false,
+            depth: 0,
+            finished: false,
+            tokens: 0,
+            directives: 0,
+            arguments: 0,
+            file: FileId::default(),
+        }
+    }
+
+    /// Sets the file the spans of events and errors belong to.
+    pub fn in_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Returns the number of blocks currently open.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Consumes the reader, returning the underlying source.
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Reads the next event.
+    ///
+    /// After [`Event::Eof`] has been returned, further calls keep returning it.
+    pub fn read_event(&mut self) -> Result<Event, ConfError> {
+        self.next_event().map_err(|error| error.in_file(self.file))
+    }
+
+    /// Reads the next event, reporting errors in the default file.
+    fn next_event(&mut self) -> Result<Event, ConfError> {
+        loop {
+            if self.finished {
+                return Ok(Event::Eof);
+            }
+
+            let token = self.next_token()?;
+            let position = token.span.start;
+            match token.token_type {
+                TokenType::Whitespace | TokenType::Continuation => {}
+                TokenType::Newline => {
+                    if self.in_directive && self.options.require_semicolons {
+                        return Err(missing_semicolon(position));
+                    }
+                    self.in_directive = false;
+                }
+                TokenType::Comment => {
+                    if self.in_directive {
+                        return Err(expected_terminator(position));
+                    }
+                    let content = self.text(&token.span).to_string();
+                    // The first token may be a pragma comment
+                    if let (1, Some(style)) = (self.tokens, token.comment_style) {
+                        self.options = pragma::apply(&content, style, position, &self.options)?;
+                    }
+                    let is_multi_line = matches!(token.comment_style, Some(CommentStyle::Block(_)));
+                    return Ok(Event::Comment(ConfComment {
+                        content,
+                        span: Span::new(self.file, token.span),
+                        is_multi_line,
+                    }));
+                }
+                TokenType::Semicolon => {
+                    if !self.in_directive {
+                        return Err(expected_name(position));
+                    }
+                    self.in_directive = false;
+                }
+                TokenType::Argument => {
+                    let argument = ConfArgument {
+                        value: self.text(&token.span).to_string(),
+                        span: Span::new(self.file, token.span.clone()),
+                        is_quoted: token.is_quoted,
+                        is_triple_quoted: token.is_triple_quoted,
+                        is_raw: token.is_raw,
+                        is_heredoc: token.is_heredoc,
+                        is_expression: token.is_expression,
+                    };
+                    if self.in_directive {
+                        self.arguments += 1;
+                        if self.arguments > self.options.max_arguments {
+                            return Err(limit_exceeded(
+                                position,
+                                Limit::Arguments,
+                                self.options.max_arguments,
+                            ));
+                        }
+                        return Ok(Event::Argument(argument));
+                    }
+                    if self.depth >= self.options.max_depth {
+                        return Err(ConfError::ParserError {
+                            span: Span::point(position),
+                            message: format!(
+                                "Maximum directive depth of {} exceeded",
+                                self.options.max_depth
+                            ),
+                        });
+                    }
+                    self.directives += 1;
+                    if self.directives > self.options.max_directives {
+                        return Err(limit_exceeded(
+                            position,
+                            Limit::Directives,
+                            self.options.max_directives,
+                        ));
+                    }
+                    self.in_directive = true;
+                    self.arguments = 0;
+                    return Ok(Event::StartDirective(argument));
+                }
+                TokenType::LeftCurlyBrace => {
+                    if !self.in_directive {
+                        return Err(expected_name(position));
+                    }
+                    self.in_directive = false;
+                    self.depth += 1;
+                    return Ok(Event::StartBlock(Span::new(self.file, token.span)));
+                }
+                TokenType::RightCurlyBrace => {
+                    if self.in_directive {
+                        return Err(expected_terminator(position));
+                    }
+                    if self.depth == 0 {
+                        return Err(expected_name(position));
+                    }
+                    self.depth -= 1;
+                    return Ok(Event::EndBlock(Span::new(self.file, token.span)));
+                }
+                TokenType::Eof => {
+                    if self.in_directive && self.options.require_semicolons {
+                        return Err(missing_semicolon(position));
+                    }
+                    if self.depth > 0 {
+                        return Err(ConfError::ParserError {
+                            span: Span::point(position),
+                            message: "Expected '}'".to_string(),
+                        });
+                    }
+                    self.finished = true;
+                    return Ok(Event::Eof);
+                }
+            }
+        }
+    }
+
+    /// Returns the text of an absolute span, which must lie in the window.
+    fn text(&self, span: &Range<usize>) -> &str {
+        &self.window[span.start - self.window_offset..span.end - self.window_offset]
+    }
+
+    /// Lexes the next token, reading more input until the token is known to
+    /// be complete. Token spans are absolute.
+    fn next_token(&mut self) -> Result<Token, ConfError> {
+        if self.position >= COMPACT_THRESHOLD {
+            // Keep a CR with a following LF, so CRLF counts as one line break
+            let mut discard = self.position;
+            if self.window.as_bytes()[discard - 1] == b'\r' {
+                discard -= 1;
+            }
+            self.discarded_lines += line_at(&self.window.as_bytes()[..discard]) - 1;
+            self.window.drain(..discard);
+            self.window_offset += discard;
+            self.position -= discard;
+        }
+
+        loop {
+            let rest = &self.window[self.position..];
+            let mut lexer = Lexer::borrowing(rest, &self.options);
+            let result = lexer.next_token();
+            let end = lexer.position();
+
+            // A token that reaches the end of the window may change once more
+            // input is available. So may an `r` followed by only `#` up to the
+            // end, which could open a raw string, and an error the lexer only
+            // found at the end, such as an unterminated string. An error before
+            // the end stands whatever follows
+            let complete = self.source_done
+                || match &result {
+                    Ok(token) => {
+                        token.token_type != TokenType::Eof
+                            && end < rest.len()
+                            && !(self.options.allow_raw_strings
+                                && &rest[token.span.clone()] == "r"
+                                && rest[end..].bytes().all(|byte| byte == b'#'))
+                    }
+                    Err(_) => end < rest.len(),
+                };
+            if !complete {
+                // The pending token only grows, so it is held to its length
+                // limit before it is complete
+                let (start, length, is_comment) = match &result {
+                    Ok(token) => (
+                        token.span.start,
+                        token.span.len(),
+                        token.token_type == TokenType::Comment,
+                    ),
+                    Err(_) => {
+                        let (start, is_comment) =
+                            Lexer::borrowing(rest, &self.options).peek_token_start();
+                        (start, rest.len() - start, is_comment)
+                    }
+                };
+                let (limit, max) = if is_comment {
+                    (Limit::CommentLength, self.options.max_comment_length)
+                } else {
+                    (Limit::ArgumentLength, self.options.max_argument_length)
+                };
+                if length > max {
+                    let position = self.window_offset + self.position + start;
+                    return Err(limit_exceeded(position, limit, max));
+                }
+
+                // Read until the pending text has doubled, so that a long
+                // token is lexed a logarithmic number of times, but not far
+                // past the limit
+                let pending = rest.len();
+                let target = (pending * 2)
+                    .min(start.saturating_add(max).saturating_add(1))
+                    .max(pending + 1);
+                self.fill_to(self.position + target)?;
+                continue;
+            }
+
+            let offset = self.window_offset + self.position;
+            let mut token = result.map_err(|error| error.shifted(offset))?;
+            self.position += end;
+            token.span = token.span.start + offset..token.span.end + offset;
+            if token.token_type != TokenType::Eof {
+                self.tokens += 1;
+                if self.tokens > self.options.max_tokens {
+                    return Err(limit_exceeded(
+                        token.span.start,
+                        Limit::Tokens,
+                        self.options.max_tokens,
+                    ));
+                }
+            }
+            return Ok(token);
+        }
+    }
+
+    /// Reads input until the window holds `length` bytes or the source is
+    /// exhausted.
+    fn fill_to(&mut self, length: usize) -> Result<(), ConfError> {
+        while !self.source_done && self.window.len() < length {
+            self.fill()?;
+        }
+        Ok(())
+    }
+
+    /// Reads and decodes the next chunk of input into the window.
+    fn fill(&mut self) -> Result<(), ConfError> {
+        let chunk = self.source.fill_buf()?;
+        if chunk.is_empty() {
+            self.source_done = true;
+            if !self.pending.is_empty() {
+                return Err(self.invalid_utf8());
+            }
+            return Ok(());
+        }
+        let length = chunk.len();
+        // Spans hold 32-bit offsets, which bounds the input whatever the options
+        let max = self.options.max_input_bytes.min(MAX_OFFSET);
+        if self.window_offset + self.window.len() + self.pending.len() + length > max {
+            return Err(limit_exceeded(max, Limit::InputBytes, max));
+        }
+        self.pending.extend_from_slice(chunk);
+        self.source.consume(length);
+
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => {
+                self.window.push_str(text);
+                self.pending.len()
+            }
+            Err(error) => {
+                let valid = error.valid_up_to();
+                let text = std::str::from_utf8(&self.pending[..valid]).unwrap_or_default();
+                self.window.push_str(text);
+                if error.error_len().is_some() {
+                    return Err(self.invalid_utf8());
+                }
+                // The chunk ends in the middle of a character
+                valid
+            }
+        };
+        self.pending.drain(..valid);
+        Ok(())
+    }
+
+    /// Returns the error for invalid UTF-8 just past the end of the window.
+    fn invalid_utf8(&self) -> ConfError {
+        ConfError::EncodingError {
+            span: Span::point(self.window_offset + self.window.len()),
+            line: self.discarded_lines + line_at(self.window.as_bytes()),
+            message: "Invalid UTF-8".to_string(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = Result<Event, ConfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.read_event() {
+            Ok(Event::Eof) => None,
+            Ok(event) => Some(Ok(event)),
+            Err(error) => {
+                // Stop after the first error
+                self.finished = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+fn expected_name(position: usize) -> ConfError {
+    ConfError::ParserError {
+        span: Span::point(position),
+        message: "Expected directive name".to_string(),
+    }
+}
+
+fn expected_terminator(position: usize) -> ConfError {
+    ConfError::ParserError {
+        span: Span::point(position),
+        message: "Expected ';', '{', or newline".to_string(),
+    }
+}
+
+fn missing_semicolon(position: usize) -> ConfError {
+    ConfError::ParserError {
+        span: Span::point(position),
+        message: "Expected ';' or '{' (require_semicolons is enabled)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Read};
+
+    const INPUT: &str = "# header\nserver \"ex ample\" {\n  listen 80; # port\n  name \"\"\"\n  caf\u{e9} \u{1f600}\n  \"\"\";\n  nested { a \\\n    b; }\n}\nlast;";
+
+    fn events<R: BufRead>(reader: Reader<R>) -> Vec<Event> {
+        reader.map(|event| event.unwrap()).collect()
+    }
+
+    #[test]
+    fn test_events_from_str() {
+        let events = events(Reader::from_str(INPUT, ConfOptions::default()));
+        let summary: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                Event::StartDirective(name) => format!("start {}", name.value),
+                Event::Argument(arg) => format!("arg {}", arg.value),
+                Event::StartBlock(_) => "{".to_string(),
+                Event::EndBlock(_) => "}".to_string(),
+                Event::Comment(comment) => comment.content.clone(),
+                Event::Eof => "eof".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                "# header",
+                "start server",
+                "arg \"ex ample\"",
+                "{",
+                "start listen",
+                "arg 80",
+                "# port",
+                "start name",
+                "arg \"\"\"\n  caf\u{e9} \u{1f600}\n  \"\"\"",
+                "start nested",
+                "{",
+                "start a",
+                "arg b",
+                "}",
+                "}",
+                "start last",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_small_buffers_match_str_reader() {
+        let expected = events(Reader::from_str(INPUT, ConfOptions::default()));
+        for capacity in [1, 2, 3, 7] {
+            let source = BufReader::with_capacity(capacity, INPUT.as_bytes());
+            let actual = events(Reader::new(source, ConfOptions::default()));
+            assert_eq!(actual, expected, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn test_raw_strings_and_heredocs_across_buffers() {
+        let input = "a r#\"x\"y\"#;\nb <<EOF\n  one\n  EOFX\n  EOF;\nc r;\n";
+        let options = ConfOptions::all_extensions();
+        let expected = events(Reader::from_str(input, options.clone()));
+        for capacity in [1, 2, 3, 7] {
+            let source = BufReader::with_capacity(capacity, input.as_bytes());
+            let actual = events(Reader::new(source, options.clone()));
+            assert_eq!(actual, expected, "capacity {}", capacity);
+        }
+        assert!(expected.iter().any(|event| matches!(
+            event,
+            Event::Argument(argument) if argument.is_heredoc && argument.text() == "one\nEOFX"
+        )));
+    }
+
+    #[test]
+    fn test_custom_comments_across_buffers() {
+        let input = "--[[ a\n--[[ b ]] ]]\nx 1\n-- c\n--[[ d ]]\n";
+        let options = ConfOptions {
+            comment_syntax: crate::CommentSyntax {
+                nestable_blocks: true,
+                ..crate::CommentSyntax::lua()
+            },
+            ..ConfOptions::default()
+        };
+        let expected = events(Reader::from_str(input, options.clone()));
+        for capacity in [1, 2, 3, 7] {
+            let source = BufReader::with_capacity(capacity, input.as_bytes());
+            let actual = events(Reader::new(source, options.clone()));
+            assert_eq!(actual, expected, "capacity {}", capacity);
+        }
+        let comments: Vec<bool> = expected
+            .iter()
+            .filter_map(|event| match event {
+                Event::Comment(comment) => Some(comment.is_multi_line),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(comments, vec![true, false, true]);
+    }
+
+    #[test]
+    fn test_pragma() {
+        let input = "# confetti: c-comments, require-semicolons\n// note\na 1;\nb 2\n";
+        let source = BufReader::with_capacity(3, input.as_bytes());
+        let options = ConfOptions {
+            pragma_policy: crate::PragmaPolicy::Allow,
+            ..ConfOptions::default()
+        };
+        let result: Result<Vec<Event>, ConfError> = Reader::new(source, options).collect();
+        match result {
+            Err(ConfError::ParserError { span, .. }) => assert_eq!(span.start(), input.len() - 1),
+            other => panic!("Expected a missing semicolon, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_spans_are_absolute() {
+        let input = "a 1;\nb 2;\n";
+        let source = BufReader::with_capacity(2, input.as_bytes());
+        for event in Reader::new(source, ConfOptions::default()) {
+            if let Event::Argument(arg) | Event::StartDirective(arg) = event.unwrap() {
+                assert_eq!(&input[arg.span.range()], arg.value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spans_name_the_file() {
+        let file = FileId::new(2);
+        let input = "# c
+a { b; }
+";
+        let events = events(Reader::from_str(input, ConfOptions::default()).in_file(file));
+        for event in &events {
+            let span = match event {
+                Event::StartDirective(arg) | Event::Argument(arg) => arg.span,
+                Event::Comment(comment) => comment.span,
+                Event::StartBlock(span) | Event::EndBlock(span) => *span,
+                Event::Eof => continue,
+            };
+            assert_eq!(span.file(), file);
+        }
+        assert_eq!(events[2], Event::StartBlock(Span::new(file, 6..7)));
+        assert_eq!(events[4], Event::EndBlock(Span::new(file, 11..12)));
+
+        let error = Reader::from_str("a {", ConfOptions::default())
+            .in_file(file)
+            .find_map(|event| event.err())
+            .unwrap();
+        assert_eq!(error.span().unwrap().file(), file);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let cases = [
+            ("a {", "Expected '}'"),
+            ("}", "Expected directive name"),
+            (";", "Expected directive name"),
+            ("a b }", "Expected ';', '{', or newline"),
+        ];
+        for (input, message) in cases {
+            let error = Reader::from_str(input, ConfOptions::default())
+                .find_map(|event| event.err())
+                .unwrap_or_else(|| panic!("no error for {:?}", input));
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_require_semicolons() {
+        let options = ConfOptions::nginx_like();
+        let result: Result<Vec<Event>, ConfError> =
+            Reader::from_str("a 1;\nb { c; }\n", options.clone()).collect();
+        assert!(result.is_ok());
+        for input in ["a 1\nb;", "a 1"] {
+            let error = Reader::from_str(input, options.clone())
+                .find_map(|event| event.err())
+                .unwrap_or_else(|| panic!("no error for {:?}", input));
+            assert!(
+                error.to_string().contains("require_semicolons"),
+                "{}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let cases = [
+            (
+                ConfOptions {
+                    max_input_bytes: 5,
+                    ..ConfOptions::default()
+                },
+                Limit::InputBytes,
+            ),
+            (
+                ConfOptions {
+                    max_directives: 2,
+                    ..ConfOptions::default()
+                },
+                Limit::Directives,
+            ),
+            (
+                ConfOptions {
+                    max_arguments: 1,
+                    ..ConfOptions::default()
+                },
+                Limit::Arguments,
+            ),
+            (
+                ConfOptions {
+                    max_tokens: 4,
+                    ..ConfOptions::default()
+                },
+                Limit::Tokens,
+            ),
+        ];
+        for (options, expected) in cases {
+            let source = BufReader::with_capacity(2, "a 1 2;\nb;\nc;\n".as_bytes());
+            let error = Reader::new(source, options)
+                .find_map(|event| event.err())
+                .unwrap_or_else(|| panic!("no error for {:?}", expected));
+            assert!(
+                matches!(error, ConfError::LimitExceeded { limit, .. } if limit == expected),
+                "{:?}",
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let options = ConfOptions {
+            max_depth: 2,
+            ..ConfOptions::default()
+        };
+        let result: Result<Vec<Event>, ConfError> =
+            Reader::from_str("a { b { c; } }", options).collect();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let bytes: &[u8] = b"a;\r\nabc \xff;";
+        let error = Reader::new(bytes, ConfOptions::default())
+            .find_map(|event| event.err())
+            .unwrap();
+        match error {
+            ConfError::EncodingError {
+                span,
+                line,
+                message,
+            } => {
+                assert_eq!((span.start(), line), (8, 2));
+                assert_eq!(message, "Invalid UTF-8");
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        // Lines are counted across discarded text
+        let mut long = "x;\r\n".repeat(COMPACT_THRESHOLD).into_bytes();
+        long.push(0xff);
+        let source = BufReader::with_capacity(1000, long.as_slice());
+        match Reader::new(source, ConfOptions::default()).find_map(|event| event.err()) {
+            Some(ConfError::EncodingError { span, line, .. }) => {
+                assert_eq!(span.start(), long.len() - 1);
+                assert_eq!(line, COMPACT_THRESHOLD + 1);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        let truncated: &[u8] = b"abc \xc3";
+        assert!(Reader::new(truncated, ConfOptions::default()).any(|event| event.is_err()));
+    }
+
+    /// Generates `count` copies of a directive without holding them in memory.
+    struct Repeat {
+        count: usize,
+        line: &'static [u8],
+        offset: usize,
+    }
+
+    impl Read for Repeat {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut written = 0;
+            while written < buf.len() && self.count > 0 {
+                let n = (buf.len() - written).min(self.line.len() - self.offset);
+                buf[written..written + n].copy_from_slice(&self.line[self.offset..self.offset + n]);
+                written += n;
+                self.offset += n;
+                if self.offset == self.line.len() {
+                    self.offset = 0;
+                    self.count -= 1;
+                }
+            }
+            Ok(written)
+        }
+    }
+
+    #[test]
+    fn test_bounded_memory() {
+        let source = Repeat {
+            count: 20_000,
+            line: b"route \"/api/v1/items\" backend-01 { weight 10; }\n",
+            offset: 0,
+        };
+        let mut reader = Reader::new(BufReader::new(source), ConfOptions::default());
+        let mut directives = 0;
+        let mut max_window = 0;
+        while let Some(event) = reader.next() {
+            if let Event::StartDirective(_) = event.unwrap() {
+                directives += 1;
+            }
+            max_window = max_window.max(reader.window.capacity());
+        }
+        assert_eq!(directives, 40_000);
+        assert!(max_window < 64 * 1024, "window grew to {}", max_window);
+    }
+
+    #[test]
+    fn test_errors_before_the_end_of_the_window_stop_reading() {
+        // The stream is far longer than anything the reader should buffer
+        let source = b"a \x01".chain(std::io::repeat(b'a').take(64 << 20));
+        let mut reader = Reader::new(BufReader::new(source), ConfOptions::default());
+        let error = reader.find_map(|event| event.err()).unwrap();
+        assert!(error.to_string().contains("U+0001"), "{}", error);
+        let unread = reader.into_inner().into_inner().get_ref().1.limit();
+        assert!(unread > 60 << 20, "read {} bytes", (64 << 20) - unread);
+
+        // Errors found at the end of the window wait for more input
+        let input = "a \"x\\\"y\";\nb /*\n*/ c;\n";
+        let expected = events(Reader::from_str(input, ConfOptions::default()));
+        for capacity in [1, 2, 3] {
+            let source = BufReader::with_capacity(capacity, input.as_bytes());
+            assert_eq!(
+                events(Reader::new(source, ConfOptions::default())),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_tokens_are_read_in_linear_time() {
+        // Lexing the whole token again for every 16-byte chunk would take
+        // minutes
+        let mut input = "a ".to_string();
+        input.push_str(&"x".repeat(4 << 20));
+        input.push_str(";\n");
+        let source = BufReader::with_capacity(16, input.as_bytes());
+        let events = events(Reader::new(source, ConfOptions::default()));
+        assert!(matches!(&events[1], Event::Argument(argument) if argument.value.len() == 4 << 20));
+    }
+
+    #[test]
+    fn test_length_limits_bound_buffering() {
+        let cases = [
+            ("a ", 2, Limit::ArgumentLength),
+            ("a \"", 2, Limit::ArgumentLength),
+            ("# ", 0, Limit::CommentLength),
+        ];
+        for (prefix, start, expected) in cases {
+            let options = ConfOptions {
+                max_argument_length: 1000,
+                max_comment_length: 1000,
+                ..ConfOptions::default()
+            };
+            let source = prefix
+                .as_bytes()
+                .chain(std::io::repeat(b'x').take(64 << 20));
+            let mut reader = Reader::new(BufReader::new(source), options);
+            match reader.find_map(|event| event.err()) {
+                Some(ConfError::LimitExceeded { span, limit, .. }) => {
+                    assert_eq!(limit, expected);
+                    assert_eq!(span.start(), start);
+                }
+                other => panic!("unexpected result {:?}", other),
+            }
+            let unread = reader.into_inner().into_inner().get_ref().1.limit();
+            assert!(unread > 60 << 20, "read {} bytes", (64 << 20) - unread);
+        }
+    }
+
+    #[test]
+    fn test_offsets_past_spans_are_rejected() {
+        let mut reader = Reader::from_str("a 1;\nb 2;\n", ConfOptions::default());
+        // As if the reader had already gone through almost 4 GiB
+        reader.window_offset = MAX_OFFSET - 6;
+        match reader.find_map(|event| event.err()) {
+            Some(ConfError::LimitExceeded { limit, max, .. }) => {
+                assert_eq!((limit, max), (Limit::InputBytes, MAX_OFFSET));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Sources registered by file, and the spans that point into them.
+//!
+//! Every [`Span`] names the file it belongs to with a [`FileId`], so spans
+//! from several files can be mixed, as when merging units or loading includes,
+//! and still be traced back. A [`SourceMap`] holds the text of each file and
+//! resolves spans to a file name, line and column.
+//!
+//! Spans from [`crate::parse`] and the other entry points that take no file
+//! belong to the default file, which is the first file registered in a map.
+//!
+//! # Examples
+//!
+//! ```
+//! use confetti_rs::source_map::SourceMap;
+//! use confetti_rs::ConfOptions;
+//!
+//! let mut map = SourceMap::new();
+//! let main = map.add("main.conf", "server {\n  listen 80;\n}\n");
+//! let extra = map.add("extra.conf", "user www;\n");
+//!
+//! let unit = map.parse(extra, ConfOptions::default()).unwrap();
+//! let span = unit.directives[0].arguments[0].span;
+//! assert_eq!(span.file(), extra);
+//! assert_eq!(map.text(span), Some("www"));
+//! assert_eq!(map.resolve(span).unwrap().to_string(), "extra.conf:1:6");
+//!
+//! let unit = map.parse(main, ConfOptions::default()).unwrap();
+//! let listen = &unit.directives[0].children[0];
+//! assert_eq!(map.resolve(listen.name.span).unwrap().to_string(), "main.conf:2:3");
+//! ```
+
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::builder::is_line_terminator;
+use crate::parser::Parser;
+use crate::{ConfError, ConfOptions, ConfUnit};
+
+/// The largest offset a [`Span`] can hold. Longer inputs are rejected with
+/// [`crate::Limit::InputBytes`].
+pub const MAX_OFFSET: usize = u32::MAX as usize;
+
+/// Identifies a file registered in a [`SourceMap`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(u32);
+
+impl FileId {
+    /// Returns the file registered at `index`, counting from 0.
+    pub fn new(index: usize) -> Self {
+        FileId(u32::try_from(index).expect("too many files in a source map"))
+    }
+
+    /// Returns the position of the file in its map, counting from 0.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A range of bytes in a file, twelve bytes in size.
+///
+/// Offsets are kept as `u32`. The parser and the streaming reader reject
+/// inputs longer than [`MAX_OFFSET`]; offsets past it given to [`Span::new`]
+/// saturate at it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    file: FileId,
+    start: u32,
+    end: u32,
+}
+
+impl Span {
+    /// Returns the span of `range` in `file`.
+    pub fn new(file: FileId, range: Range<usize>) -> Self {
+        Self {
+            file,
+            start: offset(range.start),
+            end: offset(range.end),
+        }
+    }
+
+    /// Returns the empty span at `position` in the default file.
+    pub fn point(position: usize) -> Self {
+        Self::from(position..position)
+    }
+
+    /// Returns the file the span belongs to.
+    pub fn file(self) -> FileId {
+        self.file
+    }
+
+    /// Returns the byte offset of the start of the span.
+    pub fn start(self) -> usize {
+        self.start as usize
+    }
+
+    /// Returns the byte offset of the end of the span.
+    pub fn end(self) -> usize {
+        self.end as usize
+    }
+
+    /// Returns the range of bytes the span covers, for slicing its file.
+    pub fn range(self) -> Range<usize> {
+        self.start()..self.end()
+    }
+
+    /// Returns the length of the span in bytes.
+    pub fn len(self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    /// Returns whether the span is empty.
+    pub fn is_empty(self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the span from the start of this span to the end of `other`,
+    /// in this span's file.
+    pub fn to(self, other: Span) -> Self {
+        Self {
+            end: other.end,
+            ..self
+        }
+    }
+
+    /// Returns the span in another file.
+    pub fn in_file(self, file: FileId) -> Self {
+        Self { file, ..self }
+    }
+
+    /// Returns the span moved by `delta` bytes.
+    pub(crate) fn shifted(self, delta: isize) -> Self {
+        Self::new(
+            self.file,
+            self.start().saturating_add_signed(delta)..self.end().saturating_add_signed(delta),
+        )
+    }
+}
+
+impl From<Range<usize>> for Span {
+    /// Returns the span of a range in the default file.
+    fn from(range: Range<usize>) -> Self {
+        Self::new(FileId::default(), range)
+    }
+}
+
+fn offset(position: usize) -> u32 {
+    u32::try_from(position).unwrap_or(u32::MAX)
+}
+
+/// A registered file: its name, its text and where its lines start.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    name: String,
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(name: String, source: String) -> Self {
+        let mut line_starts = vec![0];
+        let mut chars = source.char_indices().peekable();
+        while let Some((index, c)) = chars.next() {
+            if !is_line_terminator(c) {
+                continue;
+            }
+            if c == '\r' && chars.next_if(|&(_, next)| next == '\n').is_some() {
+                line_starts.push(index + 2);
+            } else {
+                line_starts.push(index + c.len_utf8());
+            }
+        }
+        Self {
+            name,
+            source,
+            line_starts,
+        }
+    }
+
+    /// Returns the name the file was registered with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the text of the file.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the 1-based line and column of a byte offset, counting the
+    /// column in characters. Offsets past the end resolve to the end.
+    pub fn line_column(&self, position: usize) -> (usize, usize) {
+        let mut position = position.min(self.source.len());
+        while !self.source.is_char_boundary(position) {
+            position -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= position) - 1;
+        let column = self.source[self.line_starts[line]..position]
+            .chars()
+            .count();
+        (line + 1, column + 1)
+    }
+}
+
+/// Where a span starts: a file name, a 1-based line and a 1-based column.
+///
+/// Displays as `name:line:column`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location<'a> {
+    /// The name of the file.
+    pub file: &'a str,
+    /// The line, counting from 1.
+    pub line: usize,
+    /// The column in characters, counting from 1.
+    pub column: usize,
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// The files spans point into.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    /// Creates an empty source map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a source under a name and returns its id.
+    pub fn add(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        let id = FileId::new(self.files.len());
+        self.files.push(SourceFile::new(name.into(), source.into()));
+        id
+    }
+
+    /// Reads a file, decoding it like [`crate::parse_bytes`], and registers
+    /// it under its path.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<FileId, ConfError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path)?;
+        let source = crate::encoding::decode(&bytes)?;
+        Ok(self.add(path.display().to_string(), source))
+    }
+
+    /// Returns a registered file.
+    pub fn file(&self, id: FileId) -> Option<&SourceFile> {
+        self.files.get(id.index())
+    }
+
+    /// Returns the registered files with their ids, in the order they were
+    /// added.
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| (FileId::new(index), file))
+    }
+
+    /// Returns the number of registered files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns whether no file is registered.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Parses a registered file, so that the spans of the unit and of any
+    /// error belong to it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` was not registered in this map.
+    pub fn parse(&self, id: FileId, options: ConfOptions) -> Result<ConfUnit, ConfError> {
+        let file = self.file(id).expect("the file is not in this source map");
+        Parser::new(&file.source, options)
+            .map_err(|error| error.in_file(id))?
+            .in_file(id)
+            .parse()
+    }
+
+    /// Returns the text a span covers, if its file is registered and the span
+    /// lies within it.
+    pub fn text(&self, span: Span) -> Option<&str> {
+        self.file(span.file())?.source.get(span.range())
+    }
+
+    /// Returns the file name, line and column where a span starts, if its
+    /// file is registered.
+    pub fn resolve(&self, span: Span) -> Option<Location<'_>> {
+        let file = self.file(span.file())?;
+        let (line, column) = file.line_column(span.start());
+        Some(Location {
+            file: &file.name,
+            line,
+            column,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_column() {
+        let file = SourceFile::new("a".into(), "ab\r\ncé\rx\u{2028}y\n".into());
+        assert_eq!(file.line_column(0), (1, 1));
+        assert_eq!(file.line_column(2), (1, 3));
+        assert_eq!(file.line_column(4), (2, 1));
+        // The column counts characters, not bytes
+        assert_eq!(file.line_column(7), (2, 3));
+        assert_eq!(file.line_column(8), (3, 1));
+        assert_eq!(file.line_column(12), (4, 1));
+        assert_eq!(file.line_column(100), (5, 1));
+    }
+
+    #[test]
+    fn test_spans_of_several_files() {
+        let mut map = SourceMap::new();
+        let first = map.add("first.conf", "a 1;\n");
+        let second = map.add("second.conf", "\n\n  b 2;\n");
+
+        let unit = map.parse(second, ConfOptions::default()).unwrap();
+        let name = unit.directives[0].name.span;
+        assert_eq!(name.file(), second);
+        assert_eq!(map.text(name), Some("b"));
+        assert_eq!(
+            map.resolve(name),
+            Some(Location {
+                file: "second.conf",
+                line: 3,
+                column: 3,
+            })
+        );
+
+        // The default file is the first one registered
+        let unit = crate::parse("a 1;\n", ConfOptions::default()).unwrap();
+        assert_eq!(unit.directives[0].name.span.file(), first);
+
+        let broken = map.add("broken.conf", "x {\n  y;\n");
+        let error = map.parse(broken, ConfOptions::default()).unwrap_err();
+        let span = error.span().unwrap();
+        assert_eq!(span.file(), broken);
+        assert_eq!(map.resolve(span).unwrap().to_string(), "broken.conf:3:1");
+    }
+
+    #[test]
+    fn test_span() {
+        let span = Span::new(FileId::new(2), 3..7);
+        assert_eq!(std::mem::size_of::<Span>(), 12);
+        assert_eq!(span.range(), 3..7);
+        assert_eq!(span.len(), 4);
+        assert_eq!(span.to(Span::from(9..12)), Span::new(FileId::new(2), 3..12));
+        assert_eq!(span.shifted(-3).range(), 0..4);
+        assert_eq!(Span::from(0..usize::MAX).end(), MAX_OFFSET);
+    }
+}
@@ -0,0 +1,574 @@
+//! Traversal of the directive tree with visitors and folds.
+//!
+//! A [`Visitor`] is called for every directive, argument and comment of a
+//! unit in source order, and a [`VisitorMut`] may change them in place. Each
+//! directive comes with a [`Context`] holding its depth and the names of the
+//! directives enclosing it, and each callback returns a [`Flow`] that can skip
+//! the children of a directive or stop the walk. Both traits share a single
+//! walking implementation.
+//!
+//! A [`Fold`] consumes a unit and builds a transformed one, and may remove
+//! directives and comments.
+//!
+//! # Examples
+//!
+//! ```
+//! use confetti_rs::visit::{walk, Context, Flow, Visitor};
+//! use confetti_rs::{parse, ConfDirective, ConfOptions};
+//!
+//! struct Outline(Vec<String>);
+//!
+//! impl Visitor for Outline {
+//!     fn visit_directive(&mut self, directive: &ConfDirective, context: &Context<'_>) -> Flow {
+//!         let indent = "  ".repeat(context.depth());
+//!         self.0.push(format!("{}{}", indent, directive.name.value));
+//!         Flow::Continue
+//!     }
+//! }
+//!
+//! let unit = parse("server {\n  listen 80;\n}\nuser www;\n", ConfOptions::default()).unwrap();
+//! let mut outline = Outline(Vec::new());
+//! walk(&mut outline, &unit);
+//! assert_eq!(outline.0, ["server", "  listen", "user"]);
+//! ```
+
+use std::mem;
+
+use crate::{ConfArgument, ConfComment, ConfDirective, ConfUnit};
+
+/// What a walk does after a visitor callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Continue with the next node.
+    Continue,
+    /// Continue, but without visiting the arguments and children of the
+    /// directive just visited. Other callbacks treat it like `Continue`.
+    SkipChildren,
+    /// Stop the walk.
+    Break,
+}
+
+impl Flow {
+    /// Returns whether the walk stops.
+    pub fn is_break(self) -> bool {
+        self == Flow::Break
+    }
+}
+
+/// The position of a directive in the tree.
+#[derive(Debug, Clone, Copy)]
+pub struct Context<'a> {
+    parent: Option<&'a Ancestor<'a>>,
+    depth: usize,
+    index: usize,
+}
+
+/// An enclosing directive, linked to its own parent.
+#[derive(Debug)]
+struct Ancestor<'a> {
+    name: &'a str,
+    parent: Option<&'a Ancestor<'a>>,
+}
+
+impl<'a> Context<'a> {
+    /// Returns the number of directives enclosing the directive, 0 at the top level.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the index of the directive among its siblings.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the name of the directive whose block holds the directive.
+    pub fn parent(&self) -> Option<&'a str> {
+        self.parent.map(|parent| parent.name)
+    }
+
+    /// Returns the names of the enclosing directives, innermost first.
+    pub fn ancestors(&self) -> impl Iterator<Item = &'a str> {
+        let mut next = self.parent;
+        std::iter::from_fn(move || {
+            let ancestor = next?;
+            next = ancestor.parent;
+            Some(ancestor.name)
+        })
+    }
+
+    /// Returns the names of the enclosing directives, outermost first.
+    pub fn path(&self) -> Vec<&'a str> {
+        let mut path: Vec<&str> = self.ancestors().collect();
+        path.reverse();
+        path
+    }
+}
+
+/// Callbacks for walking a directive tree with [`walk`] or [`walk_directives`].
+///
+/// Every callback does nothing and continues by default.
+pub trait Visitor {
+    /// Called for a directive before its arguments and children.
+    fn visit_directive(&mut self, _directive: &ConfDirective, _context: &Context<'_>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for each argument of a directive, excluding its name, with the
+    /// context of the directive.
+    fn visit_argument(&mut self, _argument: &ConfArgument, _context: &Context<'_>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for a directive after its children, unless they were skipped.
+    fn leave_directive(&mut self, _directive: &ConfDirective, _context: &Context<'_>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for each comment of a unit, in source order with its directives.
+    fn visit_comment(&mut self, _comment: &ConfComment) -> Flow {
+        Flow::Continue
+    }
+}
+
+/// Callbacks for walking and changing a directive tree with [`walk_mut`] or
+/// [`walk_directives_mut`].
+///
+/// Changes made to a directive in [`visit_directive`](Self::visit_directive)
+/// are seen by the rest of the walk: its new name is in the context of its
+/// children, and children it adds are visited.
+pub trait VisitorMut {
+    /// Called for a directive before its arguments and children.
+    fn visit_directive(&mut self, _directive: &mut ConfDirective, _context: &Context<'_>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for each argument of a directive, excluding its name, with the
+    /// context of the directive.
+    fn visit_argument(&mut self, _argument: &mut ConfArgument, _context: &Context<'_>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for a directive after its children, unless they were skipped.
+    fn leave_directive(&mut self, _directive: &mut ConfDirective, _context: &Context<'_>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called for each comment of a unit, in source order with its directives.
+    fn visit_comment(&mut self, _comment: &mut ConfComment) -> Flow {
+        Flow::Continue
+    }
+}
+
+// Defines the walk functions for one visitor trait. The `&` and `&mut` walks
+// differ only in the references they take and the iterators they use.
+macro_rules! walker {
+    (
+        $visitor:ident, $iter:ident,
+        $walk:ident, $walk_directives:ident, $walk_children:ident,
+        $($mutability:tt)?
+    ) => {
+        /// Walks the directives and comments of a unit in source order.
+        ///
+        /// Returns [`Flow::Break`] if a callback stopped the walk, and
+        /// [`Flow::Continue`] otherwise.
+        pub fn $walk<V: $visitor + ?Sized>(
+            visitor: &mut V,
+            unit: &$($mutability)? ConfUnit,
+        ) -> Flow {
+            let mut comments = unit.comments.$iter().peekable();
+            for (index, directive) in unit.directives.$iter().enumerate() {
+                while let Some(comment) =
+                    comments.next_if(|comment| comment.span.start() < directive.name.span.start())
+                {
+                    if visitor.visit_comment(comment).is_break() {
+                        return Flow::Break;
+                    }
+                }
+                let context = Context {
+                    parent: None,
+                    depth: 0,
+                    index,
+                };
+                if $walk_children(visitor, directive, &context).is_break() {
+                    return Flow::Break;
+                }
+            }
+            for comment in comments {
+                if visitor.visit_comment(comment).is_break() {
+                    return Flow::Break;
+                }
+            }
+            Flow::Continue
+        }
+
+        /// Walks a list of sibling directives at the top level.
+        ///
+        /// Returns [`Flow::Break`] if a callback stopped the walk, and
+        /// [`Flow::Continue`] otherwise.
+        pub fn $walk_directives<V: $visitor + ?Sized>(
+            visitor: &mut V,
+            directives: &$($mutability)? [ConfDirective],
+        ) -> Flow {
+            for (index, directive) in directives.$iter().enumerate() {
+                let context = Context {
+                    parent: None,
+                    depth: 0,
+                    index,
+                };
+                if $walk_children(visitor, directive, &context).is_break() {
+                    return Flow::Break;
+                }
+            }
+            Flow::Continue
+        }
+
+        fn $walk_children<V: $visitor + ?Sized>(
+            visitor: &mut V,
+            directive: &$($mutability)? ConfDirective,
+            context: &Context<'_>,
+        ) -> Flow {
+            match visitor.visit_directive(directive, context) {
+                Flow::Continue => {}
+                Flow::SkipChildren => return Flow::Continue,
+                Flow::Break => return Flow::Break,
+            }
+            for argument in directive.arguments.$iter() {
+                if visitor.visit_argument(argument, context).is_break() {
+                    return Flow::Break;
+                }
+            }
+
+            // Borrow the name and the children separately, so the name can be
+            // an ancestor while the children are walked
+            let ancestor = Ancestor {
+                name: &directive.name.value,
+                parent: context.parent,
+            };
+            for (index, child) in directive.children.$iter().enumerate() {
+                let child_context = Context {
+                    parent: Some(&ancestor),
+                    depth: context.depth + 1,
+                    index,
+                };
+                if $walk_children(visitor, child, &child_context).is_break() {
+                    return Flow::Break;
+                }
+            }
+            match visitor.leave_directive(directive, context) {
+                Flow::Break => Flow::Break,
+                _ => Flow::Continue,
+            }
+        }
+    };
+}
+
+walker!(Visitor, iter, walk, walk_directives, walk_directive,);
+walker!(
+    VisitorMut,
+    iter_mut,
+    walk_mut,
+    walk_directives_mut,
+    walk_directive_mut,
+    mut
+);
+
+/// Transformations producing a new directive tree with [`Fold::fold_unit`].
+///
+/// Every method rebuilds its node with the matching free function by default,
+/// folding the nodes it contains. An override can call that function to keep
+/// the default traversal around its own changes.
+pub trait Fold {
+    /// Folds a unit.
+    fn fold_unit(&mut self, unit: ConfUnit) -> ConfUnit {
+        fold_unit(self, unit)
+    }
+
+    /// Folds a directive, or removes it by returning `None`.
+    fn fold_directive(
+        &mut self,
+        directive: ConfDirective,
+        context: &Context<'_>,
+    ) -> Option<ConfDirective> {
+        Some(fold_directive(self, directive, context))
+    }
+
+    /// Folds the name of a directive, with the context of the directive.
+    fn fold_name(&mut self, name: ConfArgument, _context: &Context<'_>) -> ConfArgument {
+        name
+    }
+
+    /// Folds an argument of a directive, with the context of the directive.
+    fn fold_argument(&mut self, argument: ConfArgument, _context: &Context<'_>) -> ConfArgument {
+        argument
+    }
+
+    /// Folds a comment, or removes it by returning `None`.
+    fn fold_comment(&mut self, comment: ConfComment) -> Option<ConfComment> {
+        Some(comment)
+    }
+}
+
+/// Folds the comments and top-level directives of a unit.
+pub fn fold_unit<F: Fold + ?Sized>(folder: &mut F, unit: ConfUnit) -> ConfUnit {
+    let comments = unit
+        .comments
+        .into_iter()
+        .filter_map(|comment| folder.fold_comment(comment))
+        .collect();
+    let directives = fold_list(folder, unit.directives, None, 0);
+    ConfUnit {
+        directives,
+        comments,
+    }
+}
+
+/// Folds the name, arguments and children of a directive.
+///
+/// The context of the children names the directive by its folded name.
+pub fn fold_directive<F: Fold + ?Sized>(
+    folder: &mut F,
+    mut directive: ConfDirective,
+    context: &Context<'_>,
+) -> ConfDirective {
+    let name = mem::take(&mut directive.name);
+    directive.name = folder.fold_name(name, context);
+    directive.arguments = mem::take(&mut directive.arguments)
+        .into_iter()
+        .map(|argument| folder.fold_argument(argument, context))
+        .collect();
+
+    let ancestor = Ancestor {
+        name: &directive.name.value,
+        parent: context.parent,
+    };
+    let children = mem::take(&mut directive.children);
+    directive.children = fold_list(folder, children, Some(&ancestor), context.depth + 1);
+    directive
+}
+
+fn fold_list<F: Fold + ?Sized>(
+    folder: &mut F,
+    directives: Vec<ConfDirective>,
+    parent: Option<&Ancestor<'_>>,
+    depth: usize,
+) -> Vec<ConfDirective> {
+    directives
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, directive)| {
+            let context = Context {
+                parent,
+                depth,
+                index,
+            };
+            folder.fold_directive(directive, &context)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, ConfOptions};
+
+    const INPUT: &str =
+        "# top\nhttp {\n  server {\n    listen 80;\n  }\n  # ignored\n}\nuser www data;\n# end\n";
+
+    fn unit() -> ConfUnit {
+        parse(INPUT, ConfOptions::default()).unwrap()
+    }
+
+    /// Records every callback, and stops or skips at chosen directives.
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        skip: Option<&'static str>,
+        stop: Option<&'static str>,
+    }
+
+    impl Visitor for Recorder {
+        fn visit_directive(&mut self, directive: &ConfDirective, context: &Context<'_>) -> Flow {
+            let name = directive.name.value.as_str();
+            self.events.push(format!(
+                "enter {} depth={} index={} path={}",
+                name,
+                context.depth(),
+                context.index(),
+                context.path().join("/")
+            ));
+            if self.stop == Some(name) {
+                Flow::Break
+            } else if self.skip == Some(name) {
+                Flow::SkipChildren
+            } else {
+                Flow::Continue
+            }
+        }
+
+        fn visit_argument(&mut self, argument: &ConfArgument, _context: &Context<'_>) -> Flow {
+            self.events.push(format!("argument {}", argument.value));
+            Flow::Continue
+        }
+
+        fn leave_directive(&mut self, directive: &ConfDirective, _context: &Context<'_>) -> Flow {
+            self.events.push(format!("leave {}", directive.name.value));
+            Flow::Continue
+        }
+
+        fn visit_comment(&mut self, comment: &ConfComment) -> Flow {
+            self.events.push(format!("comment {}", comment.content));
+            Flow::Continue
+        }
+    }
+
+    #[test]
+    fn test_walk_order_and_context() {
+        let mut recorder = Recorder::default();
+        assert_eq!(walk(&mut recorder, &unit()), Flow::Continue);
+        assert_eq!(
+            recorder.events,
+            [
+                "comment # top",
+                "enter http depth=0 index=0 path=",
+                "enter server depth=1 index=0 path=http",
+                "enter listen depth=2 index=0 path=http/server",
+                "argument 80",
+                "leave listen",
+                "leave server",
+                "leave http",
+                "enter user depth=0 index=1 path=",
+                "argument www",
+                "argument data",
+                "leave user",
+                "comment # end",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_children_and_break() {
+        let mut recorder = Recorder {
+            skip: Some("http"),
+            ..Recorder::default()
+        };
+        walk(&mut recorder, &unit());
+        assert_eq!(
+            recorder.events[1..4],
+            [
+                "enter http depth=0 index=0 path=",
+                "enter user depth=0 index=1 path=",
+                "argument www"
+            ]
+        );
+
+        let mut recorder = Recorder {
+            stop: Some("server"),
+            ..Recorder::default()
+        };
+        assert_eq!(
+            walk_directives(&mut recorder, &unit().directives),
+            Flow::Break
+        );
+        assert_eq!(
+            recorder.events.last().unwrap(),
+            "enter server depth=1 index=0 path=http"
+        );
+    }
+
+    #[test]
+    fn test_context_ancestors() {
+        struct Ancestors(Vec<(Option<String>, Vec<String>)>);
+
+        impl Visitor for Ancestors {
+            fn visit_directive(&mut self, _: &ConfDirective, context: &Context<'_>) -> Flow {
+                let parent = context.parent().map(str::to_string);
+                let ancestors = context.ancestors().map(str::to_string).collect();
+                self.0.push((parent, ancestors));
+                Flow::Continue
+            }
+        }
+
+        let mut visitor = Ancestors(Vec::new());
+        walk(&mut visitor, &unit());
+        assert_eq!(visitor.0[2].0.as_deref(), Some("server"));
+        assert_eq!(visitor.0[2].1, ["server", "http"]);
+        assert_eq!(visitor.0[3], (None, Vec::new()));
+    }
+
+    #[test]
+    fn test_walk_mut() {
+        struct Upper;
+
+        impl VisitorMut for Upper {
+            fn visit_directive(&mut self, directive: &mut ConfDirective, _: &Context<'_>) -> Flow {
+                directive.name.value = directive.name.value.to_uppercase();
+                Flow::Continue
+            }
+
+            fn visit_argument(
+                &mut self,
+                argument: &mut ConfArgument,
+                context: &Context<'_>,
+            ) -> Flow {
+                // The context already holds the renamed ancestors
+                argument.value = format!("{}:{}", context.path().join("/"), argument.value);
+                Flow::Continue
+            }
+        }
+
+        let mut unit = unit();
+        walk_mut(&mut Upper, &mut unit);
+        let listen = &unit.directives[0].children[0].children[0];
+        assert_eq!(listen.name.value, "LISTEN");
+        assert_eq!(listen.arguments[0].value, "HTTP/SERVER:80");
+        assert_eq!(unit.directives[1].name.value, "USER");
+    }
+
+    #[test]
+    fn test_fold() {
+        /// Removes `listen` directives and comments, and prefixes the
+        /// arguments of nested directives with their parent's name.
+        struct Strip;
+
+        impl Fold for Strip {
+            fn fold_directive(
+                &mut self,
+                directive: ConfDirective,
+                context: &Context<'_>,
+            ) -> Option<ConfDirective> {
+                if directive.name.value == "listen" {
+                    return None;
+                }
+                Some(fold_directive(self, directive, context))
+            }
+
+            fn fold_argument(
+                &mut self,
+                mut argument: ConfArgument,
+                context: &Context<'_>,
+            ) -> ConfArgument {
+                if let Some(parent) = context.parent() {
+                    argument.value = format!("{}.{}", parent, argument.value);
+                }
+                argument
+            }
+
+            fn fold_comment(&mut self, _comment: ConfComment) -> Option<ConfComment> {
+                None
+            }
+        }
+
+        let folded = Strip.fold_unit(
+            parse(
+                INPUT.replace("server", "server a").as_str(),
+                ConfOptions::default(),
+            )
+            .unwrap(),
+        );
+        assert!(folded.comments.is_empty());
+        let server = &folded.directives[0].children[0];
+        assert_eq!(server.arguments[0].value, "http.a");
+        assert!(server.children.is_empty());
+        assert_eq!(folded.directives[1].arguments[0].value, "www");
+    }
+}